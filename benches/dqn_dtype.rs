@@ -0,0 +1,41 @@
+use Haba::batch::Batch;
+use Haba::device::{DeviceConfig, Precision};
+use Haba::dqn::DQNPolicy;
+use Haba::policy::Policy;
+use criterion::{Criterion, criterion_group, criterion_main};
+
+const BATCH: usize = 64;
+
+fn policy(precision: Precision) -> DQNPolicy {
+    DQNPolicy::with_device(4, 128, 2, 0.99, 0.1, DeviceConfig::Cpu, precision).unwrap()
+}
+
+fn batch() -> Batch<Vec<f64>, usize> {
+    let obs: Vec<Vec<f64>> = (0..BATCH)
+        .map(|i| (0..4).map(|j| ((i * 4 + j) as f64).sin()).collect())
+        .collect();
+    Batch::new(
+        obs.clone(),
+        (0..BATCH).map(|i| i % 2).collect(),
+        vec![1.0; BATCH],
+        (0..BATCH).map(|i| i % 10 == 0).collect(),
+        obs,
+    )
+}
+
+fn bench_dtypes(c: &mut Criterion) {
+    let batch = batch();
+    for (name, precision) in [("f32", Precision::F32), ("f64", Precision::F64)] {
+        let mut p = policy(precision);
+        c.bench_function(&format!("dqn_forward_{}", name), |b| {
+            b.iter(|| p.forward(&batch.obs, None).unwrap())
+        });
+        let mut p = policy(precision);
+        c.bench_function(&format!("dqn_learn_{}", name), |b| {
+            b.iter(|| p.learn(&batch).unwrap())
+        });
+    }
+}
+
+criterion_group!(benches, bench_dtypes);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+use Haba::cartpole::{BatchedCartPole, CartPole};
+use Haba::env::Environment;
+use Haba::venv::{DummyVectorEnv, VectorEnv};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+
+const SIZES: [usize; 3] = [1, 64, 4096];
+
+fn actions(n: usize) -> Vec<usize> {
+    (0..n).map(|i| i % 2).collect()
+}
+
+fn bench_cartpole(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cartpole_step");
+    for n in SIZES {
+        let actions = actions(n);
+        group.throughput(Throughput::Elements(n as u64));
+
+        let mut dummy = DummyVectorEnv::new(
+            (0..n)
+                .map(|i| {
+                    let mut env = CartPole::new();
+                    env.seed(i as u64);
+                    env
+                })
+                .collect(),
+        );
+        dummy.reset().unwrap();
+        group.bench_with_input(BenchmarkId::new("dummy", n), &actions, |b, actions| {
+            b.iter(|| dummy.step(actions).unwrap())
+        });
+
+        let mut batched = BatchedCartPole::new(n);
+        batched.seed(0);
+        batched.reset().unwrap();
+        group.bench_with_input(BenchmarkId::new("batched", n), &actions, |b, actions| {
+            b.iter(|| batched.step(actions).unwrap())
+        });
+        group.bench_with_input(
+            BenchmarkId::new("batched_in_place", n),
+            &actions,
+            |b, actions| b.iter(|| batched.step_batch(actions).unwrap().len()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_cartpole);
+criterion_main!(benches);
@@ -0,0 +1,67 @@
+#![allow(non_snake_case)]
+
+//! CartPole with velocities hidden: a feed-forward DQN only sees the current
+//! position and angle, while a recurrent DRQN can infer the velocities from
+//! its history.
+
+use Haba::buffer::ReplayBuffer;
+use Haba::cartpole::MaskedCartPole;
+use Haba::collector::Collector;
+use Haba::dqn::DQNPolicy;
+use Haba::drqn::DRQNPolicy;
+use Haba::error::HabaError;
+use Haba::model::RecurrentKind;
+use Haba::policy::Policy;
+use Haba::schedule::{Clock, Schedule, Scheduled};
+use Haba::trainer::Trainer;
+use Haba::venv::DummyVectorEnv;
+use Haba::wrappers::TimeLimit;
+
+const EPOCHS: usize = 30;
+
+fn epsilon() -> Scheduled {
+    Scheduled::new(
+        Schedule::Linear {
+            start: 1.0,
+            end: 0.05,
+            duration: 10_000,
+        },
+        Clock::EnvStep,
+    )
+}
+
+// Mean test return over the last five epochs
+fn train<P>(policy: P) -> Result<f64, HabaError>
+where
+    P: Policy<Observation = Vec<f64>, Action = usize>,
+{
+    let venv = DummyVectorEnv::new(vec![TimeLimit::new(MaskedCartPole::new(), 500)]);
+    let collector = Collector::new(venv, policy, Some(ReplayBuffer::new(10_000)))?;
+    let mut trainer = Trainer::new(collector, EPOCHS, 1000, 32).with_test_env(
+        DummyVectorEnv::new(vec![TimeLimit::new(MaskedCartPole::new(), 500)]),
+        5,
+        1,
+    );
+    trainer.train()?;
+    let recent = &trainer.history()[EPOCHS - 5..];
+    let returns: Vec<f64> = recent
+        .iter()
+        .filter_map(|e| e.test.as_ref().map(|t| t.returns_mean))
+        .collect();
+    Ok(returns.iter().sum::<f64>() / returns.len() as f64)
+}
+
+fn main() -> Result<(), HabaError> {
+    println!("Training DQN on masked CartPole...");
+    let dqn = train(DQNPolicy::new(2, 64, 2, 0.99, epsilon())?.with_lr(2.5e-4))?;
+
+    println!("Training DRQN on masked CartPole...");
+    let drqn = DRQNPolicy::new(RecurrentKind::Lstm, 2, 64, 2, 8, 0.99, epsilon())?.with_lr(2.5e-4);
+    let drqn = train(drqn)?;
+
+    println!(
+        "Mean test return over the last 5 epochs: DQN {:.1}, DRQN {:.1}",
+        dqn, drqn
+    );
+    Ok(())
+}
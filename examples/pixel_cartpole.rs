@@ -0,0 +1,82 @@
+#![allow(non_snake_case)]
+
+//! DQN on CartPole from pixels: the policy only sees stacked rendered
+//! frames and learns through a convolutional encoder. The encoder is a
+//! slimmer version of the Nature DQN one to keep CPU training time down.
+//!
+//! On one CPU core the 40 epochs take about 70 minutes. Over the last 10
+//! epochs the greedy policy averages a return of 82, against 16 for
+//! random actions. It clearly learns but does not solve the task; the
+//! state observations in `main.rs` get there much faster.
+
+use Haba::buffer::ReplayBuffer;
+use Haba::cartpole::PixelCartPole;
+use Haba::collector::Collector;
+use Haba::device::{DeviceConfig, Precision};
+use Haba::dqn::DQNPolicy;
+use Haba::error::HabaError;
+use Haba::model::CnnBuilder;
+use Haba::schedule::{Clock, Schedule, Scheduled};
+use Haba::trainer::{OffPolicyConfig, OffPolicyTrainer};
+use Haba::venv::DummyVectorEnv;
+use Haba::wrappers::TimeLimit;
+
+const EPOCHS: usize = 40;
+
+// Transitions hold two 4x80x40 observations, about 100 KB each as f64
+const BUFFER_SIZE: usize = 10_000;
+
+fn main() -> Result<(), HabaError> {
+    let env = PixelCartPole::new();
+    let (c, h, w) = env.obs_shape();
+    let venv = DummyVectorEnv::new(vec![TimeLimit::new(env, 500)]);
+
+    let epsilon = Scheduled::new(
+        Schedule::Linear {
+            start: 1.0,
+            end: 0.05,
+            duration: 30_000,
+        },
+        Clock::EnvStep,
+    );
+    let policy = DQNPolicy::with_encoder(
+        CnnBuilder::new(c, h, w).layer(16, 8, 4).layer(32, 4, 2),
+        256,
+        2,
+        0.99,
+        epsilon,
+        DeviceConfig::Auto,
+        Precision::F32,
+    )?
+    .with_lr(1e-4)
+    .with_target_update_freq(1000);
+
+    let collector = Collector::new(venv, policy, Some(ReplayBuffer::new(BUFFER_SIZE)))?;
+    // One update every 4 steps keeps the convolution backward passes affordable
+    let config = OffPolicyConfig {
+        max_epochs: EPOCHS,
+        step_per_epoch: 5000,
+        step_per_collect: 4,
+        update_per_step: 0.25,
+        batch_size: 32,
+        start_timesteps: 2000,
+        episode_per_test: 10,
+    };
+    let mut trainer =
+        OffPolicyTrainer::new(collector, config)?.with_test_env(DummyVectorEnv::new(vec![
+            TimeLimit::new(PixelCartPole::new(), 500),
+        ]));
+    trainer.seed(0)?;
+
+    println!("Training DQN on pixel CartPole...");
+    let summary = trainer.train()?;
+    let returns: Vec<f64> = summary.history[EPOCHS - 10..]
+        .iter()
+        .filter_map(|e| e.test.as_ref().map(|t| t.returns_mean))
+        .collect();
+    println!(
+        "Mean test return over the last 10 epochs: {:.1}",
+        returns.iter().sum::<f64>() / returns.len() as f64
+    );
+    Ok(())
+}
@@ -0,0 +1,288 @@
+use crate::env::{EnvCore, EnvResult, Environment, Phase, Step};
+use crate::error::HabaError;
+use crate::spaces::Space;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::f64::consts::PI;
+
+/// Joint angles (radians, 0 = hanging down) and angular velocities.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AcrobotState {
+    pub theta1: f64,
+    pub theta2: f64,
+    pub dtheta1: f64,
+    pub dtheta2: f64,
+}
+
+/// A two-link pendulum actuated only at the middle joint, as in Gymnasium's
+/// Acrobot-v1 (the "book" dynamics of Sutton & Barto, integrated with RK4).
+/// Actions 0, 1 and 2 apply a torque of -1, 0 and +1; every step costs -1
+/// until the tip swings above one link length over the base. Observations
+/// are `[cos θ1, sin θ1, cos θ2, sin θ2, θ1', θ2']`.
+pub struct Acrobot {
+    core: EnvCore<AcrobotState>,
+}
+
+impl Acrobot {
+    const DT: f64 = 0.2;
+    const LINK_LENGTH_1: f64 = 1.0;
+    const LINK_MASS_1: f64 = 1.0;
+    const LINK_MASS_2: f64 = 1.0;
+    const LINK_COM_POS_1: f64 = 0.5;
+    const LINK_COM_POS_2: f64 = 0.5;
+    const LINK_MOI: f64 = 1.0;
+    const GRAVITY: f64 = 9.8;
+    const MAX_VEL_1: f64 = 4.0 * PI;
+    const MAX_VEL_2: f64 = 9.0 * PI;
+    const TORQUES: [f64; 3] = [-1.0, 0.0, 1.0];
+
+    pub fn new() -> Self {
+        Self {
+            core: EnvCore::new(),
+        }
+    }
+
+    pub fn state(&self) -> &AcrobotState {
+        &self.core.state
+    }
+
+    fn observation(&self) -> Vec<f64> {
+        let s = &self.core.state;
+        vec![
+            s.theta1.cos(),
+            s.theta1.sin(),
+            s.theta2.cos(),
+            s.theta2.sin(),
+            s.dtheta1,
+            s.dtheta2,
+        ]
+    }
+
+    fn terminal(&self) -> bool {
+        let s = &self.core.state;
+        -s.theta1.cos() - (s.theta2 + s.theta1).cos() > 1.0
+    }
+
+    // Time derivative of [θ1, θ2, θ1', θ2'] under `torque`
+    fn dsdt(s: [f64; 4], torque: f64) -> [f64; 4] {
+        let (m1, m2) = (Self::LINK_MASS_1, Self::LINK_MASS_2);
+        let l1 = Self::LINK_LENGTH_1;
+        let (lc1, lc2) = (Self::LINK_COM_POS_1, Self::LINK_COM_POS_2);
+        let (i1, i2) = (Self::LINK_MOI, Self::LINK_MOI);
+        let g = Self::GRAVITY;
+        let [theta1, theta2, dtheta1, dtheta2] = s;
+
+        let d1 = m1 * lc1.powi(2)
+            + m2 * (l1.powi(2) + lc2.powi(2) + 2.0 * l1 * lc2 * theta2.cos())
+            + i1
+            + i2;
+        let d2 = m2 * (lc2.powi(2) + l1 * lc2 * theta2.cos()) + i2;
+        let phi2 = m2 * lc2 * g * (theta1 + theta2 - PI / 2.0).cos();
+        let phi1 = -m2 * l1 * lc2 * dtheta2.powi(2) * theta2.sin()
+            - 2.0 * m2 * l1 * lc2 * dtheta2 * dtheta1 * theta2.sin()
+            + (m1 * lc1 + m2 * l1) * g * (theta1 - PI / 2.0).cos()
+            + phi2;
+        let ddtheta2 =
+            (torque + d2 / d1 * phi1 - m2 * l1 * lc2 * dtheta1.powi(2) * theta2.sin() - phi2)
+                / (m2 * lc2.powi(2) + i2 - d2.powi(2) / d1);
+        let ddtheta1 = -(d2 * ddtheta2 + phi1) / d1;
+        [dtheta1, dtheta2, ddtheta1, ddtheta2]
+    }
+
+    // One classic fourth-order Runge-Kutta step of length `DT`
+    fn rk4(s: [f64; 4], torque: f64) -> [f64; 4] {
+        let add = |y: [f64; 4], k: [f64; 4], h: f64| std::array::from_fn(|i| y[i] + h * k[i]);
+        let half = Self::DT / 2.0;
+        let k1 = Self::dsdt(s, torque);
+        let k2 = Self::dsdt(add(s, k1, half), torque);
+        let k3 = Self::dsdt(add(s, k2, half), torque);
+        let k4 = Self::dsdt(add(s, k3, Self::DT), torque);
+        std::array::from_fn(|i| s[i] + Self::DT / 6.0 * (k1[i] + 2.0 * k2[i] + 2.0 * k3[i] + k4[i]))
+    }
+}
+
+// Wrap an angle into [-π, π]
+fn wrap(mut x: f64) -> f64 {
+    while x > PI {
+        x -= 2.0 * PI;
+    }
+    while x < -PI {
+        x += 2.0 * PI;
+    }
+    x
+}
+
+impl Default for Acrobot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Environment for Acrobot {
+    type Observation = Vec<f64>;
+    type Action = usize;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        let mut noise = || self.core.rng.gen_range(-0.1..0.1);
+        self.core.state = AcrobotState {
+            theta1: noise(),
+            theta2: noise(),
+            dtheta1: noise(),
+            dtheta2: noise(),
+        };
+        self.core.phase = Phase::Running;
+        Ok(self.observation())
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        self.core.phase.check_step("Acrobot")?;
+        let Some(&torque) = Self::TORQUES.get(action) else {
+            return Err(HabaError::Env(format!("invalid Acrobot action {}", action)));
+        };
+
+        let s = &self.core.state;
+        let [theta1, theta2, dtheta1, dtheta2] =
+            Self::rk4([s.theta1, s.theta2, s.dtheta1, s.dtheta2], torque);
+        self.core.state = AcrobotState {
+            theta1: wrap(theta1),
+            theta2: wrap(theta2),
+            dtheta1: dtheta1.clamp(-Self::MAX_VEL_1, Self::MAX_VEL_1),
+            dtheta2: dtheta2.clamp(-Self::MAX_VEL_2, Self::MAX_VEL_2),
+        };
+
+        let terminated = self.terminal();
+        if terminated {
+            self.core.phase = Phase::Terminated;
+        }
+        Ok(Step {
+            obs: self.observation(),
+            reward: if terminated { 0.0 } else { -1.0 },
+            done: terminated,
+            info: None,
+        })
+    }
+
+    fn observation_space(&self) -> Space {
+        let high = vec![1.0, 1.0, 1.0, 1.0, Self::MAX_VEL_1, Self::MAX_VEL_2];
+        Space::bounded(high.iter().map(|h| -h).collect(), high).expect("symmetric bounds")
+    }
+
+    fn action_space(&self) -> Space {
+        Space::discrete(3)
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.core.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        self.core.state_dict()
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.core.load_state_dict(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_checker::check_env;
+
+    #[test]
+    fn test_acrobot_matches_reference() {
+        // Gymnasium's Acrobot-v1 update equations evaluated in float64
+        let expected = [
+            [
+                0.033939211630615466,
+                0.0101677904243258,
+                -0.17583183080297707,
+                0.40177497965573855,
+            ],
+            [
+                -0.01642416281603125,
+                0.12287860907462045,
+                -0.3137046068686622,
+                0.6973507691058536,
+            ],
+            [
+                -0.08448526038116222,
+                0.27514839705634775,
+                -0.34814333345882253,
+                0.7882276600559249,
+            ],
+            [
+                -0.1222306461899201,
+                0.3560850361163077,
+                -0.020866001552377278,
+                0.005204369644563611,
+            ],
+            [
+                -0.09239622395193617,
+                0.276783656911194,
+                0.3115876063141581,
+                -0.7821334977578213,
+            ],
+            [
+                -0.017167801173711597,
+                0.09052729891163983,
+                0.4197473175241406,
+                -1.0371715554123653,
+            ],
+            [
+                0.05252501740342645,
+                -0.08369505653103504,
+                0.2575124174547432,
+                -0.6639884167511372,
+            ],
+            [
+                0.07989135607467117,
+                -0.16253792036504477,
+                0.008829758418800293,
+                -0.108002375747695,
+            ],
+            [
+                0.055629927207781704,
+                -0.12526170903689632,
+                -0.24518682578213258,
+                0.47038952502274645,
+            ],
+            [
+                -0.013113690256100832,
+                0.016313070454725215,
+                -0.42368373077479043,
+                0.9098946301647834,
+            ],
+        ];
+        let mut env = Acrobot::new();
+        env.reset().unwrap();
+        env.core.state = AcrobotState {
+            theta1: 0.05,
+            theta2: -0.03,
+            dtheta1: 0.02,
+            dtheta2: -0.01,
+        };
+        let actions = [2, 2, 2, 0, 0, 1, 2, 2, 2, 2];
+        for (action, want) in actions.into_iter().zip(&expected) {
+            let step = env.step(action).unwrap();
+            assert_eq!((step.reward, step.done), (-1.0, false));
+            let s = env.state();
+            let got = [s.theta1, s.theta2, s.dtheta1, s.dtheta2];
+            for (g, w) in got.iter().zip(want) {
+                assert!((g - w).abs() < 1e-12, "{:?} != {:?}", got, want);
+            }
+            assert_eq!(step.obs[1], s.theta1.sin());
+        }
+
+        // Swinging the tip high enough ends the episode without cost
+        env.core.state = AcrobotState {
+            theta1: PI - 0.1,
+            ..AcrobotState::default()
+        };
+        let step = env.step(1).unwrap();
+        assert_eq!((step.reward, step.done), (0.0, true));
+        assert!(env.step(1).is_err());
+        assert!(check_env(&mut Acrobot::new()).is_ok());
+    }
+}
@@ -0,0 +1,959 @@
+use crate::batch::Batch;
+use crate::checkpoint;
+use crate::env::{EnvResult, Environment, Info, Phase, Step};
+use crate::error::{HabaError, Result};
+use crate::policy::{HiddenState, Policy, PolicyMode, PolicyOutput};
+use crate::schedule::{Progress, Scheduled};
+use crate::spaces::{Space, standard_normal};
+use crate::stats::LearnStats;
+use crate::util::argmax;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Info key with the pseudo-regret of the pull that produced a step.
+pub const REGRET: &str = "regret";
+
+/// Reward distribution of one arm.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Arm {
+    /// Reward 1 with probability `p`, else 0.
+    Bernoulli(f64),
+    Gaussian {
+        mean: f64,
+        std: f64,
+    },
+}
+
+impl Arm {
+    pub fn mean(&self) -> f64 {
+        match *self {
+            Arm::Bernoulli(p) => p,
+            Arm::Gaussian { mean, .. } => mean,
+        }
+    }
+
+    fn sample(&self, rng: &mut ChaCha8Rng) -> f64 {
+        match *self {
+            Arm::Bernoulli(p) => f64::from(u8::from(rng.gen_bool(p))),
+            Arm::Gaussian { mean, std } => mean + std * standard_normal(rng),
+        }
+    }
+}
+
+/// Cumulative pseudo-regret: the expected reward lost against always
+/// pulling the best arm.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Regret {
+    pub pulls: usize,
+    pub cumulative: f64,
+    /// Pulls of an arm with the highest expected reward.
+    pub optimal_pulls: usize,
+}
+
+impl Regret {
+    /// Record a pull of `arm` given every arm's expected reward, returning
+    /// its regret.
+    pub fn record(&mut self, means: &[f64], arm: usize) -> f64 {
+        let best = means.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let regret = best - means[arm];
+        self.pulls += 1;
+        self.cumulative += regret;
+        if regret == 0.0 {
+            self.optimal_pulls += 1;
+        }
+        regret
+    }
+
+    /// Fraction of pulls that chose an optimal arm.
+    pub fn optimal_rate(&self) -> f64 {
+        if self.pulls == 0 {
+            0.0
+        } else {
+            self.optimal_pulls as f64 / self.pulls as f64
+        }
+    }
+}
+
+fn check_arm(arm: usize, n_arms: usize) -> EnvResult<()> {
+    if arm >= n_arms {
+        return Err(HabaError::Env(format!(
+            "arm {} out of range for {} arms",
+            arm, n_arms
+        )));
+    }
+    Ok(())
+}
+
+// Every pull is a one-step episode carrying its regret
+fn pull_step<O>(obs: O, reward: f64, regret: f64) -> Step<O> {
+    Step {
+        obs,
+        reward,
+        done: true,
+        info: Some(Info::from([(REGRET.to_string(), regret.into())])),
+    }
+}
+
+/// A stationary k-armed bandit. Observations carry no information, so they
+/// are `()`, and every pull is a one-step episode.
+pub struct MultiArmedBandit {
+    arms: Vec<Arm>,
+    regret: Regret,
+    rng: ChaCha8Rng,
+    phase: Phase,
+}
+
+impl MultiArmedBandit {
+    pub fn new(arms: Vec<Arm>) -> EnvResult<Self> {
+        if arms.is_empty() {
+            return Err(HabaError::Config(
+                "a bandit needs at least one arm".to_string(),
+            ));
+        }
+        if let Some(arm) = arms.iter().find(|arm| match arm {
+            Arm::Bernoulli(p) => !(0.0..=1.0).contains(p),
+            Arm::Gaussian { std, .. } => *std < 0.0,
+        }) {
+            return Err(HabaError::Config(format!("invalid arm {:?}", arm)));
+        }
+        Ok(Self {
+            arms,
+            regret: Regret::default(),
+            rng: ChaCha8Rng::from_entropy(),
+            phase: Phase::NeedsReset,
+        })
+    }
+
+    pub fn bernoulli(probs: &[f64]) -> EnvResult<Self> {
+        Self::new(probs.iter().map(|&p| Arm::Bernoulli(p)).collect())
+    }
+
+    pub fn gaussian(means: &[f64], std: f64) -> EnvResult<Self> {
+        Self::new(
+            means
+                .iter()
+                .map(|&mean| Arm::Gaussian { mean, std })
+                .collect(),
+        )
+    }
+
+    pub fn arms(&self) -> &[Arm] {
+        &self.arms
+    }
+
+    pub fn regret(&self) -> &Regret {
+        &self.regret
+    }
+}
+
+impl Environment for MultiArmedBandit {
+    type Observation = ();
+    type Action = usize;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        self.phase = Phase::Running;
+        Ok(())
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        self.phase.check_step("MultiArmedBandit")?;
+        check_arm(action, self.arms.len())?;
+        let reward = self.arms[action].sample(&mut self.rng);
+        let means: Vec<f64> = self.arms.iter().map(Arm::mean).collect();
+        let regret = self.regret.record(&means, action);
+        self.phase = Phase::Terminated;
+        Ok(pull_step((), reward, regret))
+    }
+
+    fn observation_space(&self) -> Space {
+        Space::discrete(1)
+    }
+
+    fn action_space(&self) -> Space {
+        Space::discrete(self.arms.len())
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        Ok(serde_json::json!({
+            "regret": self.regret,
+            "phase": self.phase,
+            "rng": self.rng,
+        }))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.regret = checkpoint::field(state, "regret")?;
+        self.phase = checkpoint::field(state, "phase")?;
+        self.rng = checkpoint::field(state, "rng")?;
+        Ok(())
+    }
+}
+
+/// Gaussian arms whose means take independent random walks after every
+/// pull, as in Sutton & Barto's non-stationary testbed. Regret is measured
+/// against the best arm at the time of each pull. Seeding restarts the
+/// walk from the initial means, so a seeded run replays exactly.
+pub struct NonStationaryBandit {
+    initial_means: Vec<f64>,
+    means: Vec<f64>,
+    std: f64,
+    walk_std: f64,
+    regret: Regret,
+    rng: ChaCha8Rng,
+    phase: Phase,
+}
+
+impl NonStationaryBandit {
+    pub fn new(means: Vec<f64>, std: f64, walk_std: f64) -> EnvResult<Self> {
+        if means.is_empty() || std < 0.0 || walk_std < 0.0 {
+            return Err(HabaError::Config(
+                "a bandit needs arms and non-negative noise".to_string(),
+            ));
+        }
+        Ok(Self {
+            initial_means: means.clone(),
+            means,
+            std,
+            walk_std,
+            regret: Regret::default(),
+            rng: ChaCha8Rng::from_entropy(),
+            phase: Phase::NeedsReset,
+        })
+    }
+
+    /// Current expected rewards.
+    pub fn means(&self) -> &[f64] {
+        &self.means
+    }
+
+    pub fn regret(&self) -> &Regret {
+        &self.regret
+    }
+}
+
+impl Environment for NonStationaryBandit {
+    type Observation = ();
+    type Action = usize;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        self.phase = Phase::Running;
+        Ok(())
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        self.phase.check_step("NonStationaryBandit")?;
+        check_arm(action, self.means.len())?;
+        let reward = self.means[action] + self.std * standard_normal(&mut self.rng);
+        let regret = self.regret.record(&self.means, action);
+        for mean in &mut self.means {
+            *mean += self.walk_std * standard_normal(&mut self.rng);
+        }
+        self.phase = Phase::Terminated;
+        Ok(pull_step((), reward, regret))
+    }
+
+    fn observation_space(&self) -> Space {
+        Space::discrete(1)
+    }
+
+    fn action_space(&self) -> Space {
+        Space::discrete(self.means.len())
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.means.clone_from(&self.initial_means);
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        Ok(serde_json::json!({
+            "means": self.means,
+            "regret": self.regret,
+            "phase": self.phase,
+            "rng": self.rng,
+        }))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.means = checkpoint::field(state, "means")?;
+        self.regret = checkpoint::field(state, "regret")?;
+        self.phase = checkpoint::field(state, "phase")?;
+        self.rng = checkpoint::field(state, "rng")?;
+        Ok(())
+    }
+}
+
+/// A linear contextual bandit: each episode draws a standard normal context
+/// `x`, and arm `a` pays `weights[a] · x` plus Gaussian noise.
+pub struct ContextualBandit {
+    weights: Vec<Vec<f64>>,
+    noise_std: f64,
+    context: Vec<f64>,
+    regret: Regret,
+    rng: ChaCha8Rng,
+    phase: Phase,
+}
+
+impl ContextualBandit {
+    pub fn new(weights: Vec<Vec<f64>>, noise_std: f64) -> EnvResult<Self> {
+        let dim = weights.first().map_or(0, Vec::len);
+        if dim == 0 || weights.iter().any(|w| w.len() != dim) {
+            return Err(HabaError::Config(
+                "every arm needs a weight vector of the same, non-zero length".to_string(),
+            ));
+        }
+        Ok(Self {
+            weights,
+            noise_std,
+            context: vec![0.0; dim],
+            regret: Regret::default(),
+            rng: ChaCha8Rng::from_entropy(),
+            phase: Phase::NeedsReset,
+        })
+    }
+
+    pub fn context_dim(&self) -> usize {
+        self.context.len()
+    }
+
+    /// Expected reward of every arm in the current context.
+    pub fn expected_rewards(&self) -> Vec<f64> {
+        self.weights.iter().map(|w| dot(w, &self.context)).collect()
+    }
+
+    pub fn regret(&self) -> &Regret {
+        &self.regret
+    }
+}
+
+impl Environment for ContextualBandit {
+    type Observation = Vec<f64>;
+    type Action = usize;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        for x in &mut self.context {
+            *x = standard_normal(&mut self.rng);
+        }
+        self.phase = Phase::Running;
+        Ok(self.context.clone())
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        self.phase.check_step("ContextualBandit")?;
+        check_arm(action, self.weights.len())?;
+        let means = self.expected_rewards();
+        let reward = means[action] + self.noise_std * standard_normal(&mut self.rng);
+        let regret = self.regret.record(&means, action);
+        self.phase = Phase::Terminated;
+        Ok(pull_step(self.context.clone(), reward, regret))
+    }
+
+    fn observation_space(&self) -> Space {
+        Space::uniform(&[self.context.len()], -f64::MAX, f64::MAX)
+    }
+
+    fn action_space(&self) -> Space {
+        Space::discrete(self.weights.len())
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        Ok(serde_json::json!({
+            "context": self.context,
+            "regret": self.regret,
+            "phase": self.phase,
+            "rng": self.rng,
+        }))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.context = checkpoint::field(state, "context")?;
+        self.regret = checkpoint::field(state, "regret")?;
+        self.phase = checkpoint::field(state, "phase")?;
+        self.rng = checkpoint::field(state, "rng")?;
+        Ok(())
+    }
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn check_action(arm: usize, n_arms: usize) -> Result<()> {
+    if arm >= n_arms {
+        return Err(HabaError::Shape(format!(
+            "arm {} out of range for {} arms",
+            arm, n_arms
+        )));
+    }
+    Ok(())
+}
+
+fn reward_stats(rewards: &[f64]) -> LearnStats {
+    let mut stats = LearnStats::new();
+    stats.insert(
+        "reward_mean",
+        rewards.iter().sum::<f64>() / rewards.len().max(1) as f64,
+    );
+    stats
+}
+
+/// Pull counts and running reward estimates per arm.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArmEstimates {
+    pub counts: Vec<usize>,
+    pub values: Vec<f64>,
+}
+
+impl ArmEstimates {
+    pub fn new(n_arms: usize) -> Self {
+        Self {
+            counts: vec![0; n_arms],
+            values: vec![0.0; n_arms],
+        }
+    }
+
+    /// Move the estimate of `arm` towards `reward`, by `step_size` or, if
+    /// `None`, to the sample average.
+    pub fn update(&mut self, arm: usize, reward: f64, step_size: Option<f64>) {
+        self.counts[arm] += 1;
+        let step = step_size.unwrap_or(1.0 / self.counts[arm] as f64);
+        self.values[arm] += step * (reward - self.values[arm]);
+    }
+
+    fn greedy(&self) -> usize {
+        argmax(self.values.iter().copied())
+    }
+}
+
+/// Pull the best-looking arm, or with probability epsilon a random one.
+pub struct EpsilonGreedy {
+    estimates: ArmEstimates,
+    epsilon: Scheduled,
+    step_size: Option<f64>,
+    progress: Progress,
+    mode: PolicyMode,
+    rng: ChaCha8Rng,
+}
+
+impl EpsilonGreedy {
+    pub fn new(n_arms: usize, epsilon: impl Into<Scheduled>) -> Self {
+        Self {
+            estimates: ArmEstimates::new(n_arms),
+            epsilon: epsilon.into(),
+            step_size: None,
+            progress: Progress::default(),
+            mode: PolicyMode::Train,
+            rng: ChaCha8Rng::from_entropy(),
+        }
+    }
+
+    /// Track non-stationary arms with a constant step size instead of
+    /// sample averages.
+    pub fn with_step_size(mut self, step_size: f64) -> Self {
+        self.step_size = Some(step_size);
+        self
+    }
+
+    pub fn estimates(&self) -> &ArmEstimates {
+        &self.estimates
+    }
+
+    /// Exploration rate at the current training progress. Always 0 in eval mode.
+    pub fn epsilon(&self) -> f64 {
+        match self.mode {
+            PolicyMode::Train => self.epsilon.value(self.progress),
+            PolicyMode::Eval => 0.0,
+        }
+    }
+}
+
+impl Policy for EpsilonGreedy {
+    type Observation = ();
+    type Action = usize;
+
+    fn forward(
+        &mut self,
+        obs: &[Self::Observation],
+        _state: Option<&HiddenState>,
+    ) -> Result<PolicyOutput<Self::Action>> {
+        let epsilon = self.epsilon().clamp(0.0, 1.0);
+        let n_arms = self.estimates.counts.len();
+        let actions = obs
+            .iter()
+            .map(|_| {
+                if epsilon > 0.0 && self.rng.gen_bool(epsilon) {
+                    self.rng.gen_range(0..n_arms)
+                } else {
+                    self.estimates.greedy()
+                }
+            })
+            .collect();
+        Ok(PolicyOutput::new(actions))
+    }
+
+    fn learn(&mut self, batch: &Batch<Self::Observation, Self::Action>) -> Result<LearnStats> {
+        for (&arm, &reward) in batch.act.iter().zip(&batch.rew) {
+            check_action(arm, self.estimates.counts.len())?;
+            self.estimates.update(arm, reward, self.step_size);
+        }
+        Ok(reward_stats(&batch.rew))
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    fn set_progress(&mut self, progress: Progress) {
+        self.progress = progress;
+    }
+
+    fn set_mode(&mut self, mode: PolicyMode) {
+        self.mode = mode;
+    }
+
+    fn mode(&self) -> PolicyMode {
+        self.mode
+    }
+}
+
+/// UCB1 (Auer et al., 2002): pull every arm once, then the arm maximising
+/// `value + c * sqrt(ln t / n)`. `c = sqrt(2)` is the classic choice.
+/// Deterministic; eval mode drops the bonus.
+pub struct Ucb1 {
+    estimates: ArmEstimates,
+    c: f64,
+    mode: PolicyMode,
+}
+
+impl Ucb1 {
+    pub fn new(n_arms: usize, c: f64) -> Self {
+        Self {
+            estimates: ArmEstimates::new(n_arms),
+            c,
+            mode: PolicyMode::Train,
+        }
+    }
+
+    pub fn estimates(&self) -> &ArmEstimates {
+        &self.estimates
+    }
+
+    fn choose(&self) -> usize {
+        if self.mode == PolicyMode::Eval {
+            return self.estimates.greedy();
+        }
+        let counts = &self.estimates.counts;
+        if let Some(unpulled) = counts.iter().position(|&n| n == 0) {
+            return unpulled;
+        }
+        let ln_t = (counts.iter().sum::<usize>() as f64).ln();
+        argmax(
+            self.estimates
+                .values
+                .iter()
+                .zip(counts)
+                .map(|(v, &n)| v + self.c * (ln_t / n as f64).sqrt()),
+        )
+    }
+}
+
+impl Policy for Ucb1 {
+    type Observation = ();
+    type Action = usize;
+
+    fn forward(
+        &mut self,
+        obs: &[Self::Observation],
+        _state: Option<&HiddenState>,
+    ) -> Result<PolicyOutput<Self::Action>> {
+        Ok(PolicyOutput::new(vec![self.choose(); obs.len()]))
+    }
+
+    fn learn(&mut self, batch: &Batch<Self::Observation, Self::Action>) -> Result<LearnStats> {
+        for (&arm, &reward) in batch.act.iter().zip(&batch.rew) {
+            check_action(arm, self.estimates.counts.len())?;
+            self.estimates.update(arm, reward, None);
+        }
+        Ok(reward_stats(&batch.rew))
+    }
+
+    fn set_mode(&mut self, mode: PolicyMode) {
+        self.mode = mode;
+    }
+
+    fn mode(&self) -> PolicyMode {
+        self.mode
+    }
+}
+
+/// Conjugate prior of `ThompsonSampling`, shared by every arm.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ThompsonPrior {
+    /// Beta(alpha, beta) for rewards in [0, 1]; fractional rewards update
+    /// it as partial successes.
+    Beta { alpha: f64, beta: f64 },
+    /// Normal prior on the mean, for Gaussian rewards of known `noise_std`.
+    Gaussian { mean: f64, std: f64, noise_std: f64 },
+}
+
+/// Thompson sampling: pull the arm whose mean, drawn from its posterior, is
+/// highest. Eval mode pulls the arm with the highest posterior mean.
+pub struct ThompsonSampling {
+    prior: ThompsonPrior,
+    // Per-arm posterior: (alpha, beta) for Beta, (mean, precision) for Gaussian
+    posterior: Vec<(f64, f64)>,
+    mode: PolicyMode,
+    rng: ChaCha8Rng,
+}
+
+impl ThompsonSampling {
+    pub fn new(n_arms: usize, prior: ThompsonPrior) -> Self {
+        let initial = match prior {
+            ThompsonPrior::Beta { alpha, beta } => (alpha, beta),
+            ThompsonPrior::Gaussian { mean, std, .. } => (mean, 1.0 / (std * std)),
+        };
+        Self {
+            prior,
+            posterior: vec![initial; n_arms],
+            mode: PolicyMode::Train,
+            rng: ChaCha8Rng::from_entropy(),
+        }
+    }
+
+    /// Posterior mean of every arm's expected reward.
+    pub fn posterior_means(&self) -> Vec<f64> {
+        self.posterior
+            .iter()
+            .map(|&(a, b)| match self.prior {
+                ThompsonPrior::Beta { .. } => a / (a + b),
+                ThompsonPrior::Gaussian { .. } => a,
+            })
+            .collect()
+    }
+
+    fn choose(&mut self) -> usize {
+        if self.mode == PolicyMode::Eval {
+            return argmax(self.posterior_means());
+        }
+        let draws: Vec<f64> = self
+            .posterior
+            .iter()
+            .map(|&(a, b)| match self.prior {
+                ThompsonPrior::Beta { .. } => sample_beta(&mut self.rng, a, b),
+                ThompsonPrior::Gaussian { .. } => a + standard_normal(&mut self.rng) / b.sqrt(),
+            })
+            .collect();
+        argmax(draws)
+    }
+}
+
+impl Policy for ThompsonSampling {
+    type Observation = ();
+    type Action = usize;
+
+    fn forward(
+        &mut self,
+        obs: &[Self::Observation],
+        _state: Option<&HiddenState>,
+    ) -> Result<PolicyOutput<Self::Action>> {
+        let actions = obs.iter().map(|_| self.choose()).collect();
+        Ok(PolicyOutput::new(actions))
+    }
+
+    fn learn(&mut self, batch: &Batch<Self::Observation, Self::Action>) -> Result<LearnStats> {
+        for (&arm, &reward) in batch.act.iter().zip(&batch.rew) {
+            check_action(arm, self.posterior.len())?;
+            let (a, b) = &mut self.posterior[arm];
+            match self.prior {
+                ThompsonPrior::Beta { .. } => {
+                    let reward = reward.clamp(0.0, 1.0);
+                    *a += reward;
+                    *b += 1.0 - reward;
+                }
+                ThompsonPrior::Gaussian { noise_std, .. } => {
+                    let noise_precision = 1.0 / (noise_std * noise_std);
+                    let precision = *b + noise_precision;
+                    *a = (*a * *b + reward * noise_precision) / precision;
+                    *b = precision;
+                }
+            }
+        }
+        Ok(reward_stats(&batch.rew))
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    fn set_mode(&mut self, mode: PolicyMode) {
+        self.mode = mode;
+    }
+
+    fn mode(&self) -> PolicyMode {
+        self.mode
+    }
+}
+
+// Marsaglia and Tsang's method, boosted for shape < 1
+fn sample_gamma(rng: &mut ChaCha8Rng, shape: f64) -> f64 {
+    if shape < 1.0 {
+        let u: f64 = rng.r#gen();
+        return sample_gamma(rng, shape + 1.0) * u.powf(1.0 / shape);
+    }
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        let x = standard_normal(rng);
+        let v = (1.0 + c * x).powi(3);
+        if v <= 0.0 {
+            continue;
+        }
+        let u: f64 = rng.r#gen();
+        if u.ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+            return d * v;
+        }
+    }
+}
+
+fn sample_beta(rng: &mut ChaCha8Rng, alpha: f64, beta: f64) -> f64 {
+    let x = sample_gamma(rng, alpha);
+    let y = sample_gamma(rng, beta);
+    x / (x + y)
+}
+
+/// Disjoint LinUCB (Li et al., 2010): a ridge regression of reward on
+/// context per arm, pulling the arm maximising
+/// `θ·x + alpha * sqrt(xᵀ A⁻¹ x)`. Eval mode drops the bonus.
+pub struct LinUcb {
+    alpha: f64,
+    dim: usize,
+    // Per arm, A⁻¹ (row-major dim x dim) kept by Sherman-Morrison updates,
+    // and b = Σ r x
+    a_inv: Vec<Vec<f64>>,
+    b: Vec<Vec<f64>>,
+    mode: PolicyMode,
+}
+
+impl LinUcb {
+    pub fn new(n_arms: usize, dim: usize, alpha: f64) -> Self {
+        let identity: Vec<f64> = (0..dim * dim)
+            .map(|i| if i % (dim + 1) == 0 { 1.0 } else { 0.0 })
+            .collect();
+        Self {
+            alpha,
+            dim,
+            a_inv: vec![identity; n_arms],
+            b: vec![vec![0.0; dim]; n_arms],
+            mode: PolicyMode::Train,
+        }
+    }
+
+    /// Current ridge-regression weights of `arm`.
+    pub fn theta(&self, arm: usize) -> Vec<f64> {
+        self.mat_vec(&self.a_inv[arm], &self.b[arm])
+    }
+
+    fn mat_vec(&self, m: &[f64], x: &[f64]) -> Vec<f64> {
+        m.chunks(self.dim).map(|row| dot(row, x)).collect()
+    }
+
+    fn check_context(&self, x: &[f64]) -> Result<()> {
+        if x.len() != self.dim {
+            return Err(HabaError::Shape(format!(
+                "expected a context of length {}, got {}",
+                self.dim,
+                x.len()
+            )));
+        }
+        Ok(())
+    }
+
+    fn choose(&self, x: &[f64]) -> usize {
+        argmax((0..self.a_inv.len()).map(|arm| {
+            let estimate = dot(&self.theta(arm), x);
+            if self.mode == PolicyMode::Eval {
+                return estimate;
+            }
+            let width = dot(x, &self.mat_vec(&self.a_inv[arm], x)).max(0.0).sqrt();
+            estimate + self.alpha * width
+        }))
+    }
+}
+
+impl Policy for LinUcb {
+    type Observation = Vec<f64>;
+    type Action = usize;
+
+    fn forward(
+        &mut self,
+        obs: &[Self::Observation],
+        _state: Option<&HiddenState>,
+    ) -> Result<PolicyOutput<Self::Action>> {
+        let mut actions = Vec::with_capacity(obs.len());
+        for x in obs {
+            self.check_context(x)?;
+            actions.push(self.choose(x));
+        }
+        Ok(PolicyOutput::new(actions))
+    }
+
+    fn learn(&mut self, batch: &Batch<Self::Observation, Self::Action>) -> Result<LearnStats> {
+        for ((x, &arm), &reward) in batch.obs.iter().zip(&batch.act).zip(&batch.rew) {
+            self.check_context(x)?;
+            check_action(arm, self.a_inv.len())?;
+            // (A + x xᵀ)⁻¹ = A⁻¹ - A⁻¹x xᵀA⁻¹ / (1 + xᵀA⁻¹x), with A⁻¹ symmetric
+            let a_inv_x = self.mat_vec(&self.a_inv[arm], x);
+            let denom = 1.0 + dot(x, &a_inv_x);
+            let dim = self.dim;
+            for (i, row) in self.a_inv[arm].chunks_mut(dim).enumerate() {
+                for (j, v) in row.iter_mut().enumerate() {
+                    *v -= a_inv_x[i] * a_inv_x[j] / denom;
+                }
+            }
+            for (b, xi) in self.b[arm].iter_mut().zip(x) {
+                *b += reward * xi;
+            }
+        }
+        Ok(reward_stats(&batch.rew))
+    }
+
+    fn set_mode(&mut self, mode: PolicyMode) {
+        self.mode = mode;
+    }
+
+    fn mode(&self) -> PolicyMode {
+        self.mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::ReplayBuffer;
+    use crate::collector::{CollectSpec, Collector};
+    use crate::env::InfoValue;
+    use crate::env_checker::check_env;
+    use crate::venv::DummyVectorEnv;
+    use std::fmt::Debug;
+
+    // Pull and learn online, one pull at a time
+    fn run<E, P>(mut env: E, mut policy: P, pulls: usize) -> Collector<DummyVectorEnv<E>, P>
+    where
+        E: Environment<Action = usize>,
+        E::Observation: Clone + Debug,
+        P: Policy<Observation = E::Observation, Action = usize>,
+    {
+        env.seed(0);
+        policy.seed(0);
+        let venv = DummyVectorEnv::new(vec![env]);
+        let mut collector = Collector::new(venv, policy, Some(ReplayBuffer::new(1))).unwrap();
+        for _ in 0..pulls {
+            collector.collect(CollectSpec::Steps(1)).unwrap();
+            collector.learn_on_buffer().unwrap();
+        }
+        collector
+    }
+
+    #[test]
+    fn test_agents_beat_uniform_play() {
+        let bandit = || MultiArmedBandit::bernoulli(&[0.1, 0.5, 0.9]).unwrap();
+        let uniform = run(bandit(), EpsilonGreedy::new(3, 1.0), 2000);
+        let uniform = uniform.env().envs()[0].regret().cumulative;
+        assert!((uniform / 2000.0 - 0.4).abs() < 0.05);
+
+        let greedy = run(bandit(), EpsilonGreedy::new(3, 0.1), 2000);
+        let regret = greedy.env().envs()[0].regret();
+        assert!(regret.cumulative < 0.2 * uniform, "{:?}", regret);
+        assert!((greedy.policy().estimates().values[2] - 0.9).abs() < 0.05);
+
+        let ucb = run(bandit(), Ucb1::new(3, 2f64.sqrt()), 2000);
+        let regret = ucb.env().envs()[0].regret();
+        assert!(regret.cumulative < 0.2 * uniform, "{:?}", regret);
+
+        let prior = ThompsonPrior::Beta {
+            alpha: 1.0,
+            beta: 1.0,
+        };
+        let thompson = run(bandit(), ThompsonSampling::new(3, prior), 2000);
+        let regret = thompson.env().envs()[0].regret();
+        assert!(regret.optimal_rate() > 0.95, "{:?}", regret);
+        assert_eq!(argmax(thompson.policy().posterior_means()), 2);
+
+        let prior = ThompsonPrior::Gaussian {
+            mean: 0.0,
+            std: 10.0,
+            noise_std: 1.0,
+        };
+        let bandit = MultiArmedBandit::gaussian(&[0.0, 1.0, 0.5], 1.0).unwrap();
+        let thompson = run(bandit, ThompsonSampling::new(3, prior), 2000);
+        let regret = thompson.env().envs()[0].regret();
+        assert!(regret.optimal_rate() > 0.9, "{:?}", regret);
+    }
+
+    #[test]
+    fn test_constant_step_size_tracks_drift() {
+        let bandit = NonStationaryBandit::new(vec![0.0; 5], 1.0, 0.05).unwrap();
+        let agent = EpsilonGreedy::new(5, 0.1).with_step_size(0.1);
+        let collector = run(bandit, agent, 3000);
+        let means = collector.env().envs()[0].means();
+        // The means drifted apart, and the agent follows the current best arm
+        assert!(means.iter().any(|&m| m.abs() > 0.5));
+        assert_eq!(
+            collector.policy().estimates().greedy(),
+            argmax(means.iter().copied())
+        );
+    }
+
+    #[test]
+    fn test_lin_ucb_learns_contexts() {
+        let weights = vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![-1.0, -1.0]];
+        let bandit = ContextualBandit::new(weights.clone(), 0.1).unwrap();
+        let collector = run(bandit, LinUcb::new(3, 2, 1.0), 2000);
+        let regret = collector.env().envs()[0].regret();
+        assert!(regret.optimal_rate() > 0.9, "{:?}", regret);
+        for (arm, w) in weights.iter().enumerate() {
+            let theta = collector.policy().theta(arm);
+            for (t, w) in theta.iter().zip(w) {
+                assert!((t - w).abs() < 0.1, "arm {}: {:?}", arm, theta);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bandit_envs() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let draws: Vec<f64> = (0..5000).map(|_| sample_beta(&mut rng, 2.0, 6.0)).collect();
+        assert!((draws.iter().sum::<f64>() / 5000.0 - 0.25).abs() < 0.01);
+
+        let mut regret = Regret::default();
+        assert_eq!(regret.record(&[0.25, 0.75], 0), 0.5);
+        assert_eq!(regret.record(&[0.25, 0.75], 1), 0.0);
+        assert_eq!(regret.optimal_rate(), 0.5);
+
+        let mut env = MultiArmedBandit::bernoulli(&[0.5]).unwrap();
+        env.reset().unwrap();
+        let info = env.step(0).unwrap().info.unwrap();
+        assert_eq!(info[REGRET], InfoValue::Float(0.0));
+        assert!(env.step(1).is_err());
+        assert!(MultiArmedBandit::bernoulli(&[1.5]).is_err());
+        assert!(ContextualBandit::new(vec![vec![1.0], vec![]], 0.0).is_err());
+
+        let report = check_env(&mut MultiArmedBandit::gaussian(&[0.0, 1.0], 1.0).unwrap());
+        assert!(report.is_ok(), "{}", report);
+        let report = check_env(&mut NonStationaryBandit::new(vec![0.0; 3], 1.0, 0.1).unwrap());
+        assert!(report.is_ok(), "{}", report);
+        let weights = vec![vec![1.0, 0.0], vec![0.0, 1.0]];
+        let report = check_env(&mut ContextualBandit::new(weights, 0.1).unwrap());
+        assert!(report.is_ok(), "{}", report);
+    }
+}
@@ -1,3 +1,16 @@
+use crate::env::Info;
+use serde::{Deserialize, Serialize};
+
+/// Policy-side data recorded alongside a single transition.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PolicyExtra {
+    pub logits: Option<Vec<f64>>,
+    pub log_prob: Option<f64>,
+    pub value: Option<f64>,
+    // Hidden state the policy was fed when it chose the action
+    pub state: Option<Vec<f64>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Batch<O, A> {
     pub obs: Vec<O>,
@@ -5,6 +18,21 @@ pub struct Batch<O, A> {
     pub rew: Vec<f64>,
     pub done: Vec<bool>,
     pub obs_next: Vec<O>,
+    // Action taken at `obs_next`, for on-policy targets. Repeats `act` on
+    // `done` transitions, where it is never used.
+    pub act_next: Option<Vec<A>>,
+    // Step info, when the buffer was asked to store it
+    pub info: Option<Vec<Info>>,
+
+    // Only present when every transition in the batch recorded them
+    pub logits: Option<Vec<Vec<f64>>>,
+    pub log_prob: Option<Vec<f64>>,
+    pub value: Option<Vec<f64>>,
+    pub state: Option<Vec<Vec<f64>>>,
+
+    // Set when the batch holds `len / seq_len` runs of consecutive
+    // transitions, stored one run after another
+    pub seq_len: Option<usize>,
 }
 
 impl<O, A> Batch<O, A> {
@@ -15,10 +43,30 @@ impl<O, A> Batch<O, A> {
             rew,
             done,
             obs_next,
+            act_next: None,
+            info: None,
+            logits: None,
+            log_prob: None,
+            value: None,
+            state: None,
+            seq_len: None,
         }
     }
 
+    /// Attach per-transition policy data. A column is set only if no transition is missing it.
+    pub fn with_extras(mut self, extras: &[PolicyExtra]) -> Self {
+        self.logits = extras.iter().map(|e| e.logits.clone()).collect();
+        self.log_prob = extras.iter().map(|e| e.log_prob).collect();
+        self.value = extras.iter().map(|e| e.value).collect();
+        self.state = extras.iter().map(|e| e.state.clone()).collect();
+        self
+    }
+
     pub fn len(&self) -> usize {
         self.obs.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.obs.is_empty()
+    }
 }
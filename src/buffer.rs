@@ -1,17 +1,81 @@
-use crate::batch::Batch;
+use crate::batch::{Batch, PolicyExtra};
+use crate::env::Info;
 use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(from = "StoredBuffer<O, A>")]
 pub struct ReplayBuffer<O, A> {
     obs: Vec<O>,
     act: Vec<A>,
     rew: Vec<f64>,
     done: Vec<bool>,
     obs_next: Vec<O>,
+    extra: Vec<PolicyExtra>,
+    // Which sub-env produced each transition, so sequences never mix envs
+    env_id: Vec<usize>,
+    // Only recorded for policies that need the next action
+    act_next: Vec<Option<A>>,
+    // Only recorded when `store_info` is set
+    info: Vec<Option<Info>>,
+    store_info: bool,
 
     capacity: usize,
     index: usize, // Current write position
     size: usize,  // Current number of elements
+    rng: ChaCha8Rng,
+}
+
+// How a buffer is loaded. Columns added after the first release may be
+// missing from older checkpoints, and are padded to the stored length.
+#[derive(Deserialize)]
+struct StoredBuffer<O, A> {
+    obs: Vec<O>,
+    act: Vec<A>,
+    rew: Vec<f64>,
+    done: Vec<bool>,
+    obs_next: Vec<O>,
+    extra: Vec<PolicyExtra>,
+    #[serde(default)]
+    env_id: Vec<usize>,
+    #[serde(default = "Vec::new")]
+    act_next: Vec<Option<A>>,
+    #[serde(default)]
+    info: Vec<Option<Info>>,
+    #[serde(default)]
+    store_info: bool,
+    capacity: usize,
+    index: usize,
+    size: usize,
+    rng: ChaCha8Rng,
+}
+
+impl<O, A> From<StoredBuffer<O, A>> for ReplayBuffer<O, A> {
+    fn from(stored: StoredBuffer<O, A>) -> Self {
+        let len = stored.obs.len();
+        let mut buffer = Self {
+            obs: stored.obs,
+            act: stored.act,
+            rew: stored.rew,
+            done: stored.done,
+            obs_next: stored.obs_next,
+            extra: stored.extra,
+            env_id: stored.env_id,
+            act_next: stored.act_next,
+            info: stored.info,
+            store_info: stored.store_info,
+            capacity: stored.capacity,
+            index: stored.index,
+            size: stored.size,
+            rng: stored.rng,
+        };
+        buffer.env_id.resize(len, 0);
+        buffer.act_next.resize_with(len, || None);
+        buffer.info.resize(len, None);
+        buffer
+    }
 }
 
 impl<O: Clone, A: Clone> ReplayBuffer<O, A> {
@@ -22,13 +86,94 @@ impl<O: Clone, A: Clone> ReplayBuffer<O, A> {
             rew: Vec::with_capacity(capacity),
             done: Vec::with_capacity(capacity),
             obs_next: Vec::with_capacity(capacity),
+            extra: Vec::with_capacity(capacity),
+            env_id: Vec::with_capacity(capacity),
+            act_next: Vec::with_capacity(capacity),
+            info: Vec::with_capacity(capacity),
+            store_info: false,
             capacity,
             index: 0,
             size: 0,
+            rng: ChaCha8Rng::from_entropy(),
         }
     }
 
+    /// Keep each transition's step info and return it in `Batch::info`.
+    /// Off by default, as info can be large.
+    pub fn with_info(mut self, store_info: bool) -> Self {
+        self.store_info = store_info;
+        self
+    }
+
+    /// Make sampling reproducible.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
     pub fn add(&mut self, obs: O, act: A, rew: f64, done: bool, obs_next: O) {
+        self.add_with_extra(obs, act, rew, done, obs_next, PolicyExtra::default());
+    }
+
+    pub fn add_with_extra(
+        &mut self,
+        obs: O,
+        act: A,
+        rew: f64,
+        done: bool,
+        obs_next: O,
+        extra: PolicyExtra,
+    ) {
+        self.add_from_env(0, obs, act, rew, done, obs_next, extra);
+    }
+
+    /// Add a transition produced by sub-env `env_id` of a vectorized env.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_from_env(
+        &mut self,
+        env_id: usize,
+        obs: O,
+        act: A,
+        rew: f64,
+        done: bool,
+        obs_next: O,
+        extra: PolicyExtra,
+    ) {
+        self.add_transition(env_id, obs, act, rew, done, obs_next, None, None, extra);
+    }
+
+    /// Like `add_from_env`, also recording the action taken at `obs_next`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_on_policy(
+        &mut self,
+        env_id: usize,
+        obs: O,
+        act: A,
+        rew: f64,
+        done: bool,
+        obs_next: O,
+        act_next: A,
+        extra: PolicyExtra,
+    ) {
+        let act_next = Some(act_next);
+        self.add_transition(env_id, obs, act, rew, done, obs_next, act_next, None, extra);
+    }
+
+    /// Add a transition with every optional field. `info` is dropped unless
+    /// the buffer stores info.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_transition(
+        &mut self,
+        env_id: usize,
+        obs: O,
+        act: A,
+        rew: f64,
+        done: bool,
+        obs_next: O,
+        act_next: Option<A>,
+        info: Option<Info>,
+        extra: PolicyExtra,
+    ) {
+        let info = if self.store_info { info } else { None };
         if self.size < self.capacity {
             // Append
             self.obs.push(obs);
@@ -36,6 +181,10 @@ impl<O: Clone, A: Clone> ReplayBuffer<O, A> {
             self.rew.push(rew);
             self.done.push(done);
             self.obs_next.push(obs_next);
+            self.extra.push(extra);
+            self.env_id.push(env_id);
+            self.act_next.push(act_next);
+            self.info.push(info);
             self.size += 1;
         } else {
             // Overwrite
@@ -44,34 +193,123 @@ impl<O: Clone, A: Clone> ReplayBuffer<O, A> {
             self.rew[self.index] = rew;
             self.done[self.index] = done;
             self.obs_next[self.index] = obs_next;
+            self.extra[self.index] = extra;
+            self.env_id[self.index] = env_id;
+            self.act_next[self.index] = act_next;
+            self.info[self.index] = info;
         }
 
         self.index = (self.index + 1) % self.capacity;
     }
 
-    pub fn sample(&self, batch_size: usize) -> Batch<O, A> {
-        let mut rng = rand::thread_rng();
+    pub fn sample(&mut self, batch_size: usize) -> Batch<O, A> {
         let indices: Vec<usize> = (0..self.size).collect();
         let sampled_indices: Vec<usize> = indices
-            .choose_multiple(&mut rng, batch_size)
+            .choose_multiple(&mut self.rng, batch_size)
             .cloned()
             .collect();
+        self.gather(&sampled_indices)
+    }
+
+    /// Sample `batch_size` runs of `seq_len` consecutive transitions, each
+    /// from a single sub-env. Runs may cross episode boundaries; the `done`
+    /// flags mark where. Returns `None` if the buffer holds too little data.
+    pub fn sample_sequences(&mut self, batch_size: usize, seq_len: usize) -> Option<Batch<O, A>> {
+        if seq_len == 0 || self.size < seq_len {
+            return None;
+        }
+        let mut indices = Vec::with_capacity(batch_size * seq_len);
+        let mut attempts = 0;
+        while indices.len() < batch_size * seq_len {
+            // Starts too close to the newest data cannot be completed
+            attempts += 1;
+            if attempts > 10 * batch_size + 100 {
+                return None;
+            }
+            let start = self.rng.gen_range(0..self.size);
+            if let Some(run) = self.run_from(start, seq_len) {
+                indices.extend(run);
+            }
+        }
+
+        let mut batch = self.gather(&indices);
+        batch.seq_len = Some(seq_len);
+        Some(batch)
+    }
+
+    /// Every stored transition, oldest first, leaving the buffer empty.
+    pub fn drain(&mut self) -> Batch<O, A> {
+        let indices: Vec<usize> = (0..self.size).map(|i| self.physical(i)).collect();
+        let batch = self.gather(&indices);
+        self.clear();
+        batch
+    }
+
+    pub fn clear(&mut self) {
+        self.obs.clear();
+        self.act.clear();
+        self.rew.clear();
+        self.done.clear();
+        self.obs_next.clear();
+        self.extra.clear();
+        self.env_id.clear();
+        self.act_next.clear();
+        self.info.clear();
+        self.index = 0;
+        self.size = 0;
+    }
+
+    // Storage index of the transition at logical position `logical` (0 = oldest)
+    fn physical(&self, logical: usize) -> usize {
+        let oldest = if self.size < self.capacity {
+            0
+        } else {
+            self.index
+        };
+        (oldest + logical) % self.capacity
+    }
+
+    // Physical indices of `seq_len` transitions of the env that produced the
+    // transition at logical position `start` (0 = oldest)
+    fn run_from(&self, start: usize, seq_len: usize) -> Option<Vec<usize>> {
+        let physical = |logical: usize| self.physical(logical);
+        let env = self.env_id[physical(start)];
+        let run: Vec<usize> = (start..self.size)
+            .map(physical)
+            .filter(|&i| self.env_id[i] == env)
+            .take(seq_len)
+            .collect();
+        (run.len() == seq_len).then_some(run)
+    }
 
-        let mut b_obs = Vec::with_capacity(batch_size);
-        let mut b_act = Vec::with_capacity(batch_size);
-        let mut b_rew = Vec::with_capacity(batch_size);
-        let mut b_done = Vec::with_capacity(batch_size);
-        let mut b_obs_next = Vec::with_capacity(batch_size);
+    fn gather(&self, indices: &[usize]) -> Batch<O, A> {
+        let mut b_obs = Vec::with_capacity(indices.len());
+        let mut b_act = Vec::with_capacity(indices.len());
+        let mut b_rew = Vec::with_capacity(indices.len());
+        let mut b_done = Vec::with_capacity(indices.len());
+        let mut b_obs_next = Vec::with_capacity(indices.len());
+        let mut b_extra = Vec::with_capacity(indices.len());
+        let mut b_act_next = Vec::with_capacity(indices.len());
 
-        for &idx in &sampled_indices {
+        for &idx in indices {
             b_obs.push(self.obs[idx].clone());
             b_act.push(self.act[idx].clone());
             b_rew.push(self.rew[idx]);
             b_done.push(self.done[idx]);
             b_obs_next.push(self.obs_next[idx].clone());
+            b_extra.push(self.extra[idx].clone());
+            b_act_next.push(self.act_next[idx].clone());
         }
 
-        Batch::new(b_obs, b_act, b_rew, b_done, b_obs_next)
+        let mut batch = Batch::new(b_obs, b_act, b_rew, b_done, b_obs_next).with_extras(&b_extra);
+        batch.act_next = b_act_next.into_iter().collect();
+        if self.store_info {
+            let info = indices
+                .iter()
+                .map(|&i| self.info[i].clone().unwrap_or_default());
+            batch.info = Some(info.collect());
+        }
+        batch
     }
 
     pub fn len(&self) -> usize {
@@ -82,3 +320,55 @@ impl<O: Clone, A: Clone> ReplayBuffer<O, A> {
         self.size == 0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequences_follow_one_env() {
+        let mut buffer = ReplayBuffer::new(50);
+        buffer.seed(0);
+        // Two interleaved envs; env 1 observations are negative. 60 adds wrap the ring.
+        for t in 1..=30 {
+            for env in 0..2 {
+                let obs = if env == 0 { t as f64 } else { -(t as f64) };
+                buffer.add_from_env(env, obs, 0, 1.0, t % 7 == 6, obs, PolicyExtra::default());
+            }
+        }
+
+        let batch = buffer.sample_sequences(8, 4).unwrap();
+        assert_eq!(batch.seq_len, Some(4));
+        assert_eq!(batch.len(), 32);
+        for seq in batch.obs.chunks(4) {
+            // Consecutive steps of the same env, in order
+            let steps: Vec<f64> = seq.iter().map(|o| o.abs()).collect();
+            assert!(steps.windows(2).all(|w| w[1] == w[0] + 1.0), "{:?}", seq);
+            assert!(seq.iter().all(|o| o.signum() == seq[0].signum()));
+        }
+
+        assert!(buffer.sample_sequences(1, 26).is_none());
+    }
+
+    #[test]
+    fn test_load_pads_missing_columns() {
+        let mut buffer = ReplayBuffer::new(3);
+        for t in 0..4 {
+            buffer.add(t as f64, 0usize, 1.0, false, t as f64 + 1.0);
+        }
+        let mut state = serde_json::to_value(&buffer).unwrap();
+        // As written before transitions kept their env and next action
+        let fields = state.as_object_mut().unwrap();
+        fields.remove("env_id");
+        fields.remove("act_next");
+        fields.remove("info");
+
+        let mut loaded: ReplayBuffer<f64, usize> = serde_json::from_value(state).unwrap();
+        assert_eq!(loaded.sample_sequences(2, 2).unwrap().len(), 4);
+        loaded.add_on_policy(1, 4.0, 1, 1.0, true, 5.0, 0, PolicyExtra::default());
+        let batch = loaded.drain();
+        assert_eq!(batch.obs, vec![2.0, 3.0, 4.0]);
+        // The older transitions have no next action, so neither has the batch
+        assert_eq!(batch.act_next, None);
+    }
+}
@@ -1,5 +1,15 @@
-use crate::env::{EnvResult, Environment, Step};
+use crate::checkpoint;
+use crate::env::{EnvCore, EnvResult, Environment, Parameterized, Phase, Step};
+use crate::error::{HabaError, Result};
+use crate::preprocess::{Image, Preprocessor};
+use crate::spaces::Space;
+use crate::venv::VectorEnv;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CartPoleState {
     pub x: f64,
     pub x_dot: f64,
@@ -7,92 +17,1050 @@ pub struct CartPoleState {
     pub theta_dot: f64,
 }
 
+/// How `CartPole` integrates its equations of motion.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    /// Explicit Euler, as in CartPole-v1.
+    #[default]
+    Euler,
+    /// Semi-implicit Euler: velocities are updated before positions.
+    SemiImplicitEuler,
+}
+
+impl Integrator {
+    /// Advance one position/velocity pair by `tau` given its acceleration.
+    #[inline]
+    fn update(self, tau: f64, pos: &mut f64, vel: &mut f64, acc: f64) {
+        match self {
+            Integrator::Euler => {
+                *pos += tau * *vel;
+                *vel += tau * acc;
+            }
+            Integrator::SemiImplicitEuler => {
+                *vel += tau * acc;
+                *pos += tau * *vel;
+            }
+        }
+    }
+}
+
+/// Physical constants of `CartPole`. The defaults are CartPole-v1's.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CartPoleParams {
+    pub gravity: f64,
+    pub masscart: f64,
+    pub masspole: f64,
+    /// Half the pole's length.
+    pub length: f64,
+    pub force_mag: f64,
+    /// Seconds between state updates.
+    pub tau: f64,
+}
+
+impl CartPoleParams {
+    /// Names accepted by `get` and `set`.
+    pub const NAMES: [&'static str; 6] = [
+        "gravity",
+        "masscart",
+        "masspole",
+        "length",
+        "force_mag",
+        "tau",
+    ];
+
+    pub fn get(&self, name: &str) -> Option<f64> {
+        match name {
+            "gravity" => Some(self.gravity),
+            "masscart" => Some(self.masscart),
+            "masspole" => Some(self.masspole),
+            "length" => Some(self.length),
+            "force_mag" => Some(self.force_mag),
+            "tau" => Some(self.tau),
+            _ => None,
+        }
+    }
+
+    /// Set a parameter by name. Everything but gravity and the force must
+    /// be positive.
+    pub fn set(&mut self, name: &str, value: f64) -> EnvResult<()> {
+        let (field, positive) = match name {
+            "gravity" => (&mut self.gravity, false),
+            "masscart" => (&mut self.masscart, true),
+            "masspole" => (&mut self.masspole, true),
+            "length" => (&mut self.length, true),
+            "force_mag" => (&mut self.force_mag, false),
+            "tau" => (&mut self.tau, true),
+            _ => {
+                return Err(HabaError::Config(format!(
+                    "unknown CartPole parameter {}",
+                    name
+                )));
+            }
+        };
+        if !value.is_finite() || (positive && value <= 0.0) {
+            return Err(HabaError::Config(format!(
+                "invalid CartPole {} {}",
+                name, value
+            )));
+        }
+        *field = value;
+        Ok(())
+    }
+
+    fn total_mass(&self) -> f64 {
+        self.masscart + self.masspole
+    }
+
+    fn polemass_length(&self) -> f64 {
+        self.masspole * self.length
+    }
+}
+
+impl Default for CartPoleParams {
+    fn default() -> Self {
+        Self {
+            gravity: 9.8,
+            masscart: 1.0,
+            masspole: 0.1,
+            length: 0.5,
+            force_mag: 10.0,
+            tau: 0.02,
+        }
+    }
+}
+
+/// The cart-pole balancing task with Gymnasium's CartPole-v1 dynamics.
+/// Actions are 0 (push left) and 1 (push right); every step earns a reward
+/// of 1. Episodes start from a state drawn uniformly from ±0.05 and
+/// terminate when the pole tilts beyond 12 degrees or the cart leaves the
+/// track. Stepping a terminated episode is an error. Episodes are otherwise
+/// unbounded; wrap in `wrappers::TimeLimit` to limit their length.
 pub struct CartPole {
-    state: CartPoleState,
-    max_steps: usize,
-    current_step: usize,
+    core: EnvCore<CartPoleState>,
+    params: CartPoleParams,
+    integrator: Integrator,
 }
 
 impl CartPole {
-    pub fn new(max_steps: usize) -> Self {
-        CartPole {
-            state: CartPoleState {
-                x: 0.0,
-                x_dot: 0.0,
-                theta: 0.0,
-                theta_dot: 0.0,
-            },
-            max_steps,
-            current_step: 0,
+    const X_THRESHOLD: f64 = 2.4;
+    const THETA_THRESHOLD: f64 = 12.0 * 2.0 * std::f64::consts::PI / 360.0;
+
+    pub fn new() -> Self {
+        Self {
+            core: EnvCore::new(),
+            params: CartPoleParams::default(),
+            integrator: Integrator::default(),
+        }
+    }
+
+    pub fn with_integrator(mut self, integrator: Integrator) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
+    pub fn with_params(mut self, params: CartPoleParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn state(&self) -> &CartPoleState {
+        &self.core.state
+    }
+
+    pub fn params(&self) -> &CartPoleParams {
+        &self.params
+    }
+
+    /// Parameters may be changed mid-episode; they apply from the next step.
+    pub fn params_mut(&mut self) -> &mut CartPoleParams {
+        &mut self.params
+    }
+
+    fn force(action: usize, params: &CartPoleParams) -> EnvResult<f64> {
+        match action {
+            0 => Ok(-params.force_mag),
+            1 => Ok(params.force_mag),
+            _ => Err(HabaError::Env(format!(
+                "invalid CartPole action {}",
+                action
+            ))),
+        }
+    }
+
+    // Equations of motion, shared with `BatchedCartPole`. Returns the cart
+    // and pole accelerations.
+    #[inline]
+    fn accelerations(
+        p: &CartPoleParams,
+        force: f64,
+        sin_theta: f64,
+        cos_theta: f64,
+        theta_dot: f64,
+    ) -> (f64, f64) {
+        let (total_mass, polemass_length) = (p.total_mass(), p.polemass_length());
+        let temp = (force + polemass_length * theta_dot.powi(2) * sin_theta) / total_mass;
+        let theta_acc = (p.gravity * sin_theta - cos_theta * temp)
+            / (p.length * (4.0 / 3.0 - p.masspole * cos_theta.powi(2) / total_mass));
+        let x_acc = temp - polemass_length * theta_acc * cos_theta / total_mass;
+        (x_acc, theta_acc)
+    }
+
+    #[inline]
+    fn terminated(x: f64, theta: f64) -> bool {
+        // `|` rather than `||` keeps the batched mask pass branch-free
+        (x.abs() > Self::X_THRESHOLD) | (theta.abs() > Self::THETA_THRESHOLD)
+    }
+
+    fn initial_state(rng: &mut ChaCha8Rng) -> CartPoleState {
+        let mut noise = || rng.gen_range(-0.05..0.05);
+        CartPoleState {
+            x: noise(),
+            x_dot: noise(),
+            theta: noise(),
+            theta_dot: noise(),
         }
     }
+
+    fn observation(&self) -> Vec<f64> {
+        vec![
+            self.core.state.x,
+            self.core.state.x_dot,
+            self.core.state.theta,
+            self.core.state.theta_dot,
+        ]
+    }
+
+    /// Draw the cart and pole as an RGB image, with the track spanning the
+    /// full width, on a black background. The pole is drawn longer than to scale so that small
+    /// angles stay visible at low resolution.
+    pub fn render(&self, height: usize, width: usize) -> Image {
+        const TRACK_HALF_WIDTH: f64 = 2.4;
+        const CART: [u8; 3] = [255, 255, 255];
+        const POLE: [u8; 3] = [204, 153, 102];
+
+        // Dark background, so empty pixels are zero after scaling
+        let mut image = Image::new(height, width, 3);
+        let scale = width as f64 / (2.0 * TRACK_HALF_WIDTH);
+        let cart_x = (self.core.state.x + TRACK_HALF_WIDTH) * scale;
+        let cart_y = height as f64 * 0.85;
+        let (half_w, half_h) = (0.25 * scale, 0.15 * scale);
+
+        let mut fill = |x: f64, y: f64, color: &[u8; 3]| {
+            if x >= 0.0 && y >= 0.0 && (x as usize) < width && (y as usize) < height {
+                image.set_pixel(y as usize, x as usize, color);
+            }
+        };
+        let mut y = cart_y - half_h;
+        while y < cart_y + half_h {
+            let mut x = cart_x - half_w;
+            while x < cart_x + half_w {
+                fill(x, y, &CART);
+                x += 0.5;
+            }
+            y += 0.5;
+        }
+
+        // Pole from the top of the cart, theta measured from vertical
+        let pole_len = 0.7 * height as f64;
+        let half_thickness = (0.05 * scale).max(1.0);
+        let (dx, dy) = (self.core.state.theta.sin(), -self.core.state.theta.cos());
+        let mut t = 0.0;
+        while t <= pole_len {
+            let (px, py) = (cart_x + t * dx, cart_y - half_h + t * dy);
+            let mut s = -half_thickness;
+            while s <= half_thickness {
+                fill(px - s * dy, py + s * dx, &POLE);
+                s += 0.5;
+            }
+            t += 0.5;
+        }
+        image
+    }
+
+    /// Draw the scene as text: the pole, the cart on its track, and the
+    /// raw state.
+    pub fn render_text(&self) -> String {
+        const WIDTH: usize = 41;
+        let position = (self.core.state.x + Self::X_THRESHOLD) / (2.0 * Self::X_THRESHOLD);
+        let column = (position * (WIDTH - 1) as f64)
+            .round()
+            .clamp(0.0, (WIDTH - 1) as f64) as usize;
+        let pole = match self.core.state.theta {
+            t if t > 0.05 => '/',
+            t if t < -0.05 => '\\',
+            _ => '|',
+        };
+        let mut track = vec!['-'; WIDTH];
+        track[column] = '#';
+        format!(
+            "{}{}\n{}\nx={:+.3} x_dot={:+.3} theta={:+.3} theta_dot={:+.3}",
+            " ".repeat(column),
+            pole,
+            track.into_iter().collect::<String>(),
+            self.core.state.x,
+            self.core.state.x_dot,
+            self.core.state.theta,
+            self.core.state.theta_dot
+        )
+    }
+}
+
+impl Default for CartPole {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parameterized for CartPole {
+    fn param(&self, name: &str) -> Option<f64> {
+        self.params.get(name)
+    }
+
+    fn set_param(&mut self, name: &str, value: f64) -> EnvResult<()> {
+        self.params.set(name, value)
+    }
 }
 
 impl Environment for CartPole {
     type Observation = Vec<f64>;
-    type Action = f64;
+    type Action = usize;
 
     fn reset(&mut self) -> EnvResult<Self::Observation> {
-        self.state.x = 0.0;
-        self.state.x_dot = 0.0;
-        self.state.theta = 0.0;
-        self.state.theta_dot = 0.0;
-        self.current_step = 0;
-        Ok(vec![
-            self.state.x,
-            self.state.x_dot,
-            self.state.theta,
-            self.state.theta_dot,
-        ])
+        self.core.state = Self::initial_state(&mut self.core.rng);
+        self.core.phase = Phase::Running;
+        Ok(self.observation())
     }
 
     fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
-        self.current_step += 1;
-
-        // Constants for Physics (Standard CartPole)
-        const GRAVITY: f64 = 9.8;
-        const MASSCART: f64 = 1.0;
-        const MASSPOLE: f64 = 0.1;
-        const TOTAL_MASS: f64 = MASSCART + MASSPOLE;
-        const LENGTH: f64 = 0.5; // actually half the pole's length
-        const POLEMASS_LENGTH: f64 = MASSPOLE * LENGTH;
-        const FORCE_MAG: f64 = 10.0;
-        const TAU: f64 = 0.02; // seconds between state updates
-
-        let force = if action == 1.0 { FORCE_MAG } else { -FORCE_MAG };
-
-        let cos_theta = self.state.theta.cos();
-        let sin_theta = self.state.theta.sin();
-
-        // Equations of Motion
-        let temp =
-            (force + POLEMASS_LENGTH * self.state.theta_dot.powi(2) * sin_theta) / TOTAL_MASS;
-        let theta_acc = (GRAVITY * sin_theta - cos_theta * temp)
-            / (LENGTH * (4.0 / 3.0 - MASSPOLE * cos_theta.powi(2) / TOTAL_MASS));
-        let x_acc = temp - POLEMASS_LENGTH * theta_acc * cos_theta / TOTAL_MASS;
-
-        // Euler Integration
-        self.state.x += TAU * self.state.x_dot;
-        self.state.x_dot += TAU * x_acc;
-        self.state.theta += TAU * self.state.theta_dot;
-        self.state.theta_dot += TAU * theta_acc;
-
-        // Done condition: Pole falls over (> 12 degrees) or cart goes out of bounds (> 2.4 units)
-        // 12 degrees in radians is approx 0.209
-        let done = self.state.x.abs() > 2.4
-            || self.state.theta.abs() > 0.209
-            || self.current_step >= self.max_steps;
+        self.core.phase.check_step("CartPole")?;
+        let force = Self::force(action, &self.params)?;
+        let s = &mut self.core.state;
+        let (x_acc, theta_acc) = Self::accelerations(
+            &self.params,
+            force,
+            s.theta.sin(),
+            s.theta.cos(),
+            s.theta_dot,
+        );
+        let tau = self.params.tau;
+        self.integrator.update(tau, &mut s.x, &mut s.x_dot, x_acc);
+        self.integrator
+            .update(tau, &mut s.theta, &mut s.theta_dot, theta_acc);
+
+        let terminated = Self::terminated(s.x, s.theta);
+        if terminated {
+            self.core.phase = Phase::Terminated;
+        }
 
         Ok(Step {
-            obs: vec![
-                self.state.x,
-                self.state.x_dot,
-                self.state.theta,
-                self.state.theta_dot,
-            ],
+            obs: self.observation(),
             reward: 1.0, // Survive one more frame = +1 point
-            done,
+            done: terminated,
             info: None,
         })
     }
+
+    fn observation_space(&self) -> Space {
+        // Twice the termination thresholds, as episodes end beyond them
+        let high = vec![
+            2.0 * Self::X_THRESHOLD,
+            f64::MAX,
+            2.0 * Self::THETA_THRESHOLD,
+            f64::MAX,
+        ];
+        Space::bounded(high.iter().map(|h| -h).collect(), high).expect("symmetric bounds")
+    }
+
+    fn action_space(&self) -> Space {
+        Space::discrete(2)
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.core.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        let mut state = self.core.state_dict()?;
+        state["params"] = serde_json::json!(self.params);
+        Ok(state)
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.core.load_state_dict(state)?;
+        self.params = checkpoint::field_or_default(state, "params")?;
+        Ok(())
+    }
+}
+
+/// `n` CartPoles stepped in lockstep, with the state stored as one
+/// contiguous array per variable. A step makes one pass over the arrays per
+/// stage: force, sin/cos, accelerations, integration and the termination
+/// mask. Apart from sin/cos, which are libm calls, these passes are
+/// branch-free arithmetic the compiler can vectorise. The reset pass draws
+/// from each terminated instance's RNG and is scalar. There is no per-env
+/// dispatch or allocation. Terminated instances are reset in the
+/// same step, as in `DummyVectorEnv`, so the observation reported with
+/// `done` is the first of the next episode. Each instance has its own RNG:
+/// `VectorEnv::seed` with `s` seeds instance `i` with `s + i`, replaying a
+/// `DummyVectorEnv<CartPole>` seeded the same way.
+pub struct BatchedCartPole {
+    x: Vec<f64>,
+    x_dot: Vec<f64>,
+    theta: Vec<f64>,
+    theta_dot: Vec<f64>,
+    force: Vec<f64>,
+    sin_theta: Vec<f64>,
+    cos_theta: Vec<f64>,
+    x_acc: Vec<f64>,
+    theta_acc: Vec<f64>,
+    done: Vec<bool>,
+    params: CartPoleParams,
+    integrator: Integrator,
+    rngs: Vec<ChaCha8Rng>,
+    phase: Phase,
+}
+
+impl BatchedCartPole {
+    pub fn new(n: usize) -> Self {
+        Self {
+            x: vec![0.0; n],
+            x_dot: vec![0.0; n],
+            theta: vec![0.0; n],
+            theta_dot: vec![0.0; n],
+            force: vec![0.0; n],
+            sin_theta: vec![0.0; n],
+            cos_theta: vec![0.0; n],
+            x_acc: vec![0.0; n],
+            theta_acc: vec![0.0; n],
+            done: vec![false; n],
+            params: CartPoleParams::default(),
+            integrator: Integrator::default(),
+            rngs: (0..n).map(|_| ChaCha8Rng::from_entropy()).collect(),
+            phase: Phase::NeedsReset,
+        }
+    }
+
+    pub fn with_integrator(mut self, integrator: Integrator) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
+    /// Parameters shared by every instance.
+    pub fn with_params(mut self, params: CartPoleParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Observation of instance `i`.
+    pub fn observation(&self, i: usize) -> [f64; 4] {
+        [self.x[i], self.x_dot[i], self.theta[i], self.theta_dot[i]]
+    }
+
+    /// Write every observation into `out`, row-major with 4 values per
+    /// instance, without allocating.
+    pub fn write_observations(&self, out: &mut [f64]) -> Result<()> {
+        if out.len() != 4 * self.len() {
+            return Err(HabaError::Shape(format!(
+                "expected an output of length {}, got {}",
+                4 * self.len(),
+                out.len()
+            )));
+        }
+        for (i, row) in out.chunks_exact_mut(4).enumerate() {
+            row.copy_from_slice(&self.observation(i));
+        }
+        Ok(())
+    }
+
+    /// Which instances terminated, and were reset, in the last step.
+    pub fn dones(&self) -> &[bool] {
+        &self.done
+    }
+
+    /// Step every instance without allocating and return the done mask.
+    /// Every step earns each instance a reward of 1.
+    pub fn step_batch(&mut self, actions: &[usize]) -> Result<&[bool]> {
+        self.phase.check_step("BatchedCartPole")?;
+        if actions.len() != self.len() {
+            return Err(HabaError::Shape(format!(
+                "got {} actions for {} envs",
+                actions.len(),
+                self.len()
+            )));
+        }
+        if let Some(&action) = actions.iter().find(|&&a| a > 1) {
+            return Err(HabaError::Env(format!(
+                "invalid CartPole action {}",
+                action
+            )));
+        }
+
+        let p = &self.params;
+        for (force, &action) in self.force.iter_mut().zip(actions) {
+            *force = (2.0 * action as f64 - 1.0) * p.force_mag;
+        }
+        for ((s, c), &theta) in self
+            .sin_theta
+            .iter_mut()
+            .zip(&mut self.cos_theta)
+            .zip(&self.theta)
+        {
+            (*s, *c) = (theta.sin(), theta.cos());
+        }
+        let inputs = self
+            .force
+            .iter()
+            .zip(&self.sin_theta)
+            .zip(&self.cos_theta)
+            .zip(&self.theta_dot);
+        let outputs = self.x_acc.iter_mut().zip(&mut self.theta_acc);
+        for ((((&f, &s), &c), &theta_dot), (x_acc, theta_acc)) in inputs.zip(outputs) {
+            (*x_acc, *theta_acc) = CartPole::accelerations(p, f, s, c, theta_dot);
+        }
+
+        let (integrator, tau) = (self.integrator, p.tau);
+        let cart = self.x.iter_mut().zip(&mut self.x_dot).zip(&self.x_acc);
+        for ((x, x_dot), &acc) in cart {
+            integrator.update(tau, x, x_dot, acc);
+        }
+        let pole = self
+            .theta
+            .iter_mut()
+            .zip(&mut self.theta_dot)
+            .zip(&self.theta_acc);
+        for ((theta, theta_dot), &acc) in pole {
+            integrator.update(tau, theta, theta_dot, acc);
+        }
+
+        let positions = self.x.iter().zip(&self.theta);
+        for (done, (&x, &theta)) in self.done.iter_mut().zip(positions) {
+            *done = CartPole::terminated(x, theta);
+        }
+
+        // Auto-reset by mask
+        for (i, &done) in self.done.iter().enumerate() {
+            if done {
+                let s = CartPole::initial_state(&mut self.rngs[i]);
+                (self.x[i], self.x_dot[i], self.theta[i], self.theta_dot[i]) =
+                    (s.x, s.x_dot, s.theta, s.theta_dot);
+            }
+        }
+        Ok(&self.done)
+    }
+}
+
+impl VectorEnv for BatchedCartPole {
+    type Observation = Vec<f64>;
+    type Action = usize;
+
+    fn step(&mut self, actions: &[Self::Action]) -> Result<Vec<Step<Self::Observation>>> {
+        self.step_batch(actions)?;
+        Ok((0..self.len())
+            .map(|i| Step {
+                obs: self.observation(i).to_vec(),
+                done: self.done[i],
+                reward: 1.0,
+                info: None,
+            })
+            .collect())
+    }
+
+    fn reset(&mut self) -> Result<Vec<Self::Observation>> {
+        for i in 0..self.len() {
+            let s = CartPole::initial_state(&mut self.rngs[i]);
+            (self.x[i], self.x_dot[i], self.theta[i], self.theta_dot[i]) =
+                (s.x, s.x_dot, s.theta, s.theta_dot);
+        }
+        self.done.fill(false);
+        self.phase = Phase::Running;
+        Ok((0..self.len())
+            .map(|i| self.observation(i).to_vec())
+            .collect())
+    }
+
+    fn len(&self) -> usize {
+        self.x.len()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        for (i, rng) in self.rngs.iter_mut().enumerate() {
+            *rng = ChaCha8Rng::seed_from_u64(seed.wrapping_add(i as u64));
+        }
+    }
+
+    fn state_dict(&self) -> Result<Value> {
+        Ok(serde_json::json!({
+            "x": self.x,
+            "x_dot": self.x_dot,
+            "theta": self.theta,
+            "theta_dot": self.theta_dot,
+            "params": self.params,
+            "phase": self.phase,
+            "rngs": self.rngs,
+        }))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> Result<()> {
+        let x: Vec<f64> = checkpoint::field(state, "x")?;
+        let x_dot: Vec<f64> = checkpoint::field(state, "x_dot")?;
+        let theta: Vec<f64> = checkpoint::field(state, "theta")?;
+        let theta_dot: Vec<f64> = checkpoint::field(state, "theta_dot")?;
+        let rngs: Vec<ChaCha8Rng> = checkpoint::field(state, "rngs")?;
+        let n = self.len();
+        if [
+            x.len(),
+            x_dot.len(),
+            theta.len(),
+            theta_dot.len(),
+            rngs.len(),
+        ] != [n; 5]
+        {
+            return Err(HabaError::Checkpoint(format!(
+                "expected state for {} envs",
+                n
+            )));
+        }
+        self.params = checkpoint::field_or_default(state, "params")?;
+        self.phase = checkpoint::field(state, "phase")?;
+        (self.x, self.x_dot, self.theta, self.theta_dot, self.rngs) =
+            (x, x_dot, theta, theta_dot, rngs);
+        self.done.fill(false);
+        Ok(())
+    }
+}
+
+/// CartPole that only reports cart position and pole angle. The velocities
+/// have to be inferred from history, so memoryless policies do poorly.
+#[derive(Default)]
+pub struct MaskedCartPole {
+    env: CartPole,
+}
+
+impl MaskedCartPole {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn mask(obs: Vec<f64>) -> Vec<f64> {
+        vec![obs[0], obs[2]]
+    }
+}
+
+impl Environment for MaskedCartPole {
+    type Observation = Vec<f64>;
+    type Action = usize;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        Ok(Self::mask(self.env.reset()?))
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        let step = self.env.step(action)?;
+        Ok(Step {
+            obs: Self::mask(step.obs),
+            ..step
+        })
+    }
+
+    fn observation_space(&self) -> Space {
+        match self.env.observation_space() {
+            Space::Box { low, high, .. } => Space::bounded(Self::mask(low), Self::mask(high))
+                .expect("both bounds are masked the same way"),
+            space => space,
+        }
+    }
+
+    fn action_space(&self) -> Space {
+        self.env.action_space()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        self.env.state_dict()
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.env.load_state_dict(state)
+    }
+}
+
+/// CartPole observed through rendered frames: the last four grayscale frames
+/// of `PixelCartPole::HEIGHT` x `PixelCartPole::WIDTH`, flattened channel-first.
+/// Each action is repeated for four physics steps, as the pole's motion
+/// within fewer is mostly below one pixel. The frame is taller than wide
+/// because the pole is drawn in proportion to the height, so its angular
+/// velocity shows up as larger differences between frames.
+pub struct PixelCartPole {
+    env: CartPole,
+    preprocessor: Preprocessor,
+}
+
+impl PixelCartPole {
+    pub const HEIGHT: usize = 80;
+    pub const WIDTH: usize = 40;
+    const N_FRAMES: usize = 4;
+    const FRAME_SKIP: usize = 4;
+    // Frames are rendered at twice the observation size, then downsampled
+    const RENDER_SCALE: usize = 2;
+
+    pub fn new() -> Self {
+        Self {
+            env: CartPole::new(),
+            preprocessor: Preprocessor::new(3, Self::HEIGHT, Self::WIDTH, Self::N_FRAMES)
+                .grayscale(true),
+        }
+    }
+
+    /// (channels, height, width) of an observation.
+    pub fn obs_shape(&self) -> (usize, usize, usize) {
+        self.preprocessor.output_shape()
+    }
+
+    fn frame(&self) -> Image {
+        self.env.render(
+            Self::HEIGHT * Self::RENDER_SCALE,
+            Self::WIDTH * Self::RENDER_SCALE,
+        )
+    }
+}
+
+impl Default for PixelCartPole {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Environment for PixelCartPole {
+    type Observation = Vec<f64>;
+    type Action = usize;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        self.env.reset()?;
+        self.preprocessor.reset(&self.frame())
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        let mut reward = 0.0;
+        let mut step = self.env.step(action)?;
+        reward += step.reward;
+        for _ in 1..Self::FRAME_SKIP {
+            if step.done {
+                break;
+            }
+            step = self.env.step(action)?;
+            reward += step.reward;
+        }
+        Ok(Step {
+            obs: self.preprocessor.step(&self.frame())?,
+            done: step.done,
+            reward,
+            info: step.info,
+        })
+    }
+
+    fn observation_space(&self) -> Space {
+        let (c, h, w) = self.obs_shape();
+        Space::uniform(&[c, h, w], 0.0, 1.0)
+    }
+
+    fn action_space(&self) -> Space {
+        self.env.action_space()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        Ok(serde_json::json!({
+            "env": self.env.state_dict()?,
+            "preprocessor": self.preprocessor,
+        }))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        let preprocessor = checkpoint::field(state, "preprocessor")?;
+        self.env
+            .load_state_dict(&checkpoint::field::<Value>(state, "env")?)?;
+        self.preprocessor = preprocessor;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: [f64; 4] = [0.01, -0.02, 0.03, 0.04];
+    const ACTIONS: [usize; 8] = [1, 1, 0, 1, 0, 0, 1, 1];
+
+    fn started(integrator: Integrator) -> CartPole {
+        let mut env = CartPole::new().with_integrator(integrator);
+        env.reset().unwrap();
+        let [x, x_dot, theta, theta_dot] = START;
+        env.core.state = CartPoleState {
+            x,
+            x_dot,
+            theta,
+            theta_dot,
+        };
+        env
+    }
+
+    #[test]
+    fn test_cartpole_matches_reference() {
+        // Gymnasium's CartPole-v1 update equations evaluated in float64
+        let euler = [
+            [
+                0.009600000000000001,
+                0.17467919574755525,
+                0.030799999999999998,
+                -0.2430687179600081,
+            ],
+            [
+                0.013093583914951107,
+                0.3693479760579556,
+                0.025938625640799837,
+                -0.5258796280501483,
+            ],
+            [
+                0.02048054343611022,
+                0.17387081260481596,
+                0.01542103307979687,
+                -0.22513741609237464,
+            ],
+            [
+                0.02395795968820654,
+                0.3687690101747617,
+                0.010918284757949377,
+                -0.5129163478026587,
+            ],
+            [
+                0.03133333989170178,
+                0.17349499275068458,
+                0.0006599578018962032,
+                -0.21681286835696195,
+            ],
+            [
+                0.03480323974671547,
+                -0.021636386741578473,
+                -0.0036762995652430356,
+                0.07607816471983775,
+            ],
+            [
+                0.034370512011883896,
+                0.17353807374944197,
+                -0.0021547362708462803,
+                -0.21776237729324466,
+            ],
+            [
+                0.03784127348687273,
+                0.3686907609605089,
+                -0.006509983816711174,
+                -0.5111242205294209,
+            ],
+        ];
+        let semi_implicit = [
+            [
+                0.013493583914951104,
+                0.17467919574755525,
+                0.025138625640799838,
+                -0.2430687179600081,
+            ],
+            [
+                0.020882248303859874,
+                0.3694332194454384,
+                0.014584276147601005,
+                -0.5277174746599417,
+            ],
+            [
+                0.024364431104698884,
+                0.17410914004195044,
+                0.009974778366553383,
+                -0.23047488905238106,
+            ],
+            [
+                0.03174617401095214,
+                0.36908714531266257,
+                -0.0004251178572624338,
+                -0.5199948111907908,
+            ],
+            [
+                0.035225597637729394,
+                0.1739711813388629,
+                -0.0049740353837252164,
+                -0.22744587632313912,
+            ],
+            [
+                0.03480401095677077,
+                -0.021079334047931053,
+                -0.0037007570421438325,
+                0.06366391707906921,
+            ],
+            [
+                0.038285920571529124,
+                0.17409548073791764,
+                -0.008304443450370333,
+                -0.23018432041132503,
+            ],
+            [
+                0.04567262276951425,
+                0.3693351098992561,
+                -0.018813946333890615,
+                -0.5254751441760142,
+            ],
+        ];
+        for (integrator, expected) in [
+            (Integrator::Euler, euler),
+            (Integrator::SemiImplicitEuler, semi_implicit),
+        ] {
+            let mut env = started(integrator);
+            for (&action, expected) in ACTIONS.iter().zip(&expected) {
+                let step = env.step(action).unwrap();
+                assert!(!step.done);
+                for (got, want) in step.obs.iter().zip(expected) {
+                    assert!(
+                        (got - want).abs() < 1e-12,
+                        "{:?}: {} != {}",
+                        integrator,
+                        got,
+                        want
+                    );
+                }
+            }
+        }
+
+        // Always pushing right topples the pole after 10 (Euler) or 9 steps
+        for (integrator, length) in [(Integrator::Euler, 10), (Integrator::SemiImplicitEuler, 9)] {
+            let mut env = started(integrator);
+            let dones: Vec<bool> = (0..length).map(|_| env.step(1).unwrap().done).collect();
+            assert_eq!(dones.iter().position(|&d| d), Some(length - 1));
+            assert!(env.step(1).is_err());
+        }
+    }
+
+    #[test]
+    fn test_cartpole_reset_and_actions() {
+        let mut env = CartPole::new();
+        assert!(env.step(0).is_err());
+        env.seed(7);
+        let first = env.reset().unwrap();
+        assert!(first.iter().all(|v| v.abs() < 0.05));
+        assert_ne!(first, env.reset().unwrap());
+        env.seed(7);
+        assert_eq!(env.reset().unwrap(), first);
+        assert!(env.step(2).is_err());
+
+        let text = env.render_text();
+        assert_eq!(text.lines().count(), 3);
+        assert_eq!(text.lines().nth(1).unwrap().matches('#').count(), 1);
+    }
+
+    #[test]
+    fn test_cartpole_state_keeps_params() {
+        let mut params = CartPoleParams::default();
+        params.set("gravity", 5.0).unwrap();
+        let mut env = CartPole::new().with_params(params);
+        env.reset().unwrap();
+        let mut state = env.state_dict().unwrap();
+        let mut restored = CartPole::new();
+        restored.load_state_dict(&state).unwrap();
+        assert_eq!(restored.params().gravity, 5.0);
+
+        // Checkpoints from before the parameters were stored get the defaults
+        state.as_object_mut().unwrap().remove("params");
+        restored.load_state_dict(&state).unwrap();
+        assert_eq!(restored.params().gravity, 9.8);
+
+        let mut batched = BatchedCartPole::new(2).with_params(params);
+        batched.reset().unwrap();
+        let state = batched.state_dict().unwrap();
+        let mut restored = BatchedCartPole::new(2);
+        restored.load_state_dict(&state).unwrap();
+        assert_eq!(restored.state_dict().unwrap(), state);
+    }
+
+    #[test]
+    fn test_masked_cartpole_hides_velocities() {
+        let mut env = MaskedCartPole::new();
+        let obs = env.reset().unwrap();
+        assert_eq!(obs, vec![env.env.state().x, env.env.state().theta]);
+        let obs = env.step(1).unwrap().obs;
+        let state = env.env.state();
+        assert_eq!(obs, vec![state.x, state.theta]);
+    }
+
+    #[test]
+    fn test_pixel_cartpole_observations() {
+        let mut env = PixelCartPole::new();
+        let (c, h, w) = env.obs_shape();
+        assert_eq!((c, h, w), (4, PixelCartPole::HEIGHT, PixelCartPole::WIDTH));
+
+        let obs = env.reset().unwrap();
+        assert_eq!(obs.len(), c * h * w);
+        assert!(obs.iter().all(|&v| (0.0..=1.0).contains(&v)));
+        // All stacked frames start identical, and the cart is visible
+        let newest = (c - 1) * h * w;
+        assert_eq!(obs[..h * w], obs[newest..]);
+        assert!(obs.iter().any(|&v| v > 0.5));
+
+        // Pushing moves the picture, so the newest frame differs from the oldest
+        let mut obs = obs;
+        for action in [1, 0, 1] {
+            let step = env.step(action).unwrap();
+            assert_eq!(step.reward, 4.0);
+            obs = step.obs;
+        }
+        assert_ne!(obs[..h * w], obs[newest..]);
+    }
+
+    #[test]
+    fn test_batched_cartpole_matches_dummy_vector_env() {
+        use crate::venv::DummyVectorEnv;
+
+        let n = 5;
+        let mut batched = BatchedCartPole::new(n);
+        batched.seed(7);
+        let mut dummy = DummyVectorEnv::new(
+            (0..n)
+                .map(|i| {
+                    let mut env = CartPole::new();
+                    env.seed(7 + i as u64);
+                    env
+                })
+                .collect(),
+        );
+        assert!(batched.step(&[0; 5]).is_err());
+        assert_eq!(batched.reset().unwrap(), dummy.reset().unwrap());
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut resets = 0;
+        for t in 0..200 {
+            let actions: Vec<usize> = (0..n).map(|_| rng.gen_range(0..2)).collect();
+            if t == 100 {
+                // Continue from a checkpoint
+                let state = batched.state_dict().unwrap();
+                batched = BatchedCartPole::new(n);
+                batched.load_state_dict(&state).unwrap();
+            }
+            let steps = batched.step(&actions).unwrap();
+            let expected = dummy.step(&actions).unwrap();
+            for (step, expected) in steps.iter().zip(&expected) {
+                assert_eq!(step.obs, expected.obs);
+                assert_eq!(step.done, expected.done);
+                resets += usize::from(step.done);
+            }
+        }
+        assert!(resets > 0);
+
+        let mut out = vec![0.0; 4 * n];
+        batched.write_observations(&mut out).unwrap();
+        assert_eq!(out[4..8], batched.observation(1));
+        assert!(batched.step_batch(&[0, 1, 2, 0, 1]).is_err());
+        assert!(batched.step_batch(&[0]).is_err());
+    }
 }
@@ -0,0 +1,97 @@
+use crate::error::{HabaError, Result};
+use candle_nn::VarMap;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+const EPOCH_PREFIX: &str = "epoch_";
+
+pub fn unsupported(what: &str) -> HabaError {
+    HabaError::Checkpoint(format!("{} does not support checkpointing", what))
+}
+
+/// Deserialize entry `key` of a `state_dict`.
+pub fn field<T: DeserializeOwned>(state: &Value, key: &str) -> Result<T> {
+    let value = state
+        .get(key)
+        .ok_or_else(|| HabaError::Checkpoint(format!("state is missing {:?}", key)))?;
+    serde_json::from_value(value.clone())
+        .map_err(|e| HabaError::Checkpoint(format!("invalid {:?}: {}", key, e)))
+}
+
+/// Like `field`, but a missing entry, e.g. one older checkpoints lack,
+/// gives `T::default()`.
+pub fn field_or_default<T: DeserializeOwned + Default>(state: &Value, key: &str) -> Result<T> {
+    match state.get(key) {
+        Some(_) => field(state, key),
+        None => Ok(T::default()),
+    }
+}
+
+/// Load safetensors weights into every variable of `varmap`, converting
+/// them to each variable's dtype and device.
+pub fn load_varmap(varmap: &VarMap, path: &Path) -> Result<()> {
+    let data = varmap.data().lock().unwrap();
+    let Some(device) = data.values().next().map(|var| var.device().clone()) else {
+        return Ok(());
+    };
+    let tensors = candle_core::safetensors::load(path, &device)?;
+    for (name, var) in data.iter() {
+        let tensor = tensors.get(name).ok_or_else(|| {
+            HabaError::Checkpoint(format!("{} is missing {}", path.display(), name))
+        })?;
+        var.set(&tensor.to_dtype(var.dtype())?)?;
+    }
+    Ok(())
+}
+
+/// Directory name of the checkpoint taken after `epoch`.
+pub fn epoch_dir(root: &Path, epoch: usize) -> PathBuf {
+    root.join(format!("{}{:06}", EPOCH_PREFIX, epoch))
+}
+
+/// Completed epoch checkpoints under `root`, oldest first.
+pub fn list_epochs(root: &Path) -> Result<Vec<(usize, PathBuf)>> {
+    let mut found = Vec::new();
+    if !root.exists() {
+        return Ok(found);
+    }
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        // Unfinished writes keep a `.tmp` suffix and fail to parse here
+        if let Some(Ok(epoch)) = name.strip_prefix(EPOCH_PREFIX).map(str::parse::<usize>) {
+            found.push((epoch, path));
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// Delete all but the newest `keep` epoch checkpoints under `root`.
+pub fn prune(root: &Path, keep: usize) -> Result<()> {
+    let epochs = list_epochs(root)?;
+    let excess = epochs.len().saturating_sub(keep);
+    for (_, path) in epochs.into_iter().take(excess) {
+        fs::remove_dir_all(path)?;
+    }
+    Ok(())
+}
+
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| {
+        HabaError::Checkpoint(format!("cannot serialize {}: {}", path.display(), e))
+    })?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let json = fs::read_to_string(path)?;
+    serde_json::from_str(&json)
+        .map_err(|e| HabaError::Checkpoint(format!("cannot parse {}: {}", path.display(), e)))
+}
@@ -1,7 +1,143 @@
+use crate::batch::PolicyExtra;
 use crate::buffer::ReplayBuffer;
-use crate::policy::Policy;
+use crate::checkpoint;
+use crate::env::Info;
+use crate::error::{HabaError, Result};
+use crate::policy::{HiddenState, Policy, PolicyMode};
+use crate::stats::{CollectStats, LearnStats};
 use crate::venv::VectorEnv;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use std::path::Path;
+use std::time::Instant;
+
+/// How much a call to `Collector::collect` gathers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollectSpec {
+    /// Exactly `n` transitions. Every vector step advances all envs, so on
+    /// the last one the transitions past `n` are held back and recorded at
+    /// the start of the next collection.
+    Steps(usize),
+    /// Exactly `n` completed episodes, split as evenly as possible across
+    /// envs: env `i` contributes its first `n / len` episodes, plus one if
+    /// `i < n % len`. Envs that met their share keep stepping until the
+    /// others catch up, but their extra episodes are not reported, so
+    /// short episodes are not over-represented.
+    Episodes(usize),
+}
+
+// Completed episodes of one collection, with per-env quotas in episode mode
+struct EpisodeTally {
+    quotas: Option<Vec<usize>>,
+    counted: Vec<usize>,
+    returns: Vec<f64>,
+    lens: Vec<usize>,
+    env_ids: Vec<usize>,
+}
+
+impl EpisodeTally {
+    fn new(n_envs: usize, spec: CollectSpec) -> Result<Self> {
+        let quotas = match spec {
+            CollectSpec::Steps(_) => None,
+            CollectSpec::Episodes(n) => {
+                if n > 0 && n_envs == 0 {
+                    return Err(HabaError::Config(
+                        "cannot collect episodes from an empty vector env".to_string(),
+                    ));
+                }
+                let quota = |i| n / n_envs + usize::from(i < n % n_envs);
+                Some((0..n_envs).map(quota).collect())
+            }
+        };
+        Ok(Self {
+            quotas,
+            counted: vec![0; n_envs],
+            returns: Vec::new(),
+            lens: Vec::new(),
+            env_ids: Vec::new(),
+        })
+    }
+
+    fn record(&mut self, env_id: usize, episode_return: f64, len: usize) {
+        if let Some(quotas) = &self.quotas
+            && self.counted[env_id] >= quotas[env_id]
+        {
+            return;
+        }
+        self.counted[env_id] += 1;
+        self.returns.push(episode_return);
+        self.lens.push(len);
+        self.env_ids.push(env_id);
+    }
+
+    fn is_done(&self, spec: CollectSpec, n_steps: usize) -> bool {
+        match spec {
+            CollectSpec::Steps(n) => n_steps >= n,
+            CollectSpec::Episodes(n) => self.returns.len() >= n,
+        }
+    }
+
+    // Transitions that may still be recorded after `n_steps`
+    fn step_quota(spec: CollectSpec, n_steps: usize) -> usize {
+        match spec {
+            CollectSpec::Steps(n) => n.saturating_sub(n_steps),
+            CollectSpec::Episodes(_) => usize::MAX,
+        }
+    }
+
+    fn into_stats(self, n_steps: usize, start: Instant) -> CollectStats {
+        CollectStats::new(
+            self.returns,
+            self.lens,
+            self.env_ids,
+            n_steps,
+            start.elapsed(),
+        )
+    }
+}
+
+const COLLECTOR_STATE: &str = "collector.json";
+const BUFFER_STATE: &str = "buffer.json";
+const POLICY_DIR: &str = "policy";
+
+// A transition waiting for the action taken at its `obs_next`
+#[derive(Clone, Serialize, Deserialize)]
+struct PendingTransition<O, A> {
+    obs: O,
+    act: A,
+    rew: f64,
+    obs_next: O,
+    #[serde(default)]
+    info: Option<Info>,
+    extra: PolicyExtra,
+}
+
+// A transition stepped past the end of a `CollectSpec::Steps` collection,
+// recorded by the next one
+#[derive(Clone, Serialize, Deserialize)]
+struct HeldStep<O, A> {
+    env_id: usize,
+    done: bool,
+    transition: PendingTransition<O, A>,
+}
+
+// In-flight episode bookkeeping, so a resumed run continues mid-episode
+#[derive(Serialize, Deserialize)]
+struct CollectorState<O, A> {
+    current_obs: Vec<O>,
+    episode_returns: Vec<f64>,
+    episode_lens: Vec<usize>,
+    env_steps: usize,
+    state: Option<HiddenState>,
+    #[serde(default = "Vec::new")]
+    pending: Vec<Option<PendingTransition<O, A>>>,
+    #[serde(default = "Vec::new")]
+    held: Vec<HeldStep<O, A>>,
+    env: serde_json::Value,
+}
 
 pub struct Collector<V: VectorEnv, P: Policy> {
     env: V,
@@ -9,6 +145,15 @@ pub struct Collector<V: VectorEnv, P: Policy> {
     buffer: Option<ReplayBuffer<V::Observation, V::Action>>,
     current_obs: Vec<V::Observation>,
     episode_returns: Vec<f64>,
+    episode_lens: Vec<usize>,
+    env_steps: usize,
+    deterministic: bool,
+    reset_before_collect: bool,
+    // Hidden state returned by the policy on the previous step, fed back on the next
+    state: Option<HiddenState>,
+    // Per env, the last transition of a policy that needs the next action
+    pending: Vec<Option<PendingTransition<V::Observation, V::Action>>>,
+    held: Vec<HeldStep<V::Observation, V::Action>>,
 }
 
 impl<V, P> Collector<V, P>
@@ -22,79 +167,342 @@ where
         mut env: V,
         policy: P,
         buffer: Option<ReplayBuffer<V::Observation, V::Action>>,
-    ) -> Self {
+    ) -> Result<Self> {
         // Initial reset to get first observations
-        let current_obs = env.reset().expect("Failed to reset env");
+        let current_obs = env.reset()?;
         let len = env.len();
 
-        Collector {
+        Ok(Collector {
             env,
             policy,
             buffer,
             current_obs,
             episode_returns: vec![0.0; len],
+            episode_lens: vec![0; len],
+            env_steps: 0,
+            deterministic: false,
+            reset_before_collect: false,
+            state: None,
+            pending: vec![None; len],
+            held: Vec::new(),
+        })
+    }
+
+    /// When set, `collect` runs the policy in eval mode (no exploration).
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    /// When set, `collect` calls `reset_envs` first, so that every reported
+    /// episode starts from a reset, e.g. for clean evaluation.
+    pub fn set_reset_before_collect(&mut self, reset: bool) {
+        self.reset_before_collect = reset;
+    }
+
+    /// Reset every env and forget in-flight episodes, including on-policy
+    /// transitions still waiting for their next action and transitions held
+    /// back by `CollectSpec::Steps`.
+    pub fn reset_envs(&mut self) -> Result<()> {
+        self.current_obs = self.env.reset()?;
+        self.episode_returns.fill(0.0);
+        self.episode_lens.fill(0);
+        self.state = None;
+        self.pending.fill(None);
+        self.held.clear();
+        Ok(())
+    }
+
+    pub fn collect(&mut self, spec: CollectSpec) -> Result<CollectStats> {
+        if self.reset_before_collect {
+            self.reset_envs()?;
         }
+        let prev_mode = self.policy.mode();
+        if self.deterministic {
+            self.policy.set_mode(PolicyMode::Eval);
+        }
+        let stats = self.collect_steps(spec);
+        self.policy.set_mode(prev_mode);
+        stats
     }
 
-    pub fn collect(&mut self, n_steps: usize) -> Vec<f64> {
+    fn collect_steps(&mut self, spec: CollectSpec) -> Result<CollectStats> {
+        let start = Instant::now();
         let mut steps_collected = 0;
-        let mut completed_rewards = Vec::new();
+        let mut tally = EpisodeTally::new(self.env.len(), spec)?;
+        let on_policy = self.policy.needs_next_action();
 
-        while steps_collected < n_steps {
+        // Transitions held back by the previous collection come first
+        for held in std::mem::take(&mut self.held) {
+            if EpisodeTally::step_quota(spec, steps_collected) == 0 {
+                self.held.push(held);
+            } else {
+                self.record(held, on_policy, &mut tally);
+                steps_collected += 1;
+            }
+        }
+
+        while !tally.is_done(spec, steps_collected) {
             // 1. Select Actions (Batch)
-            let actions = self.policy.forward(&self.current_obs);
+            let mut output = self
+                .policy
+                .forward(&self.current_obs, self.state.as_ref())?;
+            let actions = &output.act;
+            if on_policy && let Some(buf) = &mut self.buffer {
+                for (i, pending) in self.pending.iter_mut().enumerate() {
+                    if let Some(p) = pending.take() {
+                        let act_next = Some(actions[i].clone());
+                        buf.add_transition(
+                            i, p.obs, p.act, p.rew, false, p.obs_next, act_next, p.info, p.extra,
+                        );
+                    }
+                }
+            }
 
             // 2. Step Environment (Batch)
             // Tianshou steps all envs.
-            let steps = self.env.step(&actions).expect("Failed to step env");
-
-            // 3. Add to Buffer
-            if let Some(buf) = &mut self.buffer {
-                for (i, step) in steps.iter().enumerate() {
-                    // buffer.add needs to handle batch logic or we add one by one.
-                    // ReplayBuffer is not generic over batch yet, it takes single items.
-                    // But we have a loop here.
-                    // Note: step.obs is the NEXT observation.
-                    // self.current_obs[i] is the CURRENT observation.
-
-                    buf.add(
-                        self.current_obs[i].clone(),
-                        actions[i].clone(),
-                        step.reward,
-                        step.done,
-                        step.obs.clone(),
-                    );
+            let steps = self.env.step(actions)?;
+
+            // 3. Record the transitions, holding back those past the step quota
+            let quota = EpisodeTally::step_quota(spec, steps_collected);
+            let prev_state = std::mem::replace(&mut self.state, output.state.take());
+            for (i, step) in steps.into_iter().enumerate() {
+                // Note: step.obs is the NEXT observation.
+                // self.current_obs[i] is the CURRENT observation.
+                let mut extra = output.extra(i);
+                extra.state = prev_state.as_ref().map(|s| s[i].clone());
+                let held = HeldStep {
+                    env_id: i,
+                    done: step.done,
+                    transition: PendingTransition {
+                        obs: std::mem::replace(&mut self.current_obs[i], step.obs.clone()),
+                        act: actions[i].clone(),
+                        rew: step.reward,
+                        obs_next: step.obs,
+                        info: step.info,
+                        extra,
+                    },
+                };
+                // The env has auto-reset, so its next step starts a fresh episode
+                if held.done
+                    && let Some(state) = &mut self.state
+                {
+                    self.policy.reset_state(state, i);
+                }
+                if i < quota {
+                    self.record(held, on_policy, &mut tally);
+                    steps_collected += 1;
+                } else {
+                    self.held.push(held);
                 }
             }
+        }
+        self.env_steps += steps_collected;
+        Ok(tally.into_stats(steps_collected, start))
+    }
+
+    // Add a stepped transition to the buffer and its episode's statistics
+    fn record(
+        &mut self,
+        held: HeldStep<V::Observation, V::Action>,
+        on_policy: bool,
+        tally: &mut EpisodeTally,
+    ) {
+        let HeldStep {
+            env_id: i,
+            done,
+            transition: t,
+        } = held;
+        self.episode_returns[i] += t.rew;
+        self.episode_lens[i] += 1;
+        if done {
+            tally.record(i, self.episode_returns[i], self.episode_lens[i]);
+            self.episode_returns[i] = 0.0;
+            self.episode_lens[i] = 0;
+        }
+        let Some(buf) = &mut self.buffer else {
+            return;
+        };
+        if on_policy && !done {
+            // Added once the next action is known
+            self.pending[i] = Some(t);
+        } else {
+            // Done transitions repeat `act` as the next action
+            let act_next = on_policy.then(|| t.act.clone());
+            buf.add_transition(
+                i, t.obs, t.act, t.rew, done, t.obs_next, act_next, t.info, t.extra,
+            );
+        }
+    }
+
+    pub fn get_buffer_len(&self) -> usize {
+        self.buffer.as_ref().map_or(0, |b| b.len())
+    }
 
-            // 4. Update current observations and track rewards
-            // For environments that are done, step.obs is the reset definition?
-            // In DummyVectorEnv, we implemented auto-reset returning the new start state in step.obs.
-            // So we can just update current_obs.
-            for (i, step) in steps.iter().enumerate() {
-                self.episode_returns[i] += step.reward;
+    /// Run `n_episodes` deterministic episodes on a separate env, reset
+    /// first and split across its envs as in `CollectSpec::Episodes`.
+    ///
+    /// The buffer, the step counter and the training env are left untouched.
+    pub fn evaluate<T>(&mut self, env: &mut T, n_episodes: usize) -> Result<CollectStats>
+    where
+        T: VectorEnv<Observation = V::Observation, Action = V::Action>,
+    {
+        let prev_mode = self.policy.mode();
+        self.policy.set_mode(PolicyMode::Eval);
+        let stats = self.evaluate_episodes(env, n_episodes);
+        self.policy.set_mode(prev_mode);
+        stats
+    }
+
+    fn evaluate_episodes<T>(&mut self, env: &mut T, n_episodes: usize) -> Result<CollectStats>
+    where
+        T: VectorEnv<Observation = V::Observation, Action = V::Action>,
+    {
+        let start = Instant::now();
+        let spec = CollectSpec::Episodes(n_episodes);
+        let mut tally = EpisodeTally::new(env.len(), spec)?;
+        let mut obs = env.reset()?;
+        let mut returns = vec![0.0; env.len()];
+        let mut lens = vec![0; env.len()];
+        let mut n_steps = 0;
+        let mut state = None;
+
+        while !tally.is_done(spec, n_steps) {
+            let output = self.policy.forward(&obs, state.as_ref())?;
+            state = output.state;
+            let steps = env.step(&output.act)?;
+            n_steps += steps.len();
+            for (i, step) in steps.into_iter().enumerate() {
+                returns[i] += step.reward;
+                lens[i] += 1;
                 if step.done {
-                    completed_rewards.push(self.episode_returns[i]);
-                    self.episode_returns[i] = 0.0;
+                    if let Some(state) = &mut state {
+                        self.policy.reset_state(state, i);
+                    }
+                    tally.record(i, returns[i], lens[i]);
+                    returns[i] = 0.0;
+                    lens[i] = 0;
                 }
-                self.current_obs[i] = step.obs.clone();
+                obs[i] = step.obs;
             }
+        }
+
+        Ok(tally.into_stats(n_steps, start))
+    }
+
+    /// Total number of environment transitions collected so far.
+    pub fn env_steps(&self) -> usize {
+        self.env_steps
+    }
+
+    pub fn env(&self) -> &V {
+        &self.env
+    }
+
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+
+    pub fn policy_mut(&mut self) -> &mut P {
+        &mut self.policy
+    }
 
-            steps_collected += self.env.len(); // We collected N transitions
+    /// Seed the policy, the envs and the replay buffer sampling, each from
+    /// its own seed derived from `seed`, then reset the envs so the current
+    /// episodes start from seeded states.
+    pub fn seed(&mut self, seed: u64) -> Result<()> {
+        let mut seeds = ChaCha8Rng::seed_from_u64(seed);
+        self.policy.seed(seeds.next_u64());
+        self.env.seed(seeds.next_u64());
+        if let Some(buf) = self.buffer.as_mut() {
+            buf.seed(seeds.next_u64());
         }
-        completed_rewards
+        self.reset_envs()
     }
 
-    pub fn get_buffer_len(&self) -> usize {
-        self.buffer.as_ref().map_or(0, |b| b.len())
+    /// Learn from every buffered transition once, oldest first, then empty
+    /// the buffer. For online learners such as bandit agents, which must see
+    /// each transition exactly once. Returns `None` if the buffer is empty.
+    pub fn learn_on_buffer(&mut self) -> Result<Option<LearnStats>> {
+        let Some(buf) = self.buffer.as_mut().filter(|b| !b.is_empty()) else {
+            return Ok(None);
+        };
+        let batch = buf.drain();
+        Ok(Some(self.policy.learn(&batch)?))
     }
 
-    pub fn train_step(&mut self, batch_size: usize) {
+    /// Run one gradient update. Returns `None` if the buffer does not hold a full batch yet.
+    /// On-policy policies learn from the whole drained buffer instead, as in
+    /// `learn_on_buffer`, so they never see replayed data.
+    pub fn train_step(&mut self, batch_size: usize) -> Result<Option<LearnStats>> {
+        if self.policy.on_policy() {
+            return self.learn_on_buffer();
+        }
+        let Some(buf) = &mut self.buffer else {
+            return Ok(None);
+        };
+        // Recurrent policies train on runs of consecutive transitions
+        let batch = match self.policy.sequence_len() {
+            Some(seq_len) => buf.sample_sequences(batch_size, seq_len),
+            None => (buf.len() >= batch_size).then(|| buf.sample(batch_size)),
+        };
+        match batch {
+            Some(batch) => Ok(Some(self.policy.learn(&batch)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<V, P> Collector<V, P>
+where
+    V: VectorEnv,
+    P: Policy<Observation = V::Observation, Action = V::Action>,
+    V::Observation: Clone + Debug + Serialize + DeserializeOwned,
+    V::Action: Clone + Debug + Serialize + DeserializeOwned,
+{
+    /// Write the env state, in-flight episodes, replay buffer and policy
+    /// training state into `dir`.
+    pub fn save_state(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)?;
+        let state = CollectorState {
+            current_obs: self.current_obs.clone(),
+            episode_returns: self.episode_returns.clone(),
+            episode_lens: self.episode_lens.clone(),
+            env_steps: self.env_steps,
+            state: self.state.clone(),
+            pending: self.pending.clone(),
+            held: self.held.clone(),
+            env: self.env.state_dict()?,
+        };
+        checkpoint::write_json(&dir.join(COLLECTOR_STATE), &state)?;
         if let Some(buf) = &self.buffer {
-            if buf.len() >= batch_size {
-                self.policy.learn(&buf.sample(batch_size));
-            }
+            checkpoint::write_json(&dir.join(BUFFER_STATE), buf)?;
         }
+        self.policy.save_training_state(&dir.join(POLICY_DIR))
+    }
+
+    pub fn load_state(&mut self, dir: &Path) -> Result<()> {
+        let state: CollectorState<V::Observation, V::Action> =
+            checkpoint::read_json(&dir.join(COLLECTOR_STATE))?;
+        if state.current_obs.len() != self.env.len() {
+            return Err(HabaError::Checkpoint(format!(
+                "checkpoint has {} envs, collector has {}",
+                state.current_obs.len(),
+                self.env.len()
+            )));
+        }
+        self.env.load_state_dict(&state.env)?;
+        self.current_obs = state.current_obs;
+        self.episode_returns = state.episode_returns;
+        self.episode_lens = state.episode_lens;
+        self.env_steps = state.env_steps;
+        self.state = state.state;
+        self.pending = state.pending;
+        self.pending.resize(self.env.len(), None);
+        self.held = state.held;
+        if self.buffer.is_some() {
+            self.buffer = Some(checkpoint::read_json(&dir.join(BUFFER_STATE))?);
+        }
+        self.policy.load_training_state(&dir.join(POLICY_DIR))
     }
 }
 
@@ -103,6 +511,8 @@ mod tests {
     use super::*;
     use crate::buffer::ReplayBuffer;
     use crate::mock::MockEnv;
+    use crate::policy::PolicyOutput;
+    use crate::toy_text::GridWorld;
     use crate::venv::DummyVectorEnv;
 
     struct MockPolicy;
@@ -111,14 +521,174 @@ mod tests {
         type Observation = f64;
         type Action = ();
 
-        fn forward(&mut self, obs: &[Self::Observation]) -> Vec<Self::Action> {
+        fn forward(
+            &mut self,
+            obs: &[Self::Observation],
+            _state: Option<&HiddenState>,
+        ) -> Result<PolicyOutput<Self::Action>> {
             // Return N actions where N = batch size (obs.len())
-            vec![(); obs.len()]
+            Ok(PolicyOutput::new(vec![(); obs.len()]))
         }
 
-        fn learn(&mut self, _batch: &crate::batch::Batch<Self::Observation, Self::Action>) {
+        fn learn(
+            &mut self,
+            _batch: &crate::batch::Batch<Self::Observation, Self::Action>,
+        ) -> Result<LearnStats> {
             // No-op
+            Ok(LearnStats::new())
+        }
+    }
+
+    // Counts its own calls in the hidden state and reports the observation as log-prob
+    struct CountingPolicy;
+
+    impl crate::policy::Policy for CountingPolicy {
+        type Observation = f64;
+        type Action = ();
+
+        fn forward(
+            &mut self,
+            obs: &[Self::Observation],
+            state: Option<&HiddenState>,
+        ) -> Result<PolicyOutput<Self::Action>> {
+            let next_state = match state {
+                Some(s) => s.iter().map(|row| vec![row[0] + 1.0]).collect(),
+                None => vec![vec![1.0]; obs.len()],
+            };
+            Ok(PolicyOutput {
+                log_prob: Some(obs.iter().map(|o| -o).collect()),
+                value: Some(obs.to_vec()),
+                state: Some(next_state),
+                ..PolicyOutput::new(vec![(); obs.len()])
+            })
+        }
+
+        fn learn(
+            &mut self,
+            _batch: &crate::batch::Batch<Self::Observation, Self::Action>,
+        ) -> Result<LearnStats> {
+            Ok(LearnStats::new())
+        }
+    }
+
+    #[test]
+    fn test_collector_records_policy_output() {
+        let venv = DummyVectorEnv::new(vec![MockEnv::new(10)]);
+        let mut collector =
+            Collector::new(venv, CountingPolicy, Some(ReplayBuffer::new(100))).unwrap();
+        collector.collect(CollectSpec::Steps(3)).unwrap();
+
+        let batch = collector.buffer.as_mut().unwrap().sample(3);
+        let log_prob = batch.log_prob.expect("log_prob stored");
+        let value = batch.value.expect("value stored");
+        for i in 0..3 {
+            assert_eq!(log_prob[i], -batch.obs[i]);
+            assert_eq!(value[i], batch.obs[i]);
         }
+        // The first step has no input state, so the state column is incomplete
+        assert!(batch.state.is_none());
+        assert_eq!(collector.state, Some(vec![vec![3.0]]));
+    }
+
+    #[test]
+    fn test_collector_resets_hidden_state_on_done() {
+        // Env 0 finishes after 2 steps, env 1 after 3
+        let venv = DummyVectorEnv::new(vec![MockEnv::new(2), MockEnv::new(3)]);
+        let mut collector =
+            Collector::new(venv, CountingPolicy, Some(ReplayBuffer::new(100))).unwrap();
+        collector.collect(CollectSpec::Steps(6)).unwrap();
+
+        // Env 0 restarted its count after step 2, env 1 was just reset
+        assert_eq!(collector.state, Some(vec![vec![1.0], vec![0.0]]));
+    }
+
+    // Acts deterministically on the observation and asks for the next action
+    struct OnPolicy;
+
+    // Right along the top row of a 2x3 grid, then down
+    fn act_on(obs: usize) -> usize {
+        if obs % 3 < 2 { 2 } else { 1 }
+    }
+
+    impl crate::policy::Policy for OnPolicy {
+        type Observation = usize;
+        type Action = usize;
+
+        fn forward(
+            &mut self,
+            obs: &[Self::Observation],
+            _state: Option<&HiddenState>,
+        ) -> Result<PolicyOutput<Self::Action>> {
+            Ok(PolicyOutput::new(obs.iter().map(|&o| act_on(o)).collect()))
+        }
+
+        fn learn(
+            &mut self,
+            _batch: &crate::batch::Batch<Self::Observation, Self::Action>,
+        ) -> Result<LearnStats> {
+            Ok(LearnStats::new())
+        }
+
+        fn needs_next_action(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_collector_records_next_action() {
+        // Episodes take 3 steps
+        let map = ["S..", "..G"];
+        let venv = DummyVectorEnv::new(vec![GridWorld::from_map(&map).unwrap()]);
+        let mut collector = Collector::new(venv, OnPolicy, Some(ReplayBuffer::new(100))).unwrap();
+        collector.collect(CollectSpec::Steps(7)).unwrap();
+        // The last transition waits for the next action
+        assert_eq!(collector.get_buffer_len(), 6);
+
+        let batch = collector.buffer.as_mut().unwrap().sample(6);
+        let act_next = batch.act_next.expect("act_next stored");
+        for (i, &next) in act_next.iter().enumerate() {
+            if batch.done[i] {
+                assert_eq!(next, batch.act[i]);
+            } else {
+                assert_eq!(next, act_on(batch.obs_next[i]));
+            }
+        }
+        assert_eq!(batch.done.iter().filter(|&&d| d).count(), 2);
+    }
+
+    #[test]
+    fn test_collector_stores_typed_info() {
+        use crate::env::InfoValue;
+        use crate::wrappers::{EPISODE_LENGTH, EPISODE_RETURN, RecordEpisodeStatistics};
+
+        let envs = (0..2)
+            .map(|_| RecordEpisodeStatistics::new(MockEnv::new(3)))
+            .collect();
+        let buffer = ReplayBuffer::new(100).with_info(true);
+        let venv = DummyVectorEnv::new(envs);
+        let mut collector = Collector::new(venv, MockPolicy, Some(buffer)).unwrap();
+        collector.collect(CollectSpec::Steps(6)).unwrap();
+
+        let batch = collector.buffer.as_mut().unwrap().drain();
+        let info = batch.info.expect("info stored");
+        for (done, info) in batch.done.iter().zip(&info) {
+            if *done {
+                assert_eq!(info[EPISODE_LENGTH], InfoValue::Int(3));
+                assert!(info[EPISODE_RETURN].as_f64().is_some());
+            } else {
+                assert!(info.is_empty());
+            }
+        }
+
+        // Types survive checkpointing
+        let nested = Info::from([("cost".to_string(), 0.5.into())]);
+        let info = Info::from([
+            ("goal".to_string(), vec![1.0, 2.0].into()),
+            ("nested".to_string(), nested.into()),
+            ("success".to_string(), true.into()),
+        ]);
+        let json = serde_json::to_string(&info).unwrap();
+        assert_eq!(serde_json::from_str::<Info>(&json).unwrap(), info);
     }
 
     #[test]
@@ -134,17 +704,92 @@ mod tests {
         let buffer = ReplayBuffer::new(100);
 
         // 4. Collector
-        let mut collector = Collector::new(venv, policy, Some(buffer));
+        let mut collector = Collector::new(venv, policy, Some(buffer)).unwrap();
 
         // 5. Collect 10 steps (should fill some buffer)
         // 2 envs. 5 steps each to complete episode.
         // 10 steps total = 5 steps per env = 1 episode per env.
-        let rewards = collector.collect(10);
+        let stats = collector.collect(CollectSpec::Steps(10)).unwrap();
 
         // 6. Verify
         assert_eq!(collector.get_buffer_len(), 10);
-        assert_eq!(rewards.len(), 2); // 2 episodes finished
-        assert_eq!(rewards[0], 5.0); // Reward is 1.0 per step, 5 steps = 5.0
-        assert_eq!(rewards[1], 5.0);
+        assert_eq!(stats.n_episodes, 2); // 2 episodes finished
+        assert_eq!(stats.returns[0], 5.0); // Reward is 1.0 per step, 5 steps = 5.0
+        assert_eq!(stats.returns[1], 5.0);
+        assert_eq!(stats.lens, vec![5, 5]);
+        assert_eq!(stats.n_steps, 10);
+        assert_eq!(stats.returns_mean, 5.0);
+        assert_eq!(stats.returns_std, 0.0);
+        assert_eq!(collector.env_steps(), 10);
+    }
+
+    #[test]
+    fn test_collect_steps_is_exact() {
+        let venv = DummyVectorEnv::new((0..3).map(|_| MockEnv::new(4)).collect());
+        let mut collector = Collector::new(venv, MockPolicy, Some(ReplayBuffer::new(100))).unwrap();
+
+        // 5 is not a multiple of 3 envs: the last env's second step is held back
+        let mut episodes = 0;
+        for (n, buffered) in [(5, 5), (4, 9), (3, 12)] {
+            let stats = collector.collect(CollectSpec::Steps(n)).unwrap();
+            assert_eq!(stats.n_steps, n);
+            assert_eq!(collector.get_buffer_len(), buffered);
+            assert_eq!(collector.env_steps(), buffered);
+            episodes += stats.n_episodes;
+        }
+        // Four whole vector steps: every env finished one episode, and no
+        // transition was lost or recorded twice. Done steps report the reset
+        // observation.
+        assert_eq!(episodes, 3);
+        let batch = collector.buffer.as_mut().unwrap().drain();
+        let mut obs: Vec<_> = batch.obs.iter().zip(&batch.obs_next).collect();
+        obs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let expected: Vec<_> = (0..4)
+            .flat_map(|t| [(t as f64, ((t + 1) % 4) as f64); 3])
+            .collect();
+        assert_eq!(
+            obs.into_iter().map(|(&o, &n)| (o, n)).collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(batch.done.iter().filter(|&&d| d).count(), 3);
+    }
+
+    #[test]
+    fn test_collector_evaluate_separate_env() {
+        let venv = DummyVectorEnv::new(vec![MockEnv::new(5)]);
+        let mut collector = Collector::new(venv, MockPolicy, Some(ReplayBuffer::new(100))).unwrap();
+
+        let mut test_env = DummyVectorEnv::new(vec![MockEnv::new(3), MockEnv::new(3)]);
+        let stats = collector.evaluate(&mut test_env, 3).unwrap();
+
+        assert_eq!(stats.returns, vec![3.0, 3.0, 3.0]);
+        assert_eq!(stats.lens, vec![3, 3, 3]);
+        // Evaluation must not leak into the training data
+        assert_eq!(collector.get_buffer_len(), 0);
+        assert_eq!(collector.env_steps(), 0);
+    }
+
+    #[test]
+    fn test_collect_episodes_splits_across_envs() {
+        let venv = DummyVectorEnv::new(vec![MockEnv::new(2), MockEnv::new(5)]);
+        let mut collector = Collector::new(venv, MockPolicy, Some(ReplayBuffer::new(100))).unwrap();
+
+        // The short env finishes 2 episodes first, but only 2 of its 5 count
+        let stats = collector.collect(CollectSpec::Episodes(4)).unwrap();
+        assert_eq!(stats.n_episodes, 4);
+        assert_eq!(stats.env_ids, vec![0, 0, 1, 1]);
+        assert_eq!(stats.lens, vec![2, 2, 5, 5]);
+        assert_eq!(stats.n_steps, 20);
+        // Every transition is still stored
+        assert_eq!(collector.get_buffer_len(), 20);
+
+        // The long env is mid-episode after 3 more steps; resetting first
+        // reports only whole episodes
+        collector.collect(CollectSpec::Steps(6)).unwrap();
+        collector.set_reset_before_collect(true);
+        let stats = collector.collect(CollectSpec::Episodes(1)).unwrap();
+        assert_eq!((stats.env_ids[0], stats.lens[0]), (0, 2));
+        let stats = collector.collect(CollectSpec::Episodes(2)).unwrap();
+        assert_eq!(stats.lens, vec![2, 5]);
     }
 }
@@ -0,0 +1,114 @@
+use crate::error::{HabaError, Result};
+use candle_core::{DType, Device};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Where a policy's networks live.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DeviceConfig {
+    #[default]
+    Cpu,
+    /// CUDA device with the given ordinal.
+    Cuda(usize),
+    /// Metal device with the given ordinal.
+    Metal(usize),
+    /// The first of CUDA, Metal or CPU that is available.
+    Auto,
+}
+
+impl DeviceConfig {
+    pub fn resolve(&self) -> Result<Device> {
+        let device = match self {
+            DeviceConfig::Cpu => Device::Cpu,
+            DeviceConfig::Cuda(ordinal) => Device::new_cuda(*ordinal)
+                .map_err(|e| HabaError::Device(format!("cuda:{}: {}", ordinal, e)))?,
+            DeviceConfig::Metal(ordinal) => Device::new_metal(*ordinal)
+                .map_err(|e| HabaError::Device(format!("metal:{}: {}", ordinal, e)))?,
+            DeviceConfig::Auto => {
+                if candle_core::utils::cuda_is_available() {
+                    return DeviceConfig::Cuda(0).resolve();
+                }
+                if candle_core::utils::metal_is_available() {
+                    return DeviceConfig::Metal(0).resolve();
+                }
+                Device::Cpu
+            }
+        };
+        log::info!("Using device {:?}", device);
+        Ok(device)
+    }
+}
+
+/// Parses `cpu`, `auto`, `cuda`, `cuda:N`, `metal` and `metal:N`.
+impl FromStr for DeviceConfig {
+    type Err = HabaError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, ordinal) = match s.split_once(':') {
+            Some((kind, n)) => {
+                let n = n
+                    .parse()
+                    .map_err(|_| HabaError::Config(format!("invalid device ordinal in {:?}", s)))?;
+                (kind, Some(n))
+            }
+            None => (s, None),
+        };
+        match (kind.to_ascii_lowercase().as_str(), ordinal) {
+            ("cpu", None) => Ok(DeviceConfig::Cpu),
+            ("auto", None) => Ok(DeviceConfig::Auto),
+            ("cuda", n) => Ok(DeviceConfig::Cuda(n.unwrap_or(0))),
+            ("metal", n) => Ok(DeviceConfig::Metal(n.unwrap_or(0))),
+            _ => Err(HabaError::Config(format!("unknown device {:?}", s))),
+        }
+    }
+}
+
+/// Floating point precision of a policy's parameters and activations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Precision {
+    #[default]
+    F32,
+    F64,
+    BF16,
+}
+
+impl Precision {
+    pub fn dtype(&self) -> DType {
+        match self {
+            Precision::F32 => DType::F32,
+            Precision::F64 => DType::F64,
+            Precision::BF16 => DType::BF16,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_device_config() {
+        assert_eq!("cpu".parse::<DeviceConfig>().unwrap(), DeviceConfig::Cpu);
+        assert_eq!("auto".parse::<DeviceConfig>().unwrap(), DeviceConfig::Auto);
+        assert_eq!(
+            "cuda".parse::<DeviceConfig>().unwrap(),
+            DeviceConfig::Cuda(0)
+        );
+        assert_eq!(
+            "cuda:2".parse::<DeviceConfig>().unwrap(),
+            DeviceConfig::Cuda(2)
+        );
+        assert_eq!(
+            "metal:1".parse::<DeviceConfig>().unwrap(),
+            DeviceConfig::Metal(1)
+        );
+        assert!("tpu".parse::<DeviceConfig>().is_err());
+        assert!("cpu:1".parse::<DeviceConfig>().is_err());
+    }
+
+    #[test]
+    fn test_cpu_always_resolves() {
+        assert!(DeviceConfig::Cpu.resolve().unwrap().is_cpu());
+        assert!(DeviceConfig::Auto.resolve().is_ok());
+    }
+}
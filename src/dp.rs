@@ -0,0 +1,293 @@
+use crate::error::{HabaError, Result};
+use crate::util::argmax;
+use serde::{Deserialize, Serialize};
+
+/// One outcome of taking an action: Gymnasium's `(prob, next_state,
+/// reward, terminated)` tuple.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Transition {
+    pub prob: f64,
+    pub next_state: usize,
+    pub reward: f64,
+    pub done: bool,
+}
+
+/// The full dynamics P(s'|s,a) of a finite MDP, plus its distribution of
+/// initial states.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransitionModel {
+    n_states: usize,
+    n_actions: usize,
+    // Outcomes of (s, a) at index s * n_actions + a
+    transitions: Vec<Vec<Transition>>,
+    initial: Vec<f64>,
+}
+
+impl TransitionModel {
+    /// A model with no outcomes yet; fill it in with `set`. `initial` needs
+    /// one probability per state.
+    pub fn new(n_states: usize, n_actions: usize, initial: Vec<f64>) -> Result<Self> {
+        if initial.len() != n_states {
+            return Err(HabaError::Config(format!(
+                "{} initial probabilities for {} states",
+                initial.len(),
+                n_states
+            )));
+        }
+        Ok(Self {
+            n_states,
+            n_actions,
+            transitions: vec![Vec::new(); n_states * n_actions],
+            initial,
+        })
+    }
+
+    pub fn n_states(&self) -> usize {
+        self.n_states
+    }
+
+    pub fn n_actions(&self) -> usize {
+        self.n_actions
+    }
+
+    pub fn initial(&self) -> &[f64] {
+        &self.initial
+    }
+
+    pub fn transitions(&self, state: usize, action: usize) -> &[Transition] {
+        &self.transitions[state * self.n_actions + action]
+    }
+
+    pub fn set(&mut self, state: usize, action: usize, outcomes: Vec<Transition>) {
+        self.transitions[state * self.n_actions + action] = outcomes;
+    }
+
+    /// Error unless the initial distribution and every P(.|s,a) sum to 1
+    /// over valid states.
+    pub fn validate(&self) -> Result<()> {
+        let is_distribution = |total: f64| (total - 1.0).abs() < 1e-9;
+        if !is_distribution(self.initial.iter().sum()) {
+            return Err(HabaError::Config(
+                "initial state distribution does not sum to 1".to_string(),
+            ));
+        }
+        for state in 0..self.n_states {
+            for action in 0..self.n_actions {
+                let outcomes = self.transitions(state, action);
+                let total = outcomes.iter().map(|t| t.prob).sum();
+                if !is_distribution(total) || outcomes.iter().any(|t| t.next_state >= self.n_states)
+                {
+                    return Err(HabaError::Config(format!(
+                        "invalid transitions from state {} under action {}",
+                        state, action
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Expected return of each action from each state, given state values.
+    /// Terminal outcomes are not bootstrapped.
+    pub fn q_values(&self, values: &[f64], gamma: f64) -> Vec<Vec<f64>> {
+        (0..self.n_states)
+            .map(|s| {
+                (0..self.n_actions)
+                    .map(|a| self.backup(s, a, values, gamma))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn backup(&self, state: usize, action: usize, values: &[f64], gamma: f64) -> f64 {
+        self.transitions(state, action)
+            .iter()
+            .map(|t| {
+                let future = if t.done { 0.0 } else { values[t.next_state] };
+                t.prob * (t.reward + gamma * future)
+            })
+            .sum()
+    }
+}
+
+/// Settings shared by the solvers.
+#[derive(Debug, Clone)]
+pub struct DpConfig {
+    pub gamma: f64,
+    /// Stop once no value changes by more than this in a sweep.
+    pub tolerance: f64,
+    /// Sweeps after which a solver gives up with an error.
+    pub max_iterations: usize,
+}
+
+impl Default for DpConfig {
+    fn default() -> Self {
+        Self {
+            gamma: 0.99,
+            tolerance: 1e-10,
+            max_iterations: 100_000,
+        }
+    }
+}
+
+/// Optimal values and a greedy optimal policy.
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub values: Vec<f64>,
+    pub q_values: Vec<Vec<f64>>,
+    pub policy: Vec<usize>,
+    /// Value sweeps for value iteration, improvement steps for policy iteration.
+    pub iterations: usize,
+}
+
+impl Solution {
+    fn new(model: &TransitionModel, values: Vec<f64>, gamma: f64, iterations: usize) -> Self {
+        let q_values = model.q_values(&values, gamma);
+        let policy = q_values.iter().map(argmax).collect();
+        Self {
+            values,
+            q_values,
+            policy,
+            iterations,
+        }
+    }
+}
+
+/// Solve for optimal values by repeated Bellman optimality backups.
+pub fn value_iteration(model: &TransitionModel, config: &DpConfig) -> Result<Solution> {
+    let mut values = vec![0.0; model.n_states()];
+    for iteration in 1..=config.max_iterations {
+        let mut delta: f64 = 0.0;
+        for s in 0..model.n_states() {
+            let best = (0..model.n_actions())
+                .map(|a| model.backup(s, a, &values, config.gamma))
+                .fold(f64::NEG_INFINITY, f64::max);
+            delta = delta.max((best - values[s]).abs());
+            values[s] = best;
+        }
+        if delta <= config.tolerance {
+            return Ok(Solution::new(model, values, config.gamma, iteration));
+        }
+    }
+    Err(not_converged("value iteration", config))
+}
+
+/// Values of following `policy` forever, by iterative evaluation. With
+/// `gamma = 1` the policy must reach a terminal state from everywhere.
+pub fn policy_evaluation(
+    model: &TransitionModel,
+    policy: &[usize],
+    config: &DpConfig,
+) -> Result<Vec<f64>> {
+    if policy.len() != model.n_states() {
+        return Err(HabaError::Shape(format!(
+            "policy has {} states, model has {}",
+            policy.len(),
+            model.n_states()
+        )));
+    }
+    let mut values = vec![0.0; model.n_states()];
+    for _ in 0..config.max_iterations {
+        let mut delta: f64 = 0.0;
+        for (s, &a) in policy.iter().enumerate() {
+            let value = model.backup(s, a, &values, config.gamma);
+            delta = delta.max((value - values[s]).abs());
+            values[s] = value;
+        }
+        if delta <= config.tolerance {
+            return Ok(values);
+        }
+    }
+    Err(not_converged("policy evaluation", config))
+}
+
+// Smallest gain in Q that counts as an improvement over the current action
+const IMPROVEMENT_THRESHOLD: f64 = 1e-9;
+
+/// Alternate policy evaluation and greedy improvement, starting from
+/// action 0 everywhere, until the policy is stable.
+pub fn policy_iteration(model: &TransitionModel, config: &DpConfig) -> Result<Solution> {
+    let mut policy = vec![0; model.n_states()];
+    for iteration in 1..=config.max_iterations {
+        let values = policy_evaluation(model, &policy, config)?;
+        let q_values = model.q_values(&values, config.gamma);
+        let mut stable = true;
+        for (action, q) in policy.iter_mut().zip(&q_values) {
+            // Only switch for a real improvement, or ties could cycle forever
+            let best = argmax(q);
+            if q[best] > q[*action] + IMPROVEMENT_THRESHOLD {
+                *action = best;
+                stable = false;
+            }
+        }
+        if stable {
+            let mut solution = Solution::new(model, values, config.gamma, iteration);
+            solution.policy = policy;
+            return Ok(solution);
+        }
+    }
+    Err(not_converged("policy iteration", config))
+}
+
+fn not_converged(solver: &str, config: &DpConfig) -> HabaError {
+    HabaError::Config(format!(
+        "{} did not converge within {} iterations",
+        solver, config.max_iterations
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two states: staying in 0 pays 1 forever, moving to 1 pays 5 and ends
+    fn two_state_model() -> TransitionModel {
+        let mut model = TransitionModel::new(2, 2, vec![1.0, 0.0]).unwrap();
+        let outcome = |next_state, reward, done| Transition {
+            prob: 1.0,
+            next_state,
+            reward,
+            done,
+        };
+        model.set(0, 0, vec![outcome(0, 1.0, false)]);
+        model.set(0, 1, vec![outcome(1, 5.0, true)]);
+        model.set(1, 0, vec![outcome(1, 0.0, true)]);
+        model.set(1, 1, vec![outcome(1, 0.0, true)]);
+        model
+    }
+
+    #[test]
+    fn test_solvers_agree_on_closed_form() {
+        let model = two_state_model();
+        model.validate().unwrap();
+        assert!(matches!(
+            TransitionModel::new(3, 2, vec![1.0, 0.0]),
+            Err(HabaError::Config(_))
+        ));
+        // Staying is worth 1 / (1 - gamma), so it wins only for gamma > 0.8
+        for (gamma, action, value) in [(0.9, 0, 10.0), (0.5, 1, 5.0)] {
+            let config = DpConfig {
+                gamma,
+                ..DpConfig::default()
+            };
+            for solution in [
+                value_iteration(&model, &config).unwrap(),
+                policy_iteration(&model, &config).unwrap(),
+            ] {
+                assert_eq!(solution.policy[0], action);
+                assert!((solution.values[0] - value).abs() < 1e-8);
+            }
+        }
+
+        let config = DpConfig {
+            gamma: 1.0,
+            max_iterations: 50,
+            ..DpConfig::default()
+        };
+        assert!(policy_evaluation(&model, &[0, 0], &config).is_err());
+        assert_eq!(
+            policy_evaluation(&model, &[1, 0], &config).unwrap(),
+            [5.0, 0.0]
+        );
+    }
+}
@@ -1,9 +1,94 @@
 use crate::batch::Batch;
-use crate::model::QNet;
-use crate::policy::Policy;
-use candle_core::{DType, Device, Module, Tensor};
-use candle_nn::{AdamW, Optimizer, ParamsAdamW, VarBuilder, VarMap};
-use rand::Rng;
+use crate::checkpoint;
+use crate::device::{DeviceConfig, Precision};
+use crate::error::{HabaError, Result};
+use crate::model::{CnnBuilder, MlpBuilder, QNet};
+use crate::optim::AdamW;
+use crate::policy::{HiddenState, Policy, PolicyMode, PolicyOutput};
+use crate::schedule::{Progress, Scheduled};
+use crate::stats::LearnStats;
+use candle_core::{DType, Device, Tensor};
+use candle_nn::{ParamsAdamW, VarBuilder, VarMap};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::path::Path;
+
+const ONLINE_WEIGHTS: &str = "q_net.safetensors";
+const TARGET_WEIGHTS: &str = "target_q_net.safetensors";
+const OPTIMIZER_STATE: &str = "optimizer.safetensors";
+const TRAINING_STATE: &str = "training_state.json";
+
+/// Network shape of a `DQNPolicy`. Checkpoints only load into a policy with the same one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DQNArch {
+    pub in_dim: usize,
+    pub hidden_dim: usize,
+    pub out_dim: usize,
+    /// Image encoder in front of the MLP head, for pixel observations.
+    #[serde(default)]
+    pub encoder: Option<CnnBuilder>,
+    /// Custom MLP used instead of the `hidden_dim` layers, after the encoder
+    /// if there is one.
+    #[serde(default)]
+    pub head: Option<MlpBuilder>,
+}
+
+impl DQNArch {
+    fn q_net(&self, vb: VarBuilder) -> Result<QNet> {
+        let head_in = self
+            .encoder
+            .as_ref()
+            .map_or(self.in_dim, CnnBuilder::out_dim);
+        if let Some(head) = &self.head
+            && (head.in_dim() != head_in || head.out_dim() != self.out_dim)
+        {
+            return Err(HabaError::Config(format!(
+                "head maps {} to {} but the network needs {} to {}",
+                head.in_dim(),
+                head.out_dim(),
+                head_in,
+                self.out_dim
+            )));
+        }
+        let q_net = match (&self.encoder, &self.head) {
+            (Some(encoder), Some(head)) => QNet::with_encoder(encoder, head, vb)?,
+            // One hidden layer after the encoder, as in the Nature DQN
+            (Some(encoder), None) => {
+                let head = MlpBuilder::new(head_in, self.out_dim).hidden(&[self.hidden_dim]);
+                QNet::with_encoder(encoder, &head, vb)?
+            }
+            (None, Some(head)) => QNet::from_builder(head, vb)?,
+            (None, None) => QNet::new(self.in_dim, self.hidden_dim, self.out_dim, vb)?,
+        };
+        Ok(q_net)
+    }
+}
+
+// Written next to the weights so a checkpoint is self-describing. Shared
+// with DRQN, which has its own architecture type.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct DQNManifest<A> {
+    pub(crate) algorithm: String,
+    pub(crate) haba_version: String,
+    pub(crate) arch: A,
+    pub(crate) precision: Precision,
+    pub(crate) gamma: f64,
+    pub(crate) epsilon: Scheduled,
+    pub(crate) lr: Scheduled,
+    pub(crate) target_update_freq: usize,
+    pub(crate) update_count: usize,
+}
+
+// Only needed to resume training exactly, not to run the policy
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct DQNTrainingState {
+    optimizer_step: usize,
+    pub(crate) progress: Progress,
+    pub(crate) rng: ChaCha8Rng,
+}
 
 pub struct DQNPolicy {
     // Model
@@ -13,123 +98,226 @@ pub struct DQNPolicy {
     target_varmap: VarMap,
     optimizer: AdamW,
     device: Device,
+    precision: Precision,
+    arch: DQNArch,
 
     // Hyperparameters
     gamma: f64,
-    epsilon: f64,
+    epsilon: Scheduled,
+    lr: Scheduled,
     target_update_freq: usize,
     update_count: usize,
+    progress: Progress,
+    mode: PolicyMode,
+    rng: ChaCha8Rng,
 }
 
 impl DQNPolicy {
+    /// A DQN policy on the CPU in f32. Use `with_device` to choose otherwise.
     pub fn new(
         in_dim: usize,
         hidden_dim: usize,
         out_dim: usize,
         gamma: f64,
-        epsilon: f64,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
-        let device = if candle_core::utils::cuda_is_available() {
-            println!("CUDA detected! Using GPU.");
-            Device::new_cuda(0)?
-        } else {
-            println!("CUDA not detected. Using CPU.");
-            Device::Cpu
+        epsilon: impl Into<Scheduled>,
+    ) -> Result<Self> {
+        Self::with_device(
+            in_dim,
+            hidden_dim,
+            out_dim,
+            gamma,
+            epsilon,
+            DeviceConfig::Cpu,
+            Precision::F32,
+        )
+    }
+
+    pub fn with_device(
+        in_dim: usize,
+        hidden_dim: usize,
+        out_dim: usize,
+        gamma: f64,
+        epsilon: impl Into<Scheduled>,
+        device: DeviceConfig,
+        precision: Precision,
+    ) -> Result<Self> {
+        let arch = DQNArch {
+            in_dim,
+            hidden_dim,
+            out_dim,
+            encoder: None,
+            head: None,
         };
-        let varmap = VarMap::new();
-        let vb = VarBuilder::from_varmap(&varmap, DType::F64, &device);
+        Self::from_arch(arch, gamma, epsilon, device, precision)
+    }
+
+    /// A DQN policy for flattened channel-first images, with `encoder` in
+    /// front of a `hidden_dim` MLP head.
+    pub fn with_encoder(
+        encoder: CnnBuilder,
+        hidden_dim: usize,
+        out_dim: usize,
+        gamma: f64,
+        epsilon: impl Into<Scheduled>,
+        device: DeviceConfig,
+        precision: Precision,
+    ) -> Result<Self> {
+        let arch = DQNArch {
+            in_dim: encoder.in_dim(),
+            hidden_dim,
+            out_dim,
+            encoder: Some(encoder),
+            head: None,
+        };
+        Self::from_arch(arch, gamma, epsilon, device, precision)
+    }
 
-        let q_net = QNet::new(in_dim, hidden_dim, out_dim, vb.clone())?;
+    /// A DQN policy with any architecture, e.g. one with a custom `head`.
+    pub fn from_arch(
+        arch: DQNArch,
+        gamma: f64,
+        epsilon: impl Into<Scheduled>,
+        device: DeviceConfig,
+        precision: Precision,
+    ) -> Result<Self> {
+        let epsilon = epsilon.into();
+        let device = device.resolve()?;
+        let dtype = precision.dtype();
+        let varmap = VarMap::new();
+        let vb = VarBuilder::from_varmap(&varmap, dtype, &device);
+        let q_net = arch.q_net(vb)?;
 
         // Target net with separate vars
         let target_varmap = VarMap::new();
-        let target_vb = VarBuilder::from_varmap(&target_varmap, DType::F64, &device);
-        let target_q_net = QNet::new(in_dim, hidden_dim, out_dim, target_vb)?;
+        let target_vb = VarBuilder::from_varmap(&target_varmap, dtype, &device);
+        let target_q_net = arch.q_net(target_vb)?;
 
         // Optimizer
+        let lr = Scheduled::from(1e-3);
+        let progress = Progress::default();
         let params = ParamsAdamW {
-            lr: 1e-3,
+            lr: lr.value(progress),
             ..Default::default()
         };
-        let optimizer = AdamW::new(varmap.all_vars(), params)?;
+        let vars = varmap.data().lock().unwrap().clone();
+        let optimizer = AdamW::new(vars, params)?;
 
-        let mut policy = Self {
+        let policy = Self {
             q_net,
             target_q_net,
             varmap,
             target_varmap,
             optimizer,
             device,
+            precision,
+            arch,
             gamma,
             epsilon,
+            lr,
             target_update_freq: 100,
             update_count: 0,
+            progress,
+            mode: PolicyMode::Train,
+            rng: ChaCha8Rng::from_entropy(),
         };
 
         // Initial sync
-        policy.sync_target()?;
+        sync_target(&policy.varmap, &policy.target_varmap)?;
 
         Ok(policy)
     }
 
-    fn sync_target(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let src_data = self.varmap.data();
-        let target_data = self.target_varmap.data();
+    /// Replace the learning rate (constant 1e-3 by default) with a schedule.
+    pub fn with_lr(mut self, lr: impl Into<Scheduled>) -> Self {
+        self.lr = lr.into();
+        self.optimizer
+            .set_learning_rate(self.lr.value(self.progress));
+        self
+    }
 
-        let src_lock = src_data.lock().unwrap();
-        let mut target_lock = target_data.lock().unwrap();
+    /// Copy the online network into the target every `freq` updates (100 by default).
+    pub fn with_target_update_freq(mut self, freq: usize) -> Self {
+        self.target_update_freq = freq.max(1);
+        self
+    }
 
-        for (name, src_var) in src_lock.iter() {
-            if let Some(target_var) = target_lock.get_mut(name) {
-                target_var.set(&src_var.as_tensor())?;
-            }
+    /// Exploration rate at the current training progress. Always 0 in eval mode.
+    pub fn epsilon(&self) -> f64 {
+        exploration_rate(&self.epsilon, self.progress, self.mode)
+    }
+
+    pub fn arch(&self) -> &DQNArch {
+        &self.arch
+    }
+
+    /// Number of gradient updates performed so far.
+    pub fn update_count(&self) -> usize {
+        self.update_count
+    }
+
+    /// Learning rate at the current training progress.
+    pub fn learning_rate(&self) -> f64 {
+        self.optimizer.learning_rate()
+    }
+
+    // Stack observations into a (B, in_dim) tensor, rejecting wrongly sized ones
+    fn obs_tensor(&self, obs: &[Vec<f64>]) -> Result<Tensor> {
+        let in_dim = self.arch.in_dim;
+        if let Some(bad) = obs.iter().find(|o| o.len() != in_dim) {
+            return Err(HabaError::Shape(format!(
+                "expected observations of length {}, got {}",
+                in_dim,
+                bad.len()
+            )));
         }
-        Ok(())
+        let flat: Vec<f64> = obs.iter().flatten().cloned().collect();
+        self.column(flat, in_dim)
+    }
+
+    // (B, width) tensor in the policy's dtype from row-major f64 data
+    fn column(&self, data: Vec<f64>, width: usize) -> Result<Tensor> {
+        let rows = data.len() / width.max(1);
+        Ok(
+            Tensor::from_vec(data, (rows, width), &self.device)?
+                .to_dtype(self.precision.dtype())?,
+        )
     }
 }
 
 impl Policy for DQNPolicy {
     type Observation = Vec<f64>;
-    type Action = f64;
-
-    fn forward(&mut self, obs: &[Self::Observation]) -> Vec<Self::Action> {
-        let mut rng = rand::thread_rng();
-        let batch_size = obs.len();
+    type Action = usize;
 
+    fn forward(
+        &mut self,
+        obs: &[Self::Observation],
+        _state: Option<&HiddenState>,
+    ) -> Result<PolicyOutput<Self::Action>> {
         // 1. Get Greedy Actions from Model
-        // Flatten obs
-        let obs_flat: Vec<f64> = obs.iter().flatten().cloned().collect();
-        let obs_tensor = Tensor::from_vec(obs_flat, (batch_size, 4), &self.device).unwrap();
-        let q_values = self.q_net.forward(&obs_tensor).unwrap();
-        let greedy_actions: Vec<u32> = q_values.argmax(1).unwrap().to_vec1().unwrap();
+        let obs_tensor = self.obs_tensor(obs)?;
+        let q_values = self.q_net.forward(&obs_tensor)?;
+        let greedy_actions: Vec<u32> = q_values.argmax(1)?.to_vec1()?;
 
         // 2. Select final actions (epsilon-greedy)
-        let mut final_actions = Vec::with_capacity(batch_size);
-        for i in 0..batch_size {
-            if rng.gen_bool(self.epsilon) {
-                // Random action
-                if rng.gen_bool(0.5) {
-                    final_actions.push(1.0);
-                } else {
-                    final_actions.push(0.0);
-                }
-            } else {
-                final_actions.push(greedy_actions[i] as f64);
-            }
-        }
-        final_actions
+        let epsilon = self.epsilon();
+        let actions = epsilon_greedy(&greedy_actions, epsilon, self.arch.out_dim, &mut self.rng);
+        Ok(PolicyOutput::new(actions))
     }
 
-    fn learn(&mut self, batch: &Batch<Self::Observation, Self::Action>) {
-        if self.update_count % self.target_update_freq == 0 {
-            self.sync_target().unwrap();
+    fn learn(&mut self, batch: &Batch<Self::Observation, Self::Action>) -> Result<LearnStats> {
+        if self.update_count.is_multiple_of(self.target_update_freq) {
+            sync_target(&self.varmap, &self.target_varmap)?;
         }
 
         // 1. Prepare Tensors
         let b_size = batch.len();
-        let obs_flat: Vec<f64> = batch.obs.iter().flatten().cloned().collect();
-        let next_obs_flat: Vec<f64> = batch.obs_next.iter().flatten().cloned().collect();
         // Actions to u32 indices
+        if let Some(bad) = batch.act.iter().find(|&&a| a >= self.arch.out_dim) {
+            return Err(HabaError::Shape(format!(
+                "action {} out of range for {} Q-values",
+                bad, self.arch.out_dim
+            )));
+        }
         let acts_idx: Vec<u32> = batch.act.iter().map(|&a| a as u32).collect();
         let rews: Vec<f64> = batch.rew.clone();
         let dones: Vec<f64> = batch
@@ -138,46 +326,217 @@ impl Policy for DQNPolicy {
             .map(|&d| if d { 1.0 } else { 0.0 })
             .collect();
 
-        // Assuming obs dim is 4
-        let obs = Tensor::from_vec(obs_flat, (b_size, 4), &self.device).unwrap();
-        let next_obs = Tensor::from_vec(next_obs_flat, (b_size, 4), &self.device).unwrap();
-        let action_idx = Tensor::from_vec(acts_idx, (b_size, 1), &self.device).unwrap(); // (B, 1) integers
-        let reward = Tensor::from_vec(rews, (b_size, 1), &self.device).unwrap();
-        let done = Tensor::from_vec(dones, (b_size, 1), &self.device).unwrap();
+        let obs = self.obs_tensor(&batch.obs)?;
+        let next_obs = self.obs_tensor(&batch.obs_next)?;
+        let action_idx = Tensor::from_vec(acts_idx, (b_size, 1), &self.device)?; // (B, 1) integers
+        let reward = self.column(rews, 1)?;
+        let done = self.column(dones, 1)?;
 
         // 2. Compute Target Q
         // Q_target = r + gamma * max(Q_target(s', a'))
         // Use target_q_net for stability
-        let next_q_values = self.target_q_net.forward(&next_obs).unwrap().detach();
-        let max_next_q = next_q_values.max(1).unwrap().reshape((b_size, 1)).unwrap();
-        let target_q = (reward + (1.0 - done).unwrap() * self.gamma * max_next_q)
-            .unwrap()
-            .detach();
+        let next_q_values = self.target_q_net.forward(&next_obs)?.detach();
+        let max_next_q = next_q_values.max(1)?.reshape((b_size, 1))?;
+        let target_q = (reward + ((1.0 - done)? * self.gamma * max_next_q)?)?.detach();
 
         // 3. Compute Current Q
-        let q_values = self.q_net.forward(&obs).unwrap(); // (B, 2)
+        // Dropout, if the network has any, follows the policy mode
+        let train = self.mode == PolicyMode::Train;
+        let q_values = self.q_net.forward_t(&obs, train)?; // (B, n_actions)
         // Gather Q values for the taken actions
-        let current_q = q_values.gather(&action_idx, 1).unwrap(); // (B, 1)
+        let current_q = q_values.gather(&action_idx, 1)?; // (B, 1)
 
         // 4. Loss
-        let loss = (current_q - target_q)
-            .unwrap()
-            .sqr()
-            .unwrap()
-            .mean_all()
-            .unwrap();
+        let td_error = (&current_q - target_q)?;
+        let loss = td_error.sqr()?.mean_all()?;
 
         // 5. Optimize
-        self.optimizer.backward_step(&loss).unwrap();
+        let grads = loss.backward()?;
+        let grad_norm = self.optimizer.grad_norm(&grads)?;
+        self.optimizer.step(&grads)?;
 
         self.update_count += 1;
+
+        let mut stats = LearnStats::new();
+        stats.insert("loss", scalar(&loss)?);
+        stats.insert("q_mean", scalar(&current_q.mean_all()?)?);
+        stats.insert("td_error", scalar(&td_error.abs()?.mean_all()?)?);
+        stats.insert("grad_norm", grad_norm);
+        stats.insert("lr", self.optimizer.learning_rate());
+        Ok(stats)
+    }
+
+    /// Writes both networks as safetensors plus a `manifest.json` with the
+    /// architecture, hyperparameters and update count.
+    fn save_checkpoint(&self, dir: &Path) -> Result<()> {
+        let manifest = DQNManifest {
+            algorithm: "dqn".to_string(),
+            haba_version: env!("CARGO_PKG_VERSION").to_string(),
+            arch: self.arch.clone(),
+            precision: self.precision,
+            gamma: self.gamma,
+            epsilon: self.epsilon.clone(),
+            lr: self.lr.clone(),
+            target_update_freq: self.target_update_freq,
+            update_count: self.update_count,
+        };
+        write_checkpoint(dir, &self.varmap, &self.target_varmap, &manifest)
+    }
+
+    fn load_checkpoint(&mut self, dir: &Path) -> Result<()> {
+        let manifest = read_checkpoint(dir, "dqn", &self.arch, &self.varmap, &self.target_varmap)?;
+        self.gamma = manifest.gamma;
+        self.epsilon = manifest.epsilon;
+        self.lr = manifest.lr;
+        self.target_update_freq = manifest.target_update_freq;
+        self.update_count = manifest.update_count;
+        self.optimizer
+            .set_learning_rate(self.lr.value(self.progress));
+        Ok(())
+    }
+
+    /// Adds the optimizer moments, RNG and schedule progress to the checkpoint.
+    fn save_training_state(&self, dir: &Path) -> Result<()> {
+        self.save_checkpoint(dir)?;
+        write_training_state(dir, &self.optimizer, self.progress, &self.rng)
+    }
+
+    fn load_training_state(&mut self, dir: &Path) -> Result<()> {
+        self.load_checkpoint(dir)?;
+        let state = read_training_state(dir, &mut self.optimizer)?;
+        self.rng = state.rng;
+        self.set_progress(state.progress);
+        Ok(())
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    fn set_progress(&mut self, progress: Progress) {
+        self.progress = progress;
+        self.optimizer.set_learning_rate(self.lr.value(progress));
+    }
+
+    fn set_mode(&mut self, mode: PolicyMode) {
+        self.mode = mode;
     }
+
+    fn mode(&self) -> PolicyMode {
+        self.mode
+    }
+}
+
+pub(crate) fn scalar(t: &Tensor) -> Result<f64> {
+    Ok(t.to_dtype(DType::F64)?.to_scalar::<f64>()?)
+}
+
+/// Copy every online variable into the target variable of the same name.
+pub(crate) fn sync_target(varmap: &VarMap, target_varmap: &VarMap) -> Result<()> {
+    let src = varmap.data().lock().unwrap();
+    let target = target_varmap.data().lock().unwrap();
+    for (name, src_var) in src.iter() {
+        if let Some(target_var) = target.get(name) {
+            target_var.set(src_var.as_tensor())?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn exploration_rate(epsilon: &Scheduled, progress: Progress, mode: PolicyMode) -> f64 {
+    match mode {
+        PolicyMode::Train => epsilon.value(progress),
+        PolicyMode::Eval => 0.0,
+    }
+}
+
+/// Replace each greedy action by a uniformly random one with probability `epsilon`.
+pub(crate) fn epsilon_greedy(
+    greedy: &[u32],
+    epsilon: f64,
+    n_actions: usize,
+    rng: &mut ChaCha8Rng,
+) -> Vec<usize> {
+    let epsilon = epsilon.clamp(0.0, 1.0);
+    greedy
+        .iter()
+        .map(|&g| {
+            // Eval mode must not consume randomness, so test runs leave training reproducible
+            if epsilon > 0.0 && rng.gen_bool(epsilon) {
+                rng.gen_range(0..n_actions)
+            } else {
+                g as usize
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn write_checkpoint<A: Serialize>(
+    dir: &Path,
+    varmap: &VarMap,
+    target_varmap: &VarMap,
+    manifest: &DQNManifest<A>,
+) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    varmap.save(dir.join(ONLINE_WEIGHTS))?;
+    target_varmap.save(dir.join(TARGET_WEIGHTS))?;
+    checkpoint::write_json(&dir.join(checkpoint::MANIFEST_FILE), manifest)
+}
+
+/// Loads both networks after checking the manifest's algorithm and architecture.
+pub(crate) fn read_checkpoint<A: DeserializeOwned + PartialEq + Debug>(
+    dir: &Path,
+    algorithm: &str,
+    arch: &A,
+    varmap: &VarMap,
+    target_varmap: &VarMap,
+) -> Result<DQNManifest<A>> {
+    let manifest: DQNManifest<A> = checkpoint::read_json(&dir.join(checkpoint::MANIFEST_FILE))?;
+    if manifest.algorithm != algorithm {
+        return Err(HabaError::Checkpoint(format!(
+            "expected a {} checkpoint, found {}",
+            algorithm, manifest.algorithm
+        )));
+    }
+    if manifest.arch != *arch {
+        return Err(HabaError::Checkpoint(format!(
+            "architecture mismatch: checkpoint has {:?}, policy has {:?}",
+            manifest.arch, arch
+        )));
+    }
+
+    // Weights are converted, so a checkpoint can be loaded at another precision
+    checkpoint::load_varmap(varmap, &dir.join(ONLINE_WEIGHTS))?;
+    checkpoint::load_varmap(target_varmap, &dir.join(TARGET_WEIGHTS))?;
+    Ok(manifest)
+}
+
+pub(crate) fn write_training_state(
+    dir: &Path,
+    optimizer: &AdamW,
+    progress: Progress,
+    rng: &ChaCha8Rng,
+) -> Result<()> {
+    optimizer.save_moments(&dir.join(OPTIMIZER_STATE))?;
+    let state = DQNTrainingState {
+        optimizer_step: optimizer.step_count(),
+        progress,
+        rng: rng.clone(),
+    };
+    checkpoint::write_json(&dir.join(TRAINING_STATE), &state)
+}
+
+pub(crate) fn read_training_state(dir: &Path, optimizer: &mut AdamW) -> Result<DQNTrainingState> {
+    let state: DQNTrainingState = checkpoint::read_json(&dir.join(TRAINING_STATE))?;
+    optimizer.load_moments(&dir.join(OPTIMIZER_STATE), state.optimizer_step)?;
+    Ok(state)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::batch::Batch;
+    use crate::model::{Activation, WeightInit};
 
     #[test]
     fn test_dqn_forward() {
@@ -190,13 +549,12 @@ mod tests {
         let obs_batch = vec![obs1, obs2];
 
         // 3. Inference
-        let actions = policy.forward(&obs_batch);
+        let actions = policy.forward(&obs_batch, None).unwrap().act;
 
         // 4. Verification
         assert_eq!(actions.len(), 2);
-        // Actions should be 0.0 or 1.0
-        assert!(actions[0] == 0.0 || actions[0] == 1.0);
-        assert!(actions[1] == 0.0 || actions[1] == 1.0);
+        // Actions should be 0 or 1
+        assert!(actions.iter().all(|&a| a < 2));
     }
 
     #[test]
@@ -207,7 +565,7 @@ mod tests {
         // 2. Create Dummy Batch
         let obs = vec![vec![0.0; 4], vec![1.0; 4]];
         let next_obs = vec![vec![0.0; 4], vec![1.0; 4]];
-        let act = vec![0.0, 1.0];
+        let act = vec![0, 1];
         let rew = vec![1.0, 0.0];
         let done = vec![false, true];
 
@@ -215,9 +573,182 @@ mod tests {
 
         // 3. Learn
         // Just verify it doesn't panic
-        policy.learn(&batch);
+        let stats = policy.learn(&batch).unwrap();
 
         // Verify update count increased
         assert_eq!(policy.update_count, 1);
+        for key in ["loss", "q_mean", "td_error", "grad_norm", "lr"] {
+            assert!(stats.get(key).unwrap().is_finite(), "missing {}", key);
+        }
+        assert_eq!(stats.get("lr"), Some(1e-3));
+    }
+
+    #[test]
+    fn test_dqn_with_encoder() {
+        let encoder = CnnBuilder::new(2, 12, 12).layer(4, 4, 2).layer(8, 3, 2);
+        let in_dim = encoder.in_dim();
+        let mut policy =
+            DQNPolicy::with_encoder(encoder, 16, 3, 0.99, 0.0, DeviceConfig::Cpu, Precision::F32)
+                .unwrap();
+        assert_eq!(policy.arch().in_dim, 2 * 12 * 12);
+
+        let obs = vec![vec![0.5; in_dim], vec![1.0; in_dim]];
+        assert_eq!(policy.forward(&obs, None).unwrap().act.len(), 2);
+        let batch = Batch::new(
+            obs.clone(),
+            vec![0, 2],
+            vec![1.0, 0.0],
+            vec![false, true],
+            obs,
+        );
+        let stats = policy.learn(&batch).unwrap();
+        assert!(stats.get("loss").unwrap().is_finite());
+    }
+
+    #[test]
+    fn test_dqn_custom_head() {
+        let head = MlpBuilder::new(4, 2)
+            .hidden(&[8, 8, 8])
+            .activation(Activation::Tanh)
+            .init(WeightInit::Orthogonal { gain: 1.0 })
+            .seed(3);
+        let arch = DQNArch {
+            in_dim: 4,
+            hidden_dim: 0,
+            out_dim: 2,
+            encoder: None,
+            head: Some(head.clone()),
+        };
+        let policy =
+            DQNPolicy::from_arch(arch.clone(), 0.99, 0.0, DeviceConfig::Cpu, Precision::F32)
+                .unwrap();
+        assert!(
+            policy
+                .varmap
+                .data()
+                .lock()
+                .unwrap()
+                .contains_key("fc4.weight")
+        );
+
+        // A seeded head gives the same network every time
+        let twin = DQNPolicy::from_arch(arch.clone(), 0.99, 0.0, DeviceConfig::Cpu, Precision::F32)
+            .unwrap();
+        let obs = vec![vec![0.1, -0.2, 0.3, -0.4]];
+        let q = |p: &DQNPolicy| -> Vec<Vec<f32>> {
+            let xs = p.obs_tensor(&obs).unwrap();
+            p.q_net.forward(&xs).unwrap().to_vec2().unwrap()
+        };
+        assert_eq!(q(&policy), q(&twin));
+
+        let mismatched = DQNArch { out_dim: 3, ..arch };
+        let result = DQNPolicy::from_arch(mismatched, 0.99, 0.0, DeviceConfig::Cpu, Precision::F32);
+        assert!(matches!(result, Err(HabaError::Config(_))));
+    }
+
+    #[test]
+    fn test_dqn_rejects_bad_observation_shape() {
+        let mut policy = DQNPolicy::new(4, 16, 2, 0.99, 0.0).unwrap();
+        let result = policy.forward(&[vec![0.0; 3]], None);
+        assert!(matches!(result, Err(HabaError::Shape(_))));
+    }
+
+    #[test]
+    fn test_dqn_checkpoint_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("haba_dqn_ckpt_{}", std::process::id()));
+        let obs = vec![vec![0.1, -0.2, 0.3, -0.4], vec![1.0, 0.5, -0.5, 0.0]];
+
+        let mut policy = DQNPolicy::new(4, 16, 2, 0.9, 0.0).unwrap();
+        let batch = Batch::new(
+            obs.clone(),
+            vec![0, 1],
+            vec![1.0, 0.0],
+            vec![false, true],
+            obs.clone(),
+        );
+        policy.learn(&batch).unwrap();
+        policy.save_checkpoint(&dir).unwrap();
+
+        let mut restored = DQNPolicy::new(4, 16, 2, 0.99, 0.0).unwrap();
+        restored.load_checkpoint(&dir).unwrap();
+        assert_eq!(restored.update_count(), 1);
+        assert_eq!(restored.gamma, 0.9);
+
+        let obs_tensor = policy.obs_tensor(&obs).unwrap();
+        let expected: Vec<Vec<f32>> = policy
+            .q_net
+            .forward(&obs_tensor)
+            .unwrap()
+            .to_vec2()
+            .unwrap();
+        let actual: Vec<Vec<f32>> = restored
+            .q_net
+            .forward(&obs_tensor)
+            .unwrap()
+            .to_vec2()
+            .unwrap();
+        assert_eq!(expected, actual);
+
+        // Weights are converted when loading at another precision
+        let mut wide =
+            DQNPolicy::with_device(4, 16, 2, 0.99, 0.0, DeviceConfig::Cpu, Precision::F64).unwrap();
+        wide.load_checkpoint(&dir).unwrap();
+        let wide_obs = wide.obs_tensor(&obs).unwrap();
+        let widened: Vec<Vec<f64>> = wide.q_net.forward(&wide_obs).unwrap().to_vec2().unwrap();
+        for (w, e) in widened.iter().flatten().zip(expected.iter().flatten()) {
+            assert!((w - *e as f64).abs() < 1e-4);
+        }
+
+        // A differently shaped network must be rejected with a clear error
+        let mut wrong = DQNPolicy::new(4, 32, 2, 0.99, 0.0).unwrap();
+        let err = wrong.load_checkpoint(&dir).unwrap_err();
+        assert!(matches!(err, HabaError::Checkpoint(_)), "{}", err);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dqn_schedules_follow_progress() {
+        use crate::schedule::{Clock, Schedule};
+
+        let epsilon = Schedule::Linear {
+            start: 1.0,
+            end: 0.0,
+            duration: 100,
+        };
+        let lr = Scheduled::new(
+            Schedule::Linear {
+                start: 1e-3,
+                end: 1e-4,
+                duration: 10,
+            },
+            Clock::GradStep,
+        );
+        let mut policy = DQNPolicy::new(4, 16, 2, 0.99, epsilon).unwrap().with_lr(lr);
+        assert_eq!(policy.epsilon(), 1.0);
+
+        policy.set_progress(Progress {
+            env_step: 50,
+            grad_step: 10,
+        });
+        assert!((policy.epsilon() - 0.5).abs() < 1e-9);
+        assert!((policy.learning_rate() - 1e-4).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_dqn_eval_mode_is_greedy() {
+        let mut policy = DQNPolicy::new(4, 16, 2, 0.99, 1.0).unwrap();
+        policy.eval();
+        assert_eq!(policy.epsilon(), 0.0);
+
+        // With epsilon 1.0 in train mode actions would be random; eval must be repeatable
+        let obs = vec![vec![0.5, -0.5, 0.1, 0.2]; 8];
+        let first = policy.forward(&obs, None).unwrap().act;
+        for _ in 0..5 {
+            assert_eq!(policy.forward(&obs, None).unwrap().act, first);
+        }
+
+        policy.train();
+        assert_eq!(policy.epsilon(), 1.0);
     }
 }
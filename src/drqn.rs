@@ -0,0 +1,449 @@
+use crate::batch::Batch;
+use crate::device::{DeviceConfig, Precision};
+use crate::dqn::{
+    DQNManifest, epsilon_greedy, exploration_rate, read_checkpoint, read_training_state, scalar,
+    sync_target, write_checkpoint, write_training_state,
+};
+use crate::error::{HabaError, Result};
+use crate::model::{RecurrentKind, RecurrentQNet};
+use crate::optim::AdamW;
+use crate::policy::{HiddenState, Policy, PolicyMode, PolicyOutput};
+use crate::schedule::{Progress, Scheduled};
+use crate::stats::LearnStats;
+use candle_core::{DType, Device, Tensor};
+use candle_nn::{ParamsAdamW, VarBuilder, VarMap};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Network shape of a `DRQNPolicy`. Checkpoints only load into a policy with the same one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DRQNArch {
+    pub kind: RecurrentKind,
+    pub in_dim: usize,
+    pub hidden_dim: usize,
+    pub out_dim: usize,
+    /// Length of the runs the policy learns from.
+    pub seq_len: usize,
+}
+
+/// Deep Recurrent Q-Network (Hausknecht & Stone, 2015).
+///
+/// Acts with one hidden state per env and learns from runs of `seq_len`
+/// consecutive transitions, starting each run from the hidden state stored
+/// with its first transition.
+pub struct DRQNPolicy {
+    q_net: RecurrentQNet,
+    target_q_net: RecurrentQNet,
+    varmap: VarMap,
+    target_varmap: VarMap,
+    optimizer: AdamW,
+    device: Device,
+    precision: Precision,
+    arch: DRQNArch,
+
+    gamma: f64,
+    epsilon: Scheduled,
+    lr: Scheduled,
+    target_update_freq: usize,
+    update_count: usize,
+    progress: Progress,
+    mode: PolicyMode,
+    rng: ChaCha8Rng,
+}
+
+impl DRQNPolicy {
+    /// A DRQN policy on the CPU in f32. Use `from_arch` to choose otherwise.
+    pub fn new(
+        kind: RecurrentKind,
+        in_dim: usize,
+        hidden_dim: usize,
+        out_dim: usize,
+        seq_len: usize,
+        gamma: f64,
+        epsilon: impl Into<Scheduled>,
+    ) -> Result<Self> {
+        let arch = DRQNArch {
+            kind,
+            in_dim,
+            hidden_dim,
+            out_dim,
+            seq_len,
+        };
+        Self::from_arch(arch, gamma, epsilon, DeviceConfig::Cpu, Precision::F32)
+    }
+
+    pub fn from_arch(
+        arch: DRQNArch,
+        gamma: f64,
+        epsilon: impl Into<Scheduled>,
+        device: DeviceConfig,
+        precision: Precision,
+    ) -> Result<Self> {
+        if arch.seq_len == 0 {
+            return Err(HabaError::Config("seq_len must be positive".to_string()));
+        }
+        let device = device.resolve()?;
+        let dtype = precision.dtype();
+        let net =
+            |vb| RecurrentQNet::new(arch.kind, arch.in_dim, arch.hidden_dim, arch.out_dim, vb);
+        let varmap = VarMap::new();
+        let q_net = net(VarBuilder::from_varmap(&varmap, dtype, &device))?;
+        let target_varmap = VarMap::new();
+        let target_q_net = net(VarBuilder::from_varmap(&target_varmap, dtype, &device))?;
+
+        let lr = Scheduled::from(1e-3);
+        let progress = Progress::default();
+        let params = ParamsAdamW {
+            lr: lr.value(progress),
+            ..Default::default()
+        };
+        let vars = varmap.data().lock().unwrap().clone();
+        let optimizer = AdamW::new(vars, params)?;
+
+        let policy = Self {
+            q_net,
+            target_q_net,
+            varmap,
+            target_varmap,
+            optimizer,
+            device,
+            precision,
+            arch,
+            gamma,
+            epsilon: epsilon.into(),
+            lr,
+            target_update_freq: 100,
+            update_count: 0,
+            progress,
+            mode: PolicyMode::Train,
+            rng: ChaCha8Rng::from_entropy(),
+        };
+        sync_target(&policy.varmap, &policy.target_varmap)?;
+        Ok(policy)
+    }
+
+    /// Replace the learning rate (constant 1e-3 by default) with a schedule.
+    pub fn with_lr(mut self, lr: impl Into<Scheduled>) -> Self {
+        self.lr = lr.into();
+        self.optimizer
+            .set_learning_rate(self.lr.value(self.progress));
+        self
+    }
+
+    /// Copy the online network into the target every `freq` updates (100 by default).
+    pub fn with_target_update_freq(mut self, freq: usize) -> Self {
+        self.target_update_freq = freq.max(1);
+        self
+    }
+
+    /// Exploration rate at the current training progress. Always 0 in eval mode.
+    pub fn epsilon(&self) -> f64 {
+        exploration_rate(&self.epsilon, self.progress, self.mode)
+    }
+
+    pub fn arch(&self) -> &DRQNArch {
+        &self.arch
+    }
+
+    /// Number of gradient updates performed so far.
+    pub fn update_count(&self) -> usize {
+        self.update_count
+    }
+
+    // (rows, width) tensor in the policy's dtype, rejecting wrongly sized rows
+    fn rows(&self, rows: &[Vec<f64>], width: usize, what: &str) -> Result<Tensor> {
+        if let Some(bad) = rows.iter().find(|r| r.len() != width) {
+            return Err(HabaError::Shape(format!(
+                "expected {} of length {}, got {}",
+                what,
+                width,
+                bad.len()
+            )));
+        }
+        let flat: Vec<f64> = rows.iter().flatten().copied().collect();
+        Ok(Tensor::from_vec(flat, (rows.len(), width), &self.device)?
+            .to_dtype(self.precision.dtype())?)
+    }
+
+    // (B, 1) tensor in the policy's dtype
+    fn column(&self, values: Vec<f64>) -> Result<Tensor> {
+        let len = values.len();
+        Ok(Tensor::from_vec(values, (len, 1), &self.device)?.to_dtype(self.precision.dtype())?)
+    }
+
+    // Rows `b * seq_len + t` for every run b: timestep t of each run
+    fn timestep(&self, t: usize, n_runs: usize) -> Result<Tensor> {
+        let idx: Vec<u32> = (0..n_runs)
+            .map(|b| (b * self.arch.seq_len + t) as u32)
+            .collect();
+        Ok(Tensor::from_vec(idx, n_runs, &self.device)?)
+    }
+}
+
+impl Policy for DRQNPolicy {
+    type Observation = Vec<f64>;
+    type Action = usize;
+
+    fn forward(
+        &mut self,
+        obs: &[Self::Observation],
+        state: Option<&HiddenState>,
+    ) -> Result<PolicyOutput<Self::Action>> {
+        let xs = self.rows(obs, self.arch.in_dim, "observations")?;
+        let state = match state {
+            Some(s) => self.rows(s, self.q_net.core().state_dim(), "hidden states")?,
+            None => self.q_net.core().zero_state(obs.len())?,
+        };
+        let (q_values, next_state) = self.q_net.step(&xs, &state)?;
+        let greedy: Vec<u32> = q_values.argmax(1)?.to_vec1()?;
+        let act = epsilon_greedy(&greedy, self.epsilon(), self.arch.out_dim, &mut self.rng);
+        Ok(PolicyOutput {
+            state: Some(next_state.to_dtype(DType::F64)?.to_vec2()?),
+            ..PolicyOutput::new(act)
+        })
+    }
+
+    /// Expects `batch.seq_len == Some(seq_len)`. Without stored hidden states
+    /// every run starts from zeros.
+    fn learn(&mut self, batch: &Batch<Self::Observation, Self::Action>) -> Result<LearnStats> {
+        let DRQNArch {
+            in_dim,
+            out_dim,
+            seq_len,
+            ..
+        } = self.arch;
+        if batch.seq_len != Some(seq_len) || batch.len() % seq_len != 0 {
+            return Err(HabaError::Shape(format!(
+                "expected runs of {} transitions, got seq_len {:?} for {} transitions",
+                seq_len,
+                batch.seq_len,
+                batch.len()
+            )));
+        }
+        if let Some(bad) = batch.act.iter().find(|&&a| a >= out_dim) {
+            return Err(HabaError::Shape(format!(
+                "action {} out of range for {} Q-values",
+                bad, out_dim
+            )));
+        }
+        if self.update_count.is_multiple_of(self.target_update_freq) {
+            sync_target(&self.varmap, &self.target_varmap)?;
+        }
+
+        let n_runs = batch.len() / seq_len;
+        let obs = self.rows(&batch.obs, in_dim, "observations")?;
+        let obs_next = self.rows(&batch.obs_next, in_dim, "observations")?;
+        let act: Vec<u32> = batch.act.iter().map(|&a| a as u32).collect();
+        let act = Tensor::from_vec(act, (batch.len(), 1), &self.device)?;
+        let rew = self.column(batch.rew.clone())?;
+        let not_done = self.column(
+            batch
+                .done
+                .iter()
+                .map(|&d| if d { 0.0 } else { 1.0 })
+                .collect(),
+        )?;
+
+        let first = self.timestep(0, n_runs)?;
+        let mut state = match &batch.state {
+            Some(states) => self
+                .rows(states, self.q_net.core().state_dim(), "hidden states")?
+                .index_select(&first, 0)?,
+            None => self.q_net.core().zero_state(n_runs)?,
+        };
+        let mut target_state = state.clone();
+
+        let mut current = Vec::with_capacity(seq_len);
+        let mut targets = Vec::with_capacity(seq_len);
+        for t in 0..seq_len {
+            let idx = self.timestep(t, n_runs)?;
+            let obs_t = obs.index_select(&idx, 0)?;
+            let alive = not_done.index_select(&idx, 0)?;
+
+            let (q, next_state) = self.q_net.step(&obs_t, &state)?;
+            current.push(q.gather(&act.index_select(&idx, 0)?, 1)?);
+
+            // The target net sees the same history, then the next observation
+            let (_, next_target_state) = self.target_q_net.step(&obs_t, &target_state)?;
+            let (q_next, _) = self
+                .target_q_net
+                .step(&obs_next.index_select(&idx, 0)?, &next_target_state)?;
+            let max_next = q_next.max_keepdim(1)?;
+            let target =
+                (rew.index_select(&idx, 0)? + ((&alive * self.gamma)? * max_next)?)?.detach();
+            targets.push(target);
+
+            // An episode ending mid-run restarts from the initial state
+            state = next_state.broadcast_mul(&alive)?;
+            target_state = next_target_state.broadcast_mul(&alive)?.detach();
+        }
+
+        let current_q = Tensor::cat(&current, 0)?;
+        let td_error = (&current_q - Tensor::cat(&targets, 0)?)?;
+        let loss = td_error.sqr()?.mean_all()?;
+
+        let grads = loss.backward()?;
+        let grad_norm = self.optimizer.grad_norm(&grads)?;
+        self.optimizer.step(&grads)?;
+        self.update_count += 1;
+
+        let mut stats = LearnStats::new();
+        stats.insert("loss", scalar(&loss)?);
+        stats.insert("q_mean", scalar(&current_q.mean_all()?)?);
+        stats.insert("td_error", scalar(&td_error.abs()?.mean_all()?)?);
+        stats.insert("grad_norm", grad_norm);
+        stats.insert("lr", self.optimizer.learning_rate());
+        Ok(stats)
+    }
+
+    fn sequence_len(&self) -> Option<usize> {
+        Some(self.arch.seq_len)
+    }
+
+    /// Writes both networks plus a manifest, in the same layout as DQN.
+    fn save_checkpoint(&self, dir: &Path) -> Result<()> {
+        let manifest = DQNManifest {
+            algorithm: "drqn".to_string(),
+            haba_version: env!("CARGO_PKG_VERSION").to_string(),
+            arch: self.arch,
+            precision: self.precision,
+            gamma: self.gamma,
+            epsilon: self.epsilon.clone(),
+            lr: self.lr.clone(),
+            target_update_freq: self.target_update_freq,
+            update_count: self.update_count,
+        };
+        write_checkpoint(dir, &self.varmap, &self.target_varmap, &manifest)
+    }
+
+    fn load_checkpoint(&mut self, dir: &Path) -> Result<()> {
+        let manifest = read_checkpoint(dir, "drqn", &self.arch, &self.varmap, &self.target_varmap)?;
+        self.gamma = manifest.gamma;
+        self.epsilon = manifest.epsilon;
+        self.lr = manifest.lr;
+        self.target_update_freq = manifest.target_update_freq;
+        self.update_count = manifest.update_count;
+        self.optimizer
+            .set_learning_rate(self.lr.value(self.progress));
+        Ok(())
+    }
+
+    fn save_training_state(&self, dir: &Path) -> Result<()> {
+        self.save_checkpoint(dir)?;
+        write_training_state(dir, &self.optimizer, self.progress, &self.rng)
+    }
+
+    fn load_training_state(&mut self, dir: &Path) -> Result<()> {
+        self.load_checkpoint(dir)?;
+        let state = read_training_state(dir, &mut self.optimizer)?;
+        self.rng = state.rng;
+        self.set_progress(state.progress);
+        Ok(())
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    fn set_progress(&mut self, progress: Progress) {
+        self.progress = progress;
+        self.optimizer.set_learning_rate(self.lr.value(progress));
+    }
+
+    fn set_mode(&mut self, mode: PolicyMode) {
+        self.mode = mode;
+    }
+
+    fn mode(&self) -> PolicyMode {
+        self.mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::PolicyExtra;
+    use crate::buffer::ReplayBuffer;
+
+    #[test]
+    fn test_drqn_forward_threads_state() {
+        let mut policy = DRQNPolicy::new(RecurrentKind::Lstm, 2, 8, 2, 4, 0.99, 0.0).unwrap();
+        let obs = vec![vec![0.1, 0.2]; 3];
+        let out = policy.forward(&obs, None).unwrap();
+        let state = out.state.unwrap();
+        assert_eq!(state.len(), 3);
+        assert_eq!(state[0].len(), 16);
+
+        assert!(policy.forward(&obs, Some(&state)).is_ok());
+        let bad = vec![vec![0.0; 3]; 3];
+        assert!(matches!(
+            policy.forward(&obs, Some(&bad)),
+            Err(HabaError::Shape(_))
+        ));
+    }
+
+    #[test]
+    fn test_drqn_learns_from_sequences() {
+        let mut policy = DRQNPolicy::new(RecurrentKind::Gru, 2, 8, 2, 4, 0.99, 0.0).unwrap();
+        let mut buffer = ReplayBuffer::new(100);
+        let mut state = None;
+        for t in 0..20 {
+            let obs = vec![vec![t as f64 / 20.0, 1.0]];
+            let out = policy.forward(&obs, state.as_ref()).unwrap();
+            let extra = PolicyExtra {
+                state: state.as_ref().map(|s: &HiddenState| s[0].clone()),
+                ..Default::default()
+            };
+            let next = vec![(t + 1) as f64 / 20.0, 1.0];
+            buffer.add_from_env(0, obs[0].clone(), out.act[0], 1.0, t % 10 == 9, next, extra);
+            state = out.state;
+        }
+
+        let batch = buffer.sample_sequences(3, 4).unwrap();
+        let stats = policy.learn(&batch).unwrap();
+        assert!(stats.get("loss").unwrap().is_finite());
+        assert!(stats.get("grad_norm").unwrap() > 0.0);
+
+        // Single transitions are rejected
+        assert!(policy.learn(&buffer.sample(4)).is_err());
+    }
+
+    #[test]
+    fn test_drqn_checkpoint_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("haba_drqn_ckpt_{}", std::process::id()));
+        let arch = DRQNArch {
+            kind: RecurrentKind::Gru,
+            in_dim: 2,
+            hidden_dim: 8,
+            out_dim: 2,
+            seq_len: 4,
+        };
+        let mut policy =
+            DRQNPolicy::from_arch(arch, 0.9, 0.0, DeviceConfig::Cpu, Precision::F64).unwrap();
+        policy.set_progress(Progress {
+            env_step: 10,
+            grad_step: 3,
+        });
+        policy.save_training_state(&dir).unwrap();
+
+        // Loads at another precision, with the same greedy actions
+        let mut restored = DRQNPolicy::new(RecurrentKind::Gru, 2, 8, 2, 4, 0.99, 0.0).unwrap();
+        restored.load_training_state(&dir).unwrap();
+        assert_eq!(restored.gamma, 0.9);
+        assert_eq!(restored.progress.grad_step, 3);
+        let obs: Vec<Vec<f64>> = (0..8).map(|i| vec![i as f64 / 4.0, -1.0]).collect();
+        assert_eq!(
+            policy.forward(&obs, None).unwrap().act,
+            restored.forward(&obs, None).unwrap().act
+        );
+
+        let mut wrong = DRQNPolicy::new(RecurrentKind::Lstm, 2, 8, 2, 4, 0.99, 0.0).unwrap();
+        assert!(matches!(
+            wrong.load_checkpoint(&dir),
+            Err(HabaError::Checkpoint(_))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,7 +1,15 @@
+use crate::checkpoint;
+use crate::error::HabaError;
+use crate::spaces::Space;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
 
 //Handle illegal inputs
-pub type EnvResult<T> = Result<T, String>;
+pub type EnvResult<T> = Result<T, HabaError>;
 
 //Step struct reture the sequence of observations
 #[derive(Debug)]
@@ -9,7 +17,229 @@ pub struct Step<O> {
     pub obs: O,
     pub done: bool,
     pub reward: f64,
-    pub info: Option<HashMap<String, String>>,
+    pub info: Option<Info>,
+}
+
+/// Diagnostics reported alongside a step, keyed by name.
+pub type Info = HashMap<String, InfoValue>;
+
+/// One typed diagnostic value in an `Info` map. Serialized tagged with its
+/// type, with NaN and the infinities written as the strings "NaN", "inf"
+/// and "-inf", since JSON has no numbers for them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InfoValue {
+    Bool(bool),
+    Int(i64),
+    Float(#[serde(with = "json_float")] f64),
+    Vector(#[serde(with = "json_float::vec")] Vec<f64>),
+    Text(String),
+    Map(Info),
+}
+
+mod json_float {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Number(f64),
+        Text(String),
+    }
+
+    fn encode(v: f64) -> Repr {
+        match v {
+            v if v.is_finite() => Repr::Number(v),
+            v if v.is_nan() => Repr::Text("NaN".to_string()),
+            v if v > 0.0 => Repr::Text("inf".to_string()),
+            _ => Repr::Text("-inf".to_string()),
+        }
+    }
+
+    fn decode(repr: Repr) -> Result<f64, String> {
+        match repr {
+            Repr::Number(v) => Ok(v),
+            Repr::Text(s) => match s.as_str() {
+                "NaN" => Ok(f64::NAN),
+                "inf" => Ok(f64::INFINITY),
+                "-inf" => Ok(f64::NEG_INFINITY),
+                _ => Err(format!("invalid float {:?}", s)),
+            },
+        }
+    }
+
+    pub fn serialize<S: Serializer>(v: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        encode(*v).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        decode(Repr::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+
+    pub mod vec {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(v: &[f64], serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(v.iter().map(|&x| encode(x)))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Vec<f64>, D::Error> {
+            Vec::<Repr>::deserialize(deserializer)?
+                .into_iter()
+                .map(decode)
+                .collect::<Result<_, _>>()
+                .map_err(D::Error::custom)
+        }
+    }
+}
+
+impl InfoValue {
+    /// The value as a number, if it is one. Integers are converted.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            InfoValue::Int(v) => Some(v as f64),
+            InfoValue::Float(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            InfoValue::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_vector(&self) -> Option<&[f64]> {
+        match self {
+            InfoValue::Vector(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            InfoValue::Text(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&Info> {
+        match self {
+            InfoValue::Map(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for InfoValue {
+    fn from(v: bool) -> Self {
+        InfoValue::Bool(v)
+    }
+}
+
+impl From<i64> for InfoValue {
+    fn from(v: i64) -> Self {
+        InfoValue::Int(v)
+    }
+}
+
+impl From<usize> for InfoValue {
+    fn from(v: usize) -> Self {
+        InfoValue::Int(v as i64)
+    }
+}
+
+impl From<f64> for InfoValue {
+    fn from(v: f64) -> Self {
+        InfoValue::Float(v)
+    }
+}
+
+impl From<Vec<f64>> for InfoValue {
+    fn from(v: Vec<f64>) -> Self {
+        InfoValue::Vector(v)
+    }
+}
+
+impl From<String> for InfoValue {
+    fn from(v: String) -> Self {
+        InfoValue::Text(v)
+    }
+}
+
+impl From<&str> for InfoValue {
+    fn from(v: &str) -> Self {
+        InfoValue::Text(v.to_string())
+    }
+}
+
+impl From<Info> for InfoValue {
+    fn from(v: Info) -> Self {
+        InfoValue::Map(v)
+    }
+}
+
+/// Where an environment is in its episode, for rejecting out-of-order calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Phase {
+    NeedsReset,
+    Running,
+    Terminated,
+}
+
+impl Phase {
+    /// Error unless a step is allowed: after `reset` and before termination.
+    pub(crate) fn check_step(self, env: &str) -> EnvResult<()> {
+        match self {
+            Phase::Running => Ok(()),
+            Phase::NeedsReset => Err(HabaError::Env(format!("{} stepped before reset", env))),
+            Phase::Terminated => Err(HabaError::Env(format!(
+                "{} stepped after the episode terminated; call reset",
+                env
+            ))),
+        }
+    }
+}
+
+/// Physical state, episode phase and RNG of a simulated env, with the
+/// seeding and checkpointing every such env shares.
+pub(crate) struct EnvCore<S> {
+    pub(crate) state: S,
+    pub(crate) phase: Phase,
+    pub(crate) rng: ChaCha8Rng,
+}
+
+impl<S: Default + Serialize + DeserializeOwned> EnvCore<S> {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: S::default(),
+            phase: Phase::NeedsReset,
+            rng: ChaCha8Rng::from_entropy(),
+        }
+    }
+
+    pub(crate) fn seed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    pub(crate) fn state_dict(&self) -> EnvResult<Value> {
+        Ok(serde_json::json!({
+            "state": self.state,
+            "phase": self.phase,
+            "rng": self.rng,
+        }))
+    }
+
+    pub(crate) fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.state = checkpoint::field(state, "state")?;
+        self.phase = checkpoint::field(state, "phase")?;
+        self.rng = checkpoint::field(state, "rng")?;
+        Ok(())
+    }
 }
 
 pub trait Environment {
@@ -18,4 +248,92 @@ pub trait Environment {
 
     fn reset(&mut self) -> EnvResult<Self::Observation>;
     fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>>;
+
+    fn observation_space(&self) -> Space;
+    fn action_space(&self) -> Space;
+
+    /// Seed the environment's randomness, taking effect from the next
+    /// `reset`. Deterministic environments can ignore it.
+    fn seed(&mut self, _seed: u64) {}
+
+    // Full internal state, so a training run can be checkpointed and resumed exactly
+    fn state_dict(&self) -> EnvResult<Value> {
+        Err(checkpoint::unsupported("this environment"))
+    }
+
+    fn load_state_dict(&mut self, _state: &Value) -> EnvResult<()> {
+        Err(checkpoint::unsupported("this environment"))
+    }
+}
+
+/// An environment with named scalar parameters, such as physical constants.
+pub trait Parameterized {
+    fn param(&self, name: &str) -> Option<f64>;
+    fn set_param(&mut self, name: &str, value: f64) -> EnvResult<()>;
+}
+
+// Lets boxed environments, e.g. from `registry::make`, be used anywhere
+impl<E: Environment + ?Sized> Environment for Box<E> {
+    type Observation = E::Observation;
+    type Action = E::Action;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        (**self).reset()
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        (**self).step(action)
+    }
+
+    fn observation_space(&self) -> Space {
+        (**self).observation_space()
+    }
+
+    fn action_space(&self) -> Space {
+        (**self).action_space()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        (**self).seed(seed)
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        (**self).state_dict()
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        (**self).load_state_dict(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_info_roundtrips_through_json() {
+        let nested = Info::from([("nan".to_string(), InfoValue::Float(f64::NAN))]);
+        let info = Info::from([
+            ("done".to_string(), InfoValue::Bool(true)),
+            ("steps".to_string(), InfoValue::Int(3)),
+            ("inf".to_string(), InfoValue::Float(f64::INFINITY)),
+            ("vec".to_string(), vec![1.5, f64::NEG_INFINITY].into()),
+            ("text".to_string(), "NaN".into()),
+            ("map".to_string(), nested.into()),
+        ]);
+        let json = serde_json::to_string(&info).unwrap();
+        let loaded: Info = serde_json::from_str(&json).unwrap();
+        // NaN != NaN, so compare the serialized forms
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::from_str::<Value>(&json).unwrap()
+        );
+        assert_eq!(loaded["text"], InfoValue::Text("NaN".to_string()));
+        assert_eq!(loaded["inf"].as_f64(), Some(f64::INFINITY));
+        let map = loaded["map"].as_map().unwrap();
+        assert!(map["nan"].as_f64().unwrap().is_nan());
+
+        let bad: serde_json::Result<InfoValue> = serde_json::from_str(r#"{"float": "nan?"}"#);
+        assert!(bad.is_err());
+    }
 }
@@ -0,0 +1,389 @@
+use crate::env::{Environment, Step};
+use crate::spaces::SpaceElement;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::fmt;
+
+/// Which invariant an environment broke.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// `step` succeeded before the first `reset`.
+    StepBeforeReset,
+    /// `reset` or `step` returned an error during a valid episode.
+    EnvError,
+    /// An action of the action space could not be generated.
+    UnsampleableActionSpace,
+    /// An observation lay outside the observation space.
+    ObservationOutOfSpace,
+    /// A reward was NaN or infinite.
+    NonFiniteReward,
+    /// Two runs with the same seed and actions diverged.
+    NonDeterministic,
+    /// Restoring a `state_dict` did not reproduce the same trajectory.
+    StateDictMismatch,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    /// Probing episode and step (0 = `reset`) where it happened, if any.
+    pub episode: Option<usize>,
+    pub step: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.kind)?;
+        match (self.episode, self.step) {
+            (Some(episode), Some(step)) => write!(f, " (episode {}, step {})", episode, step)?,
+            (None, Some(step)) => write!(f, " (step {})", step)?,
+            _ => {}
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// The outcome of `check_env`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub violations: Vec<Violation>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    pub fn has(&self, kind: ViolationKind) -> bool {
+        self.violations.iter().any(|v| v.kind == kind)
+    }
+
+    fn add(
+        &mut self,
+        kind: ViolationKind,
+        episode: Option<usize>,
+        step: Option<usize>,
+        message: String,
+    ) {
+        self.violations.push(Violation {
+            kind,
+            episode,
+            step,
+            message,
+        });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "environment passed all checks");
+        }
+        writeln!(f, "{} violation(s):", self.violations.len())?;
+        for violation in &self.violations {
+            writeln!(f, "  {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+/// How hard `check_env_with` probes.
+#[derive(Debug, Clone)]
+pub struct CheckConfig {
+    /// Episodes of random actions.
+    pub episodes: usize,
+    /// Steps after which a probing episode is cut short.
+    pub max_steps: usize,
+    /// Seed for the environment and the random actions.
+    pub seed: u64,
+}
+
+impl Default for CheckConfig {
+    fn default() -> Self {
+        Self {
+            episodes: 3,
+            max_steps: 200,
+            seed: 0,
+        }
+    }
+}
+
+/// Probe a freshly constructed environment for broken invariants with the
+/// default `CheckConfig`.
+pub fn check_env<E>(env: &mut E) -> Report
+where
+    E: Environment,
+    E::Observation: SpaceElement + PartialEq,
+    E::Action: SpaceElement + Clone,
+{
+    check_env_with(env, &CheckConfig::default())
+}
+
+/// Run random probing episodes and report every violation found:
+/// `step` must fail before the first `reset`, observations must lie in the
+/// observation space, rewards must be finite, episodes must replay exactly
+/// under the same seed, and, if the env supports checkpointing, restoring a
+/// `state_dict` must replay the same trajectory.
+pub fn check_env_with<E>(env: &mut E, config: &CheckConfig) -> Report
+where
+    E: Environment,
+    E::Observation: SpaceElement + PartialEq,
+    E::Action: SpaceElement + Clone,
+{
+    let mut report = Report::default();
+    let mut rng = ChaCha8Rng::seed_from_u64(config.seed);
+    let action_space = env.action_space();
+    let Some(first_action) = action_space.sample::<E::Action, _>(&mut rng) else {
+        report.add(
+            ViolationKind::UnsampleableActionSpace,
+            None,
+            None,
+            format!("cannot sample an action from {:?}", action_space),
+        );
+        return report;
+    };
+
+    if env.step(first_action).is_ok() {
+        report.add(
+            ViolationKind::StepBeforeReset,
+            None,
+            Some(1),
+            "step before the first reset should return an error".to_string(),
+        );
+    }
+
+    // Random episodes, checking every observation and reward
+    let observation_space = env.observation_space();
+    for episode in 0..config.episodes {
+        let actions = random_actions(env, config.seed + episode as u64, config.max_steps);
+        env.seed(config.seed + episode as u64);
+        let trajectory = match rollout(env, &actions) {
+            Ok(trajectory) => trajectory,
+            Err((step, message)) => {
+                report.add(ViolationKind::EnvError, Some(episode), Some(step), message);
+                continue;
+            }
+        };
+        for (step, (obs, reward, _)) in trajectory.iter().enumerate() {
+            if !observation_space.contains(obs) {
+                report.add(
+                    ViolationKind::ObservationOutOfSpace,
+                    Some(episode),
+                    Some(step),
+                    format!("observation not in {:?}", observation_space),
+                );
+            }
+            if !reward.is_finite() {
+                report.add(
+                    ViolationKind::NonFiniteReward,
+                    Some(episode),
+                    Some(step),
+                    format!("reward {}", reward),
+                );
+            }
+        }
+    }
+
+    // Same seed and actions must give the same trajectory
+    let actions = random_actions(env, config.seed, config.max_steps);
+    env.seed(config.seed);
+    let first = rollout(env, &actions);
+    env.seed(config.seed);
+    let second = rollout(env, &actions);
+    if let (Ok(first), Ok(second)) = (&first, &second)
+        && let Some(step) = first_difference(first, second)
+    {
+        report.add(
+            ViolationKind::NonDeterministic,
+            None,
+            Some(step),
+            "two runs with the same seed and actions diverged".to_string(),
+        );
+    }
+
+    check_state_dict(env, config, &mut report);
+    report
+}
+
+// (observation, reward, done) from `reset` (step 0) onwards
+type Trajectory<O> = Vec<(O, f64, bool)>;
+
+fn random_actions<E>(env: &E, seed: u64, n: usize) -> Vec<E::Action>
+where
+    E: Environment,
+    E::Action: SpaceElement,
+{
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let space = env.action_space();
+    (0..n).filter_map(|_| space.sample(&mut rng)).collect()
+}
+
+// Reset, then play `actions` until the episode ends. Errors carry the step.
+fn rollout<E>(
+    env: &mut E,
+    actions: &[E::Action],
+) -> Result<Trajectory<E::Observation>, (usize, String)>
+where
+    E: Environment,
+    E::Action: Clone,
+{
+    let obs = env
+        .reset()
+        .map_err(|e| (0, format!("reset failed: {}", e)))?;
+    let mut trajectory = vec![(obs, 0.0, false)];
+    play(env, actions, &mut trajectory, 1)?;
+    Ok(trajectory)
+}
+
+fn play<E>(
+    env: &mut E,
+    actions: &[E::Action],
+    trajectory: &mut Trajectory<E::Observation>,
+    first_step: usize,
+) -> Result<(), (usize, String)>
+where
+    E: Environment,
+    E::Action: Clone,
+{
+    for (i, action) in actions.iter().enumerate() {
+        let Step {
+            obs, reward, done, ..
+        } = env
+            .step(action.clone())
+            .map_err(|e| (first_step + i, format!("step failed: {}", e)))?;
+        trajectory.push((obs, reward, done));
+        if done {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn first_difference<O: PartialEq>(a: &Trajectory<O>, b: &Trajectory<O>) -> Option<usize> {
+    let same = |x: &(O, f64, bool), y: &(O, f64, bool)| {
+        x.0 == y.0 && x.2 == y.2 && (x.1 == y.1 || (x.1.is_nan() && y.1.is_nan()))
+    };
+    match a.iter().zip(b).position(|(x, y)| !same(x, y)) {
+        Some(step) => Some(step),
+        None if a.len() != b.len() => Some(a.len().min(b.len())),
+        None => None,
+    }
+}
+
+// Snapshot mid-episode, play on, restore and replay. Skipped for envs
+// without checkpoint support.
+fn check_state_dict<E>(env: &mut E, config: &CheckConfig, report: &mut Report)
+where
+    E: Environment,
+    E::Observation: PartialEq,
+    E::Action: SpaceElement + Clone,
+{
+    let actions = random_actions(env, config.seed + 1, config.max_steps);
+    let (warmup, rest) = actions.split_at(actions.len().min(5));
+    env.seed(config.seed);
+    let Ok(prefix) = rollout(env, warmup) else {
+        return;
+    };
+    if prefix.last().is_some_and(|(_, _, done)| *done) {
+        return;
+    }
+    let Ok(snapshot) = env.state_dict() else {
+        return;
+    };
+    let step = prefix.len();
+    let mut first = Vec::new();
+    if play(env, rest, &mut first, step).is_err() {
+        return;
+    }
+    if let Err(e) = env.load_state_dict(&snapshot) {
+        report.add(
+            ViolationKind::StateDictMismatch,
+            None,
+            Some(step),
+            format!("cannot load its own state_dict: {}", e),
+        );
+        return;
+    }
+    let mut second = Vec::new();
+    let replayed = play(env, rest, &mut second, step);
+    if replayed.is_err() || first_difference(&first, &second).is_some() {
+        report.add(
+            ViolationKind::StateDictMismatch,
+            None,
+            Some(step),
+            "restoring a state_dict did not reproduce the trajectory".to_string(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartpole::{CartPole, MaskedCartPole};
+    use crate::env::EnvResult;
+    use crate::mock::MockEnv;
+    use crate::spaces::Space;
+    use crate::wrappers::TimeLimit;
+    use rand::Rng;
+
+    #[test]
+    fn test_builtin_envs_pass() {
+        let report = check_env(&mut TimeLimit::new(CartPole::new(), 500));
+        assert!(report.is_ok(), "{}", report);
+        let report = check_env(&mut MaskedCartPole::new());
+        assert!(report.is_ok(), "{}", report);
+        let report = check_env(&mut MockEnv::new(10));
+        assert!(report.is_ok(), "{}", report);
+    }
+
+    // Breaks most invariants at once
+    struct Broken {
+        rng: ChaCha8Rng,
+    }
+
+    impl Environment for Broken {
+        type Observation = Vec<f64>;
+        type Action = usize;
+
+        fn reset(&mut self) -> EnvResult<Self::Observation> {
+            Ok(vec![0.0])
+        }
+
+        fn step(&mut self, _action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+            let x: f64 = self.rng.gen_range(0.0..2.0);
+            Ok(Step {
+                obs: vec![x],
+                done: false,
+                reward: if x > 1.9 { f64::NAN } else { 0.0 },
+                info: None,
+            })
+        }
+
+        fn observation_space(&self) -> Space {
+            Space::uniform(&[1], 0.0, 1.0)
+        }
+
+        fn action_space(&self) -> Space {
+            Space::discrete(2)
+        }
+    }
+
+    #[test]
+    fn test_violations_are_reported() {
+        let mut env = Broken {
+            rng: ChaCha8Rng::seed_from_u64(0),
+        };
+        let report = check_env(&mut env);
+        for kind in [
+            ViolationKind::StepBeforeReset,
+            ViolationKind::ObservationOutOfSpace,
+            ViolationKind::NonFiniteReward,
+            ViolationKind::NonDeterministic,
+        ] {
+            assert!(report.has(kind), "{:?} missing from {}", kind, report);
+        }
+        // No checkpoint support, so that check is skipped
+        assert!(!report.has(ViolationKind::StateDictMismatch));
+    }
+}
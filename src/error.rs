@@ -0,0 +1,58 @@
+use std::fmt;
+
+/// Errors surfaced by environments, policies, collectors and trainers.
+#[derive(Debug)]
+pub enum HabaError {
+    /// An environment rejected a call or failed internally.
+    Env(String),
+    /// Data did not have the expected size or shape.
+    Shape(String),
+    /// The requested compute device is unavailable or failed.
+    Device(String),
+    /// Reading or writing files failed.
+    Io(std::io::Error),
+    /// Invalid or inconsistent configuration.
+    Config(String),
+    /// A checkpoint is malformed or does not match the object loading it.
+    Checkpoint(String),
+    /// A tensor operation failed.
+    Tensor(candle_core::Error),
+}
+
+pub type Result<T> = std::result::Result<T, HabaError>;
+
+impl fmt::Display for HabaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HabaError::Env(msg) => write!(f, "environment error: {}", msg),
+            HabaError::Shape(msg) => write!(f, "shape error: {}", msg),
+            HabaError::Device(msg) => write!(f, "device error: {}", msg),
+            HabaError::Io(err) => write!(f, "io error: {}", err),
+            HabaError::Config(msg) => write!(f, "config error: {}", msg),
+            HabaError::Checkpoint(msg) => write!(f, "checkpoint error: {}", msg),
+            HabaError::Tensor(err) => write!(f, "tensor error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for HabaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HabaError::Io(err) => Some(err),
+            HabaError::Tensor(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for HabaError {
+    fn from(err: std::io::Error) -> Self {
+        HabaError::Io(err)
+    }
+}
+
+impl From<candle_core::Error> for HabaError {
+    fn from(err: candle_core::Error) -> Self {
+        HabaError::Tensor(err)
+    }
+}
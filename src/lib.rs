@@ -1,11 +1,36 @@
+#![allow(non_snake_case)]
+
+pub mod acrobot;
+pub mod bandits;
 pub mod batch;
 pub mod buffer;
 pub mod cartpole;
+pub mod checkpoint;
 pub mod collector;
+pub mod device;
+pub mod dp;
 pub mod dqn;
+pub mod drqn;
 pub mod env;
+pub mod env_checker;
+pub mod error;
 pub mod mock;
 pub mod model;
+pub mod mountain_car;
+pub mod normalize;
+pub mod optim;
+pub mod pendulum;
 pub mod policy;
+pub mod preprocess;
+pub mod registry;
+pub mod schedule;
+pub mod spaces;
+pub mod stats;
+pub mod tabular;
+pub mod toy_text;
 pub mod trainer;
+mod util;
 pub mod venv;
+pub mod wrappers;
+
+pub use registry::{make, make_vec};
@@ -1,23 +1,23 @@
+#![allow(non_snake_case)]
+
 use Haba::buffer::ReplayBuffer;
-use Haba::cartpole::CartPole;
 use Haba::collector::Collector;
 use Haba::dqn::DQNPolicy;
+use Haba::error::HabaError;
+use Haba::registry::VectorMode;
 use Haba::trainer::Trainer;
-use Haba::venv::DummyVectorEnv;
 
-fn main() -> Result<(), String> {
+fn main() -> Result<(), HabaError> {
     // 1. Initialize the World and Agent
-    let env = CartPole::new(1000);
-
-    let venv = DummyVectorEnv::new(vec![env]);
+    let venv = Haba::make_vec::<Vec<f64>, usize>("CartPole-v1", 1, VectorMode::Dummy)?;
 
     // 2. Define Policy
-    let policy = DQNPolicy::new(4, 64, 2, 0.99, 0.1).unwrap();
+    let policy = DQNPolicy::new(4, 64, 2, 0.99, 0.1)?;
 
     // 3. Initialize Collector
     // Buffer size: capacity for raw transitions.
     let buffer = ReplayBuffer::new(10000);
-    let collector = Collector::new(venv, policy, Some(buffer));
+    let collector = Collector::new(venv, policy, Some(buffer))?;
 
     // 4. Initialize Trainer
     let mut trainer = Trainer::new(collector, 200, 1000, 64);
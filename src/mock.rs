@@ -1,10 +1,16 @@
 use crate::env::{EnvResult, Environment, Step};
+use crate::error::HabaError;
+use crate::spaces::Space;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MockEnv {
     obs: f64,
     count: usize,
     max_steps: usize,
+    #[serde(skip)]
+    ready: bool,
 }
 
 impl MockEnv {
@@ -13,6 +19,7 @@ impl MockEnv {
             obs: 0.0,
             count: 0,
             max_steps,
+            ready: false,
         }
     }
 }
@@ -22,6 +29,9 @@ impl Environment for MockEnv {
     type Action = ();
 
     fn step(&mut self, _action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        if !self.ready {
+            return Err(HabaError::Env("MockEnv stepped before reset".to_string()));
+        }
         self.count += 1;
         self.obs += 1.0;
         let done = self.count >= self.max_steps;
@@ -37,6 +47,26 @@ impl Environment for MockEnv {
     fn reset(&mut self) -> EnvResult<Self::Observation> {
         self.obs = 0.0;
         self.count = 0;
+        self.ready = true;
         Ok(self.obs)
     }
+
+    fn observation_space(&self) -> Space {
+        Space::uniform(&[1], 0.0, self.max_steps as f64)
+    }
+
+    fn action_space(&self) -> Space {
+        Space::discrete(1)
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        serde_json::to_value(self).map_err(|e| HabaError::Checkpoint(e.to_string()))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        *self = serde_json::from_value(state.clone())
+            .map_err(|e| HabaError::Checkpoint(e.to_string()))?;
+        self.ready = true;
+        Ok(())
+    }
 }
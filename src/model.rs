@@ -1,26 +1,726 @@
-use candle_core::{Result, Tensor};
-use candle_nn::{Linear, Module, VarBuilder, linear};
+use candle_core::{Result, Tensor, bail};
+use candle_nn::rnn::{GRUState, LSTMState};
+use candle_nn::{
+    Conv2d, Conv2dConfig, Dropout, GRU, LSTM, LayerNorm, Linear, Module, RNN, VarBuilder, conv2d,
+    gru, layer_norm, linear, lstm,
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Activation {
+    #[default]
+    ReLU,
+    Tanh,
+    Gelu,
+    Silu,
+}
+
+impl Activation {
+    pub fn apply(&self, xs: &Tensor) -> Result<Tensor> {
+        match self {
+            Activation::ReLU => xs.relu(),
+            Activation::Tanh => xs.tanh(),
+            Activation::Gelu => xs.gelu(),
+            Activation::Silu => xs.silu(),
+        }
+    }
+}
+
+/// How the weights of new linear layers are initialized. Biases start at
+/// zero except with `Default`, which keeps candle's own initialization.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum WeightInit {
+    #[default]
+    Default,
+    Orthogonal {
+        gain: f64,
+    },
+    Xavier {
+        gain: f64,
+    },
+}
+
+/// Builds an `Mlp`. Linear layers are named `fc1..fcN` and layer norms
+/// `ln1..`, so checkpoints stay readable across configurations.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MlpBuilder {
+    in_dim: usize,
+    out_dim: usize,
+    hidden: Vec<usize>,
+    activation: Activation,
+    layer_norm: bool,
+    dropout: Option<f32>,
+    init: WeightInit,
+    output_scale: f64,
+    #[serde(default)]
+    seed: Option<u64>,
+}
+
+impl MlpBuilder {
+    pub fn new(in_dim: usize, out_dim: usize) -> Self {
+        Self {
+            in_dim,
+            out_dim,
+            hidden: Vec::new(),
+            activation: Activation::default(),
+            layer_norm: false,
+            dropout: None,
+            init: WeightInit::default(),
+            output_scale: 1.0,
+            seed: None,
+        }
+    }
+
+    pub fn in_dim(&self) -> usize {
+        self.in_dim
+    }
+
+    pub fn out_dim(&self) -> usize {
+        self.out_dim
+    }
+
+    pub fn hidden(mut self, sizes: &[usize]) -> Self {
+        self.hidden = sizes.to_vec();
+        self
+    }
+
+    pub fn activation(mut self, activation: Activation) -> Self {
+        self.activation = activation;
+        self
+    }
+
+    /// Normalize every hidden layer before its activation.
+    pub fn layer_norm(mut self, enabled: bool) -> Self {
+        self.layer_norm = enabled;
+        self
+    }
+
+    /// Dropout after every hidden activation, active only in training.
+    pub fn dropout(mut self, p: f32) -> Self {
+        self.dropout = Some(p);
+        self
+    }
+
+    pub fn init(mut self, init: WeightInit) -> Self {
+        self.init = init;
+        self
+    }
+
+    /// Multiply the initial weights of the output layer, e.g. 0.01 for
+    /// policy heads that should start close to uniform.
+    pub fn output_scale(mut self, scale: f64) -> Self {
+        self.output_scale = scale;
+        self
+    }
+
+    /// Draw `Orthogonal` and `Xavier` weights from a generator seeded with
+    /// `seed`, so the same builder always gives the same network. Unseeded
+    /// builders draw from entropy; `Default` always uses candle's own init.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn build(&self, vb: VarBuilder) -> Result<Mlp> {
+        let mut dims = vec![self.in_dim];
+        dims.extend(&self.hidden);
+        dims.push(self.out_dim);
+
+        let n_layers = dims.len() - 1;
+        let mut rng = match self.seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_entropy(),
+        };
+        let mut layers = Vec::with_capacity(n_layers);
+        let mut norms = Vec::new();
+        for (i, pair) in dims.windows(2).enumerate() {
+            let is_output = i + 1 == n_layers;
+            let scale = if is_output { self.output_scale } else { 1.0 };
+            let name = format!("fc{}", i + 1);
+            layers.push(init_linear(
+                pair[0],
+                pair[1],
+                self.init,
+                scale,
+                &mut rng,
+                vb.pp(name),
+            )?);
+            if self.layer_norm && !is_output {
+                norms.push(layer_norm(pair[1], 1e-5, vb.pp(format!("ln{}", i + 1)))?);
+            }
+        }
+
+        Ok(Mlp {
+            layers,
+            norms,
+            activation: self.activation,
+            dropout: self.dropout.map(Dropout::new),
+        })
+    }
+}
 
+#[derive(Debug, Clone)]
+pub struct Mlp {
+    layers: Vec<Linear>,
+    norms: Vec<LayerNorm>,
+    activation: Activation,
+    dropout: Option<Dropout>,
+}
+
+impl Mlp {
+    /// Forward pass; `train` enables dropout.
+    pub fn forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor> {
+        let (output, hidden) = self
+            .layers
+            .split_last()
+            .expect("an MLP has an output layer");
+        let mut xs = xs.clone();
+        for (i, layer) in hidden.iter().enumerate() {
+            xs = layer.forward(&xs)?;
+            if let Some(norm) = self.norms.get(i) {
+                xs = norm.forward(&xs)?;
+            }
+            xs = self.activation.apply(&xs)?;
+            if let Some(dropout) = &self.dropout {
+                xs = dropout.forward(&xs, train)?;
+            }
+        }
+        output.forward(&xs)
+    }
+}
+
+impl Module for Mlp {
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        self.forward_t(xs, false)
+    }
+}
+
+fn init_linear(
+    in_dim: usize,
+    out_dim: usize,
+    init: WeightInit,
+    scale: f64,
+    rng: &mut impl Rng,
+    vb: VarBuilder,
+) -> Result<Linear> {
+    // Variables already present (e.g. loaded weights) are left untouched
+    let fresh = !vb.contains_tensor("weight");
+    let layer = match init {
+        WeightInit::Default => linear(in_dim, out_dim, vb)?,
+        WeightInit::Xavier { gain } => {
+            let bound = gain * (6.0 / (in_dim + out_dim) as f64).sqrt();
+            let values = fresh.then(|| {
+                (0..out_dim * in_dim)
+                    .map(|_| rng.gen_range(-bound..=bound))
+                    .collect()
+            });
+            linear_with_weights(in_dim, out_dim, values, vb)?
+        }
+        WeightInit::Orthogonal { gain } => {
+            let values = fresh.then(|| orthogonal(out_dim, in_dim, gain, rng));
+            linear_with_weights(in_dim, out_dim, values, vb)?
+        }
+    };
+    if fresh && scale != 1.0 {
+        let weight = layer.weight();
+        weight.slice_set(&(weight * scale)?, 0, 0)?;
+    }
+    Ok(layer)
+}
+
+// Linear layer with a zero bias and the given row-major weights, if any
+fn linear_with_weights(
+    in_dim: usize,
+    out_dim: usize,
+    values: Option<Vec<f64>>,
+    vb: VarBuilder,
+) -> Result<Linear> {
+    let weight = vb.get_with_hints((out_dim, in_dim), "weight", candle_nn::Init::Const(0.0))?;
+    if let Some(values) = values {
+        let values = Tensor::from_vec(values, (out_dim, in_dim), weight.device())?
+            .to_dtype(weight.dtype())?;
+        // Writes through to the variable's storage
+        weight.slice_set(&values, 0, 0)?;
+    }
+    let bias = vb.get_with_hints(out_dim, "bias", candle_nn::Init::Const(0.0))?;
+    Ok(Linear::new(weight, Some(bias)))
+}
+
+// Row-major (rows, cols) matrix with orthonormal rows or columns, whichever
+// is the smaller set, scaled by `gain`
+fn orthogonal(rows: usize, cols: usize, gain: f64, rng: &mut impl Rng) -> Vec<f64> {
+    let (n, len) = (rows.min(cols), rows.max(cols));
+    let mut vectors: Vec<Vec<f64>> = Vec::with_capacity(n);
+    while vectors.len() < n {
+        let mut v: Vec<f64> = (0..len).map(|_| standard_normal(rng)).collect();
+        // Modified Gram-Schmidt against the accepted vectors
+        for u in &vectors {
+            let dot: f64 = v.iter().zip(u).map(|(a, b)| a * b).sum();
+            v.iter_mut().zip(u).for_each(|(a, b)| *a -= dot * b);
+        }
+        let norm = v.iter().map(|a| a * a).sum::<f64>().sqrt();
+        if norm > 1e-8 {
+            vectors.push(v.into_iter().map(|a| a / norm).collect());
+        }
+    }
+
+    let mut out = vec![0.0; rows * cols];
+    for r in 0..rows {
+        for c in 0..cols {
+            let value = if rows <= cols {
+                vectors[r][c]
+            } else {
+                vectors[c][r]
+            };
+            out[r * cols + c] = gain * value;
+        }
+    }
+    out
+}
+
+// Box-Muller transform
+fn standard_normal(rng: &mut impl Rng) -> f64 {
+    let u1: f64 = 1.0 - rng.r#gen::<f64>();
+    let u2: f64 = rng.r#gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConvLayer {
+    pub out_channels: usize,
+    pub kernel: usize,
+    pub stride: usize,
+}
+
+/// Builds a `CnnEncoder` for channel-first images of a fixed shape.
+/// Convolutions are named `conv1..convN`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CnnBuilder {
+    channels: usize,
+    height: usize,
+    width: usize,
+    layers: Vec<ConvLayer>,
+    activation: Activation,
+}
+
+impl CnnBuilder {
+    pub fn new(channels: usize, height: usize, width: usize) -> Self {
+        Self {
+            channels,
+            height,
+            width,
+            layers: Vec::new(),
+            activation: Activation::ReLU,
+        }
+    }
+
+    /// The three-layer encoder from the Nature DQN paper (Mnih et al., 2015).
+    pub fn nature(channels: usize, height: usize, width: usize) -> Self {
+        Self::new(channels, height, width)
+            .layer(32, 8, 4)
+            .layer(64, 4, 2)
+            .layer(64, 3, 1)
+    }
+
+    pub fn layer(mut self, out_channels: usize, kernel: usize, stride: usize) -> Self {
+        self.layers.push(ConvLayer {
+            out_channels,
+            kernel,
+            stride,
+        });
+        self
+    }
+
+    pub fn activation(mut self, activation: Activation) -> Self {
+        self.activation = activation;
+        self
+    }
+
+    /// Length of one flattened input image.
+    pub fn in_dim(&self) -> usize {
+        self.channels * self.height * self.width
+    }
+
+    /// (channels, height, width) after the last convolution, or `None` if the
+    /// image is too small for the stack.
+    pub fn output_shape(&self) -> Option<(usize, usize, usize)> {
+        let mut shape = (self.channels, self.height, self.width);
+        for l in &self.layers {
+            if shape.1 < l.kernel || shape.2 < l.kernel || l.stride == 0 {
+                return None;
+            }
+            shape = (
+                l.out_channels,
+                (shape.1 - l.kernel) / l.stride + 1,
+                (shape.2 - l.kernel) / l.stride + 1,
+            );
+        }
+        Some(shape)
+    }
+
+    /// Number of features the encoder produces per image.
+    pub fn out_dim(&self) -> usize {
+        self.output_shape().map_or(0, |(c, h, w)| c * h * w)
+    }
+
+    pub fn build(&self, vb: VarBuilder) -> Result<CnnEncoder> {
+        if self.output_shape().is_none() {
+            bail!(
+                "a {}x{} image is too small for {:?}",
+                self.height,
+                self.width,
+                self.layers
+            );
+        }
+        let mut convs = Vec::with_capacity(self.layers.len());
+        let mut in_channels = self.channels;
+        for (i, l) in self.layers.iter().enumerate() {
+            let config = Conv2dConfig {
+                stride: l.stride,
+                ..Default::default()
+            };
+            let name = format!("conv{}", i + 1);
+            convs.push(conv2d(
+                in_channels,
+                l.out_channels,
+                l.kernel,
+                config,
+                vb.pp(name),
+            )?);
+            in_channels = l.out_channels;
+        }
+        Ok(CnnEncoder {
+            convs,
+            in_shape: (self.channels, self.height, self.width),
+            activation: self.activation,
+        })
+    }
+}
+
+/// Convolution stack plus flatten: images in, feature vectors out.
+#[derive(Debug, Clone)]
+pub struct CnnEncoder {
+    convs: Vec<Conv2d>,
+    in_shape: (usize, usize, usize),
+    activation: Activation,
+}
+
+impl Module for CnnEncoder {
+    /// Accepts (B, C, H, W) images or the same flattened to (B, C * H * W).
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        let (c, h, w) = self.in_shape;
+        let mut xs = xs.reshape(((), c, h, w))?;
+        for conv in &self.convs {
+            xs = self.activation.apply(&conv.forward(&xs)?)?;
+        }
+        xs.flatten_from(1)
+    }
+}
+
+/// Q-network: observation in, one value per discrete action out.
 #[derive(Debug, Clone)]
 pub struct QNet {
-    fc1: Linear,
-    fc2: Linear,
-    fc3: Linear,
+    encoder: Option<CnnEncoder>,
+    mlp: Mlp,
 }
 
 impl QNet {
+    /// Two ReLU hidden layers of `hidden_dim` units.
     pub fn new(in_dim: usize, hidden_dim: usize, out_dim: usize, vb: VarBuilder) -> Result<Self> {
-        let fc1 = linear(in_dim, hidden_dim, vb.pp("fc1"))?;
-        let fc2 = linear(hidden_dim, hidden_dim, vb.pp("fc2"))?;
-        let fc3 = linear(hidden_dim, out_dim, vb.pp("fc3"))?;
-        Ok(Self { fc1, fc2, fc3 })
+        Self::from_builder(
+            &MlpBuilder::new(in_dim, out_dim).hidden(&[hidden_dim, hidden_dim]),
+            vb,
+        )
+    }
+
+    pub fn from_builder(builder: &MlpBuilder, vb: VarBuilder) -> Result<Self> {
+        Ok(Self {
+            encoder: None,
+            mlp: builder.build(vb)?,
+        })
+    }
+
+    /// An image encoder followed by `head`, whose input size must be the
+    /// encoder's `out_dim`.
+    pub fn with_encoder(encoder: &CnnBuilder, head: &MlpBuilder, vb: VarBuilder) -> Result<Self> {
+        if head.in_dim != encoder.out_dim() {
+            bail!(
+                "head expects {} inputs but the encoder produces {}",
+                head.in_dim,
+                encoder.out_dim()
+            );
+        }
+        Ok(Self {
+            encoder: Some(encoder.build(vb.pp("encoder"))?),
+            mlp: head.build(vb.pp("head"))?,
+        })
     }
 
     pub fn forward(&self, xs: &Tensor) -> Result<Tensor> {
-        let xs = self.fc1.forward(xs)?;
-        let xs = xs.relu()?;
-        let xs = self.fc2.forward(&xs)?;
-        let xs = xs.relu()?;
-        self.fc3.forward(&xs)
+        self.forward_t(xs, false)
+    }
+
+    pub fn forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor> {
+        match &self.encoder {
+            Some(encoder) => self.mlp.forward_t(&encoder.forward(xs)?, train),
+            None => self.mlp.forward_t(xs, train),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecurrentKind {
+    Lstm,
+    Gru,
+}
+
+#[derive(Debug, Clone)]
+enum Cell {
+    Lstm(LSTM),
+    Gru(GRU),
+}
+
+/// An LSTM or GRU layer stepped one timestep at a time over a batch of
+/// independent hidden states, one row per env.
+///
+/// States are plain (B, `state_dim`) tensors so they can travel through
+/// `HiddenState`. LSTM rows hold `h` followed by `c`.
+#[derive(Debug, Clone)]
+pub struct RecurrentCore {
+    cell: Cell,
+    hidden_dim: usize,
+}
+
+impl RecurrentCore {
+    pub fn new(
+        kind: RecurrentKind,
+        in_dim: usize,
+        hidden_dim: usize,
+        vb: VarBuilder,
+    ) -> Result<Self> {
+        let cell = match kind {
+            RecurrentKind::Lstm => Cell::Lstm(lstm(in_dim, hidden_dim, Default::default(), vb)?),
+            RecurrentKind::Gru => Cell::Gru(gru(in_dim, hidden_dim, Default::default(), vb)?),
+        };
+        Ok(Self { cell, hidden_dim })
+    }
+
+    pub fn hidden_dim(&self) -> usize {
+        self.hidden_dim
+    }
+
+    pub fn state_dim(&self) -> usize {
+        match self.cell {
+            Cell::Lstm(_) => 2 * self.hidden_dim,
+            Cell::Gru(_) => self.hidden_dim,
+        }
+    }
+
+    /// The initial state for `batch` envs.
+    pub fn zero_state(&self, batch: usize) -> Result<Tensor> {
+        match &self.cell {
+            Cell::Lstm(cell) => {
+                let s = cell.zero_state(batch)?;
+                Tensor::cat(&[s.h, s.c], 1)
+            }
+            Cell::Gru(cell) => Ok(cell.zero_state(batch)?.h),
+        }
+    }
+
+    /// Advance every row by one timestep. Returns the output `h` (B, hidden)
+    /// and the next state (B, state_dim).
+    pub fn step(&self, xs: &Tensor, state: &Tensor) -> Result<(Tensor, Tensor)> {
+        match &self.cell {
+            Cell::Lstm(cell) => {
+                let h = state.narrow(1, 0, self.hidden_dim)?;
+                let c = state.narrow(1, self.hidden_dim, self.hidden_dim)?;
+                let next = cell.step(xs, &LSTMState::new(h, c))?;
+                let state = Tensor::cat(&[&next.h, &next.c], 1)?;
+                Ok((next.h, state))
+            }
+            Cell::Gru(cell) => {
+                let next = cell.step(xs, &GRUState { h: state.clone() })?;
+                Ok((next.h.clone(), next.h))
+            }
+        }
+    }
+}
+
+/// Recurrent Q-network (DRQN): a ReLU input layer, a recurrent core and a
+/// linear output layer.
+#[derive(Debug, Clone)]
+pub struct RecurrentQNet {
+    fc: Linear,
+    core: RecurrentCore,
+    out: Linear,
+}
+
+impl RecurrentQNet {
+    pub fn new(
+        kind: RecurrentKind,
+        in_dim: usize,
+        hidden_dim: usize,
+        out_dim: usize,
+        vb: VarBuilder,
+    ) -> Result<Self> {
+        Ok(Self {
+            fc: linear(in_dim, hidden_dim, vb.pp("fc"))?,
+            core: RecurrentCore::new(kind, hidden_dim, hidden_dim, vb.pp("rnn"))?,
+            out: linear(hidden_dim, out_dim, vb.pp("out"))?,
+        })
+    }
+
+    pub fn core(&self) -> &RecurrentCore {
+        &self.core
+    }
+
+    /// Q-values for one timestep and the next hidden state.
+    pub fn step(&self, xs: &Tensor, state: &Tensor) -> Result<(Tensor, Tensor)> {
+        let xs = self.fc.forward(xs)?.relu()?;
+        let (h, state) = self.core.step(&xs, state)?;
+        Ok((self.out.forward(&h)?, state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candle_core::{DType, Device};
+    use candle_nn::VarMap;
+
+    #[test]
+    fn test_mlp_layers_and_shapes() {
+        let varmap = VarMap::new();
+        let vb = VarBuilder::from_varmap(&varmap, DType::F32, &Device::Cpu);
+        let mlp = MlpBuilder::new(3, 2)
+            .hidden(&[8, 5, 4])
+            .activation(Activation::Tanh)
+            .layer_norm(true)
+            .build(vb)
+            .unwrap();
+
+        let xs = Tensor::zeros((6, 3), DType::F32, &Device::Cpu).unwrap();
+        assert_eq!(mlp.forward(&xs).unwrap().dims(), &[6, 2]);
+
+        let data = varmap.data().lock().unwrap();
+        let mut names: Vec<&str> = data.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "fc1.bias",
+                "fc1.weight",
+                "fc2.bias",
+                "fc2.weight",
+                "fc3.bias",
+                "fc3.weight",
+                "fc4.bias",
+                "fc4.weight",
+                "ln1.bias",
+                "ln1.weight",
+                "ln2.bias",
+                "ln2.weight",
+                "ln3.bias",
+                "ln3.weight"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_orthogonal_init() {
+        let varmap = VarMap::new();
+        let vb = VarBuilder::from_varmap(&varmap, DType::F64, &Device::Cpu);
+        let gain = 2f64.sqrt();
+        let mlp = MlpBuilder::new(8, 3)
+            .hidden(&[16])
+            .init(WeightInit::Orthogonal { gain })
+            .output_scale(0.01)
+            .build(vb)
+            .unwrap();
+
+        // Tall matrix: orthonormal columns, W^T W = gain^2 I
+        let w = mlp.layers[0].weight();
+        let wtw: Vec<Vec<f64>> = w.t().unwrap().matmul(w).unwrap().to_vec2().unwrap();
+        for (i, row) in wtw.iter().enumerate() {
+            for (j, x) in row.iter().enumerate() {
+                let expected = if i == j { gain * gain } else { 0.0 };
+                assert!((x - expected).abs() < 1e-9);
+            }
+        }
+
+        // Wide output matrix: orthogonal rows, also scaled by the output scale
+        let w = mlp.layers[1].weight();
+        let wwt: Vec<Vec<f64>> = w.matmul(&w.t().unwrap()).unwrap().to_vec2().unwrap();
+        assert!((wwt[0][0] - (0.01 * gain).powi(2)).abs() < 1e-12);
+        assert!(wwt[0][1].abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_nature_cnn_shapes() {
+        let cnn = CnnBuilder::nature(4, 84, 84);
+        assert_eq!(cnn.output_shape(), Some((64, 7, 7)));
+        assert!(
+            CnnBuilder::nature(1, 20, 20)
+                .build(VarBuilder::zeros(DType::F32, &Device::Cpu))
+                .is_err()
+        );
+
+        let varmap = VarMap::new();
+        let vb = VarBuilder::from_varmap(&varmap, DType::F32, &Device::Cpu);
+        let cnn = CnnBuilder::nature(2, 40, 40);
+        let head = MlpBuilder::new(cnn.out_dim(), 3).hidden(&[32]);
+        let net = QNet::with_encoder(&cnn, &head, vb).unwrap();
+
+        // Flattened observations are reshaped to images
+        let xs = Tensor::zeros((5, cnn.in_dim()), DType::F32, &Device::Cpu).unwrap();
+        assert_eq!(net.forward(&xs).unwrap().dims(), &[5, 3]);
+        assert!(
+            varmap
+                .data()
+                .lock()
+                .unwrap()
+                .contains_key("encoder.conv3.weight")
+        );
+    }
+
+    #[test]
+    fn test_recurrent_core_carries_state() {
+        for (kind, state_dim) in [(RecurrentKind::Lstm, 16), (RecurrentKind::Gru, 8)] {
+            let varmap = VarMap::new();
+            let vb = VarBuilder::from_varmap(&varmap, DType::F32, &Device::Cpu);
+            let net = RecurrentQNet::new(kind, 3, 8, 2, vb).unwrap();
+            assert_eq!(net.core().state_dim(), state_dim);
+
+            let xs = Tensor::ones((4, 3), DType::F32, &Device::Cpu).unwrap();
+            let s0 = net.core().zero_state(4).unwrap();
+            let (q1, s1) = net.step(&xs, &s0).unwrap();
+            assert_eq!(q1.dims(), &[4, 2]);
+            assert_eq!(s1.dims(), &[4, state_dim]);
+
+            // Same input, different memory, different output
+            let (q2, _) = net.step(&xs, &s1).unwrap();
+            let diff = (q1 - q2).unwrap().abs().unwrap().sum_all().unwrap();
+            assert!(diff.to_scalar::<f32>().unwrap() > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_dropout_only_in_training() {
+        let varmap = VarMap::new();
+        let vb = VarBuilder::from_varmap(&varmap, DType::F32, &Device::Cpu);
+        let mlp = MlpBuilder::new(4, 1)
+            .hidden(&[256])
+            .dropout(0.5)
+            .build(vb)
+            .unwrap();
+        let xs = Tensor::ones((1, 4), DType::F32, &Device::Cpu).unwrap();
+
+        let eval_a = mlp.forward_t(&xs, false).unwrap().to_vec2::<f32>().unwrap();
+        let eval_b = mlp.forward_t(&xs, false).unwrap().to_vec2::<f32>().unwrap();
+        assert_eq!(eval_a, eval_b);
+        let train = mlp.forward_t(&xs, true).unwrap().to_vec2::<f32>().unwrap();
+        assert_ne!(eval_a, train);
     }
 }
@@ -0,0 +1,295 @@
+use crate::env::{EnvCore, EnvResult, Environment, Phase, Step};
+use crate::error::HabaError;
+use crate::spaces::Space;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MountainCarState {
+    pub position: f64,
+    pub velocity: f64,
+}
+
+const MIN_POSITION: f64 = -1.2;
+const MAX_POSITION: f64 = 0.6;
+const MAX_SPEED: f64 = 0.07;
+const GRAVITY: f64 = 0.0025;
+
+impl MountainCarState {
+    // Start at rest near the valley floor
+    fn sample(rng: &mut ChaCha8Rng) -> Self {
+        Self {
+            position: rng.gen_range(-0.6..-0.4),
+            velocity: 0.0,
+        }
+    }
+
+    // Apply an acceleration for one step; the left wall is inelastic
+    fn advance(&mut self, acceleration: f64) {
+        self.velocity += acceleration - GRAVITY * (3.0 * self.position).cos();
+        self.velocity = self.velocity.clamp(-MAX_SPEED, MAX_SPEED);
+        self.position += self.velocity;
+        self.position = self.position.clamp(MIN_POSITION, MAX_POSITION);
+        if self.position == MIN_POSITION && self.velocity < 0.0 {
+            self.velocity = 0.0;
+        }
+    }
+
+    fn observation(&self) -> Vec<f64> {
+        vec![self.position, self.velocity]
+    }
+
+    fn space() -> Space {
+        Space::bounded(
+            vec![MIN_POSITION, -MAX_SPEED],
+            vec![MAX_POSITION, MAX_SPEED],
+        )
+        .expect("2 bounds each")
+    }
+}
+
+/// An underpowered car in a valley must rock back and forth to reach the
+/// flag on the right hill, as in Gymnasium's MountainCar-v0. Actions are 0
+/// (accelerate left), 1 (coast) and 2 (accelerate right); every step costs
+/// -1 until the car reaches position 0.5.
+pub struct MountainCar {
+    core: EnvCore<MountainCarState>,
+}
+
+impl MountainCar {
+    const FORCE: f64 = 0.001;
+    const GOAL_POSITION: f64 = 0.5;
+
+    pub fn new() -> Self {
+        Self {
+            core: EnvCore::new(),
+        }
+    }
+
+    pub fn state(&self) -> &MountainCarState {
+        &self.core.state
+    }
+}
+
+impl Default for MountainCar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Environment for MountainCar {
+    type Observation = Vec<f64>;
+    type Action = usize;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        self.core.state = MountainCarState::sample(&mut self.core.rng);
+        self.core.phase = Phase::Running;
+        Ok(self.core.state.observation())
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        self.core.phase.check_step("MountainCar")?;
+        if action > 2 {
+            return Err(HabaError::Env(format!(
+                "invalid MountainCar action {}",
+                action
+            )));
+        }
+        self.core.state.advance((action as f64 - 1.0) * Self::FORCE);
+
+        let terminated =
+            self.core.state.position >= Self::GOAL_POSITION && self.core.state.velocity >= 0.0;
+        if terminated {
+            self.core.phase = Phase::Terminated;
+        }
+        Ok(Step {
+            obs: self.core.state.observation(),
+            reward: -1.0,
+            done: terminated,
+            info: None,
+        })
+    }
+
+    fn observation_space(&self) -> Space {
+        MountainCarState::space()
+    }
+
+    fn action_space(&self) -> Space {
+        Space::discrete(3)
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.core.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        self.core.state_dict()
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.core.load_state_dict(state)
+    }
+}
+
+/// MountainCar with a continuous force in [-1, 1], as in Gymnasium's
+/// MountainCarContinuous-v0. Each step costs 0.1 times the squared action,
+/// and reaching position 0.45 earns 100.
+pub struct MountainCarContinuous {
+    core: EnvCore<MountainCarState>,
+}
+
+impl MountainCarContinuous {
+    const POWER: f64 = 0.0015;
+    const GOAL_POSITION: f64 = 0.45;
+
+    pub fn new() -> Self {
+        Self {
+            core: EnvCore::new(),
+        }
+    }
+
+    pub fn state(&self) -> &MountainCarState {
+        &self.core.state
+    }
+}
+
+impl Default for MountainCarContinuous {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Environment for MountainCarContinuous {
+    type Observation = Vec<f64>;
+    type Action = Vec<f64>;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        self.core.state = MountainCarState::sample(&mut self.core.rng);
+        self.core.phase = Phase::Running;
+        Ok(self.core.state.observation())
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        self.core.phase.check_step("MountainCarContinuous")?;
+        let &[action] = action.as_slice() else {
+            return Err(HabaError::Shape(format!(
+                "MountainCarContinuous takes 1 action value, got {}",
+                action.len()
+            )));
+        };
+        // The force is clipped, but the cost is of the action as given
+        self.core
+            .state
+            .advance(action.clamp(-1.0, 1.0) * Self::POWER);
+
+        let terminated =
+            self.core.state.position >= Self::GOAL_POSITION && self.core.state.velocity >= 0.0;
+        let mut reward = if terminated { 100.0 } else { 0.0 };
+        reward -= action.powi(2) * 0.1;
+        if terminated {
+            self.core.phase = Phase::Terminated;
+        }
+        Ok(Step {
+            obs: self.core.state.observation(),
+            reward,
+            done: terminated,
+            info: None,
+        })
+    }
+
+    fn observation_space(&self) -> Space {
+        MountainCarState::space()
+    }
+
+    fn action_space(&self) -> Space {
+        Space::uniform(&[1], -1.0, 1.0)
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.core.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        self.core.state_dict()
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.core.load_state_dict(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_checker::check_env;
+
+    fn assert_close(got: &[f64], want: &[f64]) {
+        for (g, w) in got.iter().zip(want) {
+            assert!((g - w).abs() < 1e-12, "{:?} != {:?}", got, want);
+        }
+    }
+
+    #[test]
+    fn test_mountain_car_matches_reference() {
+        // Gymnasium's MountainCar-v0 update equations evaluated in float64
+        let expected = [
+            [-0.49917684300416926, 0.0008231569958307428],
+            [-0.49753668667935325, 0.0016401563248160246],
+            [-0.4970917969323474, 0.00044488974700586273],
+            [-0.49684550006784745, 0.0002462968644999427],
+            [-0.4957996374204934, 0.0010458626473540712],
+            [-0.49596202671027434, -0.0001623892897809659],
+            [-0.49733145422579234, -0.0013694275155180027],
+            [-0.49789768282304186, -0.0005662285972495108],
+        ];
+        let mut env = MountainCar::new();
+        env.reset().unwrap();
+        env.core.state.position = -0.5;
+        for (action, want) in [2, 2, 0, 1, 2, 0, 0, 2].into_iter().zip(&expected) {
+            let step = env.step(action).unwrap();
+            assert_eq!((step.reward, step.done), (-1.0, false));
+            assert_close(&step.obs, want);
+        }
+        assert!(env.step(3).is_err());
+
+        // The left wall stops the car dead
+        env.core.state = MountainCarState {
+            position: -1.19,
+            velocity: -0.05,
+        };
+        assert_eq!(env.step(0).unwrap().obs, vec![MIN_POSITION, 0.0]);
+        assert!(check_env(&mut MountainCar::new()).is_ok());
+    }
+
+    #[test]
+    fn test_mountain_car_continuous_matches_reference() {
+        let expected = [
+            ([-0.49867684300416926, 0.0013231569958307428], -0.1),
+            ([-0.4967904264118068, 0.0018864165923624283], -0.025),
+            ([-0.4966048557566195, 0.00018557065518730429], -0.4),
+            ([-0.49624651828860405, 0.0003583374680154399], -0.00625),
+            ([-0.49459309261373896, 0.0016534256748651117], -0.225),
+        ];
+        let mut env = MountainCarContinuous::new();
+        env.reset().unwrap();
+        env.core.state.position = -0.5;
+        for (action, (want, reward)) in [1.0, 0.5, -2.0, 0.25, 1.5].into_iter().zip(&expected) {
+            let step = env.step(vec![action]).unwrap();
+            assert_close(&step.obs, want);
+            assert!((step.reward - reward).abs() < 1e-12);
+        }
+
+        // Reaching the goal pays 100 less the action cost, and ends the episode
+        env.core.state = MountainCarState {
+            position: 0.44,
+            velocity: 0.05,
+        };
+        let step = env.step(vec![1.0]).unwrap();
+        assert!(step.done);
+        assert!((step.reward - 99.9).abs() < 1e-12);
+        assert!(env.step(vec![1.0]).is_err());
+        assert!(check_env(&mut MountainCarContinuous::new()).is_ok());
+    }
+}
@@ -0,0 +1,487 @@
+use crate::checkpoint;
+use crate::env::{EnvResult, Environment, Step};
+use crate::error::{HabaError, Result};
+use crate::spaces::Space;
+use crate::venv::VectorEnv;
+use crate::wrappers::Wrapper;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+const EPSILON: f64 = 1e-8;
+
+/// Per-dimension running mean and variance.
+///
+/// Batches are folded in with the parallel form of Welford's algorithm
+/// (Chan et al.), so statistics gathered separately can be merged exactly.
+/// The dimension is fixed by the first update.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunningMeanStd {
+    mean: Vec<f64>,
+    // Population variance
+    var: Vec<f64>,
+    count: f64,
+}
+
+impl RunningMeanStd {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mean(&self) -> &[f64] {
+        &self.mean
+    }
+
+    pub fn var(&self) -> &[f64] {
+        &self.var
+    }
+
+    pub fn count(&self) -> f64 {
+        self.count
+    }
+
+    pub fn update(&mut self, x: &[f64]) -> Result<()> {
+        self.update_batch(std::slice::from_ref(&x.to_vec()))
+    }
+
+    pub fn update_batch(&mut self, xs: &[Vec<f64>]) -> Result<()> {
+        let Some(first) = xs.first() else {
+            return Ok(());
+        };
+        let dim = first.len();
+        if let Some(bad) = xs.iter().find(|x| x.len() != dim) {
+            return Err(HabaError::Shape(format!(
+                "batch mixes lengths {} and {}",
+                dim,
+                bad.len()
+            )));
+        }
+        let n = xs.len() as f64;
+        let mean: Vec<f64> = (0..dim)
+            .map(|d| xs.iter().map(|x| x[d]).sum::<f64>() / n)
+            .collect();
+        let var: Vec<f64> = (0..dim)
+            .map(|d| xs.iter().map(|x| (x[d] - mean[d]).powi(2)).sum::<f64>() / n)
+            .collect();
+        self.merge(&RunningMeanStd {
+            mean,
+            var,
+            count: n,
+        })
+    }
+
+    /// Fold in statistics gathered elsewhere.
+    pub fn merge(&mut self, other: &RunningMeanStd) -> Result<()> {
+        if other.count == 0.0 {
+            return Ok(());
+        }
+        if self.count == 0.0 {
+            *self = other.clone();
+            return Ok(());
+        }
+        if other.mean.len() != self.mean.len() {
+            return Err(HabaError::Shape(format!(
+                "cannot merge statistics of length {} into {}",
+                other.mean.len(),
+                self.mean.len()
+            )));
+        }
+        let total = self.count + other.count;
+        for d in 0..self.mean.len() {
+            let delta = other.mean[d] - self.mean[d];
+            let m2 = self.var[d] * self.count
+                + other.var[d] * other.count
+                + delta * delta * self.count * other.count / total;
+            self.mean[d] += delta * other.count / total;
+            self.var[d] = m2 / total;
+        }
+        self.count = total;
+        Ok(())
+    }
+
+    /// `(x - mean) / std`, clipped to `[-clip, clip]`. Identity before any update.
+    pub fn normalize(&self, x: &[f64], clip: f64) -> Vec<f64> {
+        if self.count == 0.0 {
+            return x.to_vec();
+        }
+        x.iter()
+            .zip(self.mean.iter().zip(&self.var))
+            .map(|(v, (m, var))| ((v - m) / (var + EPSILON).sqrt()).clamp(-clip, clip))
+            .collect()
+    }
+}
+
+/// Normalizes observations with running statistics.
+///
+/// Wrapping a single `Environment` keeps statistics for that env; wrapping
+/// a `VectorEnv` shares one set across all of its sub-envs. The statistics
+/// are part of `state_dict`, so they are checkpointed with the env.
+pub struct NormalizeObservation<E> {
+    env: E,
+    obs_rms: RunningMeanStd,
+    clip: f64,
+    frozen: bool,
+}
+
+impl<E> NormalizeObservation<E> {
+    pub fn new(env: E) -> Self {
+        Self {
+            env,
+            obs_rms: RunningMeanStd::new(),
+            clip: 10.0,
+            frozen: false,
+        }
+    }
+
+    /// Clip normalized values to `[-clip, clip]` (10 by default).
+    pub fn with_clip(mut self, clip: f64) -> Self {
+        self.clip = clip;
+        self
+    }
+
+    /// Stop updating the statistics, e.g. on an evaluation env.
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    pub fn obs_rms(&self) -> &RunningMeanStd {
+        &self.obs_rms
+    }
+
+    /// Replace the statistics, e.g. to evaluate with those of the training env.
+    pub fn set_obs_rms(&mut self, obs_rms: RunningMeanStd) {
+        self.obs_rms = obs_rms;
+    }
+
+    fn observe(&mut self, obs: &[Vec<f64>]) -> Result<Vec<Vec<f64>>> {
+        if !self.frozen {
+            self.obs_rms.update_batch(obs)?;
+        }
+        Ok(obs
+            .iter()
+            .map(|o| self.obs_rms.normalize(o, self.clip))
+            .collect())
+    }
+
+    fn state_with(&self, inner: Value) -> Value {
+        json!({"env": inner, "obs_rms": self.obs_rms})
+    }
+}
+
+impl<E> Wrapper for NormalizeObservation<E> {
+    type Inner = E;
+
+    fn inner(&self) -> &E {
+        &self.env
+    }
+
+    fn inner_mut(&mut self) -> &mut E {
+        &mut self.env
+    }
+}
+
+impl<E: Environment<Observation = Vec<f64>>> Environment for NormalizeObservation<E> {
+    type Observation = Vec<f64>;
+    type Action = E::Action;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        let obs = self.env.reset()?;
+        Ok(self.observe(&[obs])?.remove(0))
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        let mut step = self.env.step(action)?;
+        step.obs = self.observe(&[step.obs])?.remove(0);
+        Ok(step)
+    }
+
+    fn observation_space(&self) -> Space {
+        Space::uniform(self.env.observation_space().shape(), -self.clip, self.clip)
+    }
+
+    fn action_space(&self) -> Space {
+        self.env.action_space()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        Ok(self.state_with(self.env.state_dict()?))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.obs_rms = checkpoint::field(state, "obs_rms")?;
+        self.env
+            .load_state_dict(&checkpoint::field::<Value>(state, "env")?)
+    }
+}
+
+impl<V: VectorEnv<Observation = Vec<f64>>> VectorEnv for NormalizeObservation<V> {
+    type Observation = Vec<f64>;
+    type Action = V::Action;
+
+    fn step(&mut self, actions: &[Self::Action]) -> Result<Vec<Step<Self::Observation>>> {
+        let mut steps = self.env.step(actions)?;
+        let obs: Vec<Vec<f64>> = steps
+            .iter_mut()
+            .map(|s| std::mem::take(&mut s.obs))
+            .collect();
+        for (step, obs) in steps.iter_mut().zip(self.observe(&obs)?) {
+            step.obs = obs;
+        }
+        Ok(steps)
+    }
+
+    fn reset(&mut self) -> Result<Vec<Self::Observation>> {
+        let obs = self.env.reset()?;
+        self.observe(&obs)
+    }
+
+    fn len(&self) -> usize {
+        self.env.len()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    fn state_dict(&self) -> Result<Value> {
+        Ok(self.state_with(self.env.state_dict()?))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> Result<()> {
+        self.obs_rms = checkpoint::field(state, "obs_rms")?;
+        self.env
+            .load_state_dict(&checkpoint::field::<Value>(state, "env")?)
+    }
+}
+
+/// Scales rewards by the running standard deviation of the discounted
+/// return, then clips them. Like `NormalizeObservation`, a wrapped
+/// `VectorEnv` shares one set of statistics across its sub-envs.
+pub struct NormalizeReward<E> {
+    env: E,
+    return_rms: RunningMeanStd,
+    // Discounted return of the running episode, per sub-env
+    returns: Vec<f64>,
+    gamma: f64,
+    clip: f64,
+    frozen: bool,
+}
+
+impl<E> NormalizeReward<E> {
+    pub fn new(env: E, gamma: f64) -> Self {
+        Self {
+            env,
+            return_rms: RunningMeanStd::new(),
+            returns: Vec::new(),
+            gamma,
+            clip: 10.0,
+            frozen: false,
+        }
+    }
+
+    /// Clip scaled rewards to `[-clip, clip]` (10 by default).
+    pub fn with_clip(mut self, clip: f64) -> Self {
+        self.clip = clip;
+        self
+    }
+
+    /// Stop updating the statistics, e.g. on an evaluation env.
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    pub fn return_rms(&self) -> &RunningMeanStd {
+        &self.return_rms
+    }
+
+    pub fn set_return_rms(&mut self, return_rms: RunningMeanStd) {
+        self.return_rms = return_rms;
+    }
+
+    fn scale<O>(&mut self, steps: &mut [Step<O>]) -> Result<()> {
+        self.returns.resize(steps.len(), 0.0);
+        for (ret, step) in self.returns.iter_mut().zip(steps.iter()) {
+            *ret = *ret * self.gamma + step.reward;
+        }
+        if !self.frozen {
+            let returns: Vec<Vec<f64>> = self.returns.iter().map(|&r| vec![r]).collect();
+            self.return_rms.update_batch(&returns)?;
+        }
+        let std = self
+            .return_rms
+            .var()
+            .first()
+            .map_or(1.0, |var| (var + EPSILON).sqrt());
+        for (ret, step) in self.returns.iter_mut().zip(steps.iter_mut()) {
+            step.reward = (step.reward / std).clamp(-self.clip, self.clip);
+            if step.done {
+                *ret = 0.0;
+            }
+        }
+        Ok(())
+    }
+
+    fn state_with(&self, inner: Value) -> Value {
+        json!({"env": inner, "return_rms": self.return_rms, "returns": self.returns})
+    }
+
+    fn load_own_state(&mut self, state: &Value) -> Result<Value> {
+        self.return_rms = checkpoint::field(state, "return_rms")?;
+        self.returns = checkpoint::field(state, "returns")?;
+        checkpoint::field(state, "env")
+    }
+}
+
+impl<E> Wrapper for NormalizeReward<E> {
+    type Inner = E;
+
+    fn inner(&self) -> &E {
+        &self.env
+    }
+
+    fn inner_mut(&mut self) -> &mut E {
+        &mut self.env
+    }
+}
+
+impl<E: Environment> Environment for NormalizeReward<E> {
+    type Observation = E::Observation;
+    type Action = E::Action;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        self.returns = vec![0.0];
+        self.env.reset()
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        let mut steps = [self.env.step(action)?];
+        self.scale(&mut steps)?;
+        let [step] = steps;
+        Ok(step)
+    }
+
+    fn observation_space(&self) -> Space {
+        self.env.observation_space()
+    }
+
+    fn action_space(&self) -> Space {
+        self.env.action_space()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        Ok(self.state_with(self.env.state_dict()?))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        let inner = self.load_own_state(state)?;
+        self.env.load_state_dict(&inner)
+    }
+}
+
+impl<V: VectorEnv> VectorEnv for NormalizeReward<V> {
+    type Observation = V::Observation;
+    type Action = V::Action;
+
+    fn step(&mut self, actions: &[Self::Action]) -> Result<Vec<Step<Self::Observation>>> {
+        let mut steps = self.env.step(actions)?;
+        self.scale(&mut steps)?;
+        Ok(steps)
+    }
+
+    fn reset(&mut self) -> Result<Vec<Self::Observation>> {
+        self.returns = vec![0.0; self.env.len()];
+        self.env.reset()
+    }
+
+    fn len(&self) -> usize {
+        self.env.len()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    fn state_dict(&self) -> Result<Value> {
+        Ok(self.state_with(self.env.state_dict()?))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> Result<()> {
+        let inner = self.load_own_state(state)?;
+        self.env.load_state_dict(&inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartpole::CartPole;
+    use crate::venv::DummyVectorEnv;
+
+    #[test]
+    fn test_running_mean_std_merge() {
+        let xs: Vec<Vec<f64>> = (0..10).map(|i| vec![i as f64, (i * i) as f64]).collect();
+        let mut whole = RunningMeanStd::new();
+        whole.update_batch(&xs).unwrap();
+
+        let mut left = RunningMeanStd::new();
+        left.update_batch(&xs[..3]).unwrap();
+        let mut right = RunningMeanStd::new();
+        for x in &xs[3..] {
+            right.update(x).unwrap();
+        }
+        left.merge(&right).unwrap();
+
+        assert_eq!(left.count(), 10.0);
+        for d in 0..2 {
+            assert!((left.mean()[d] - whole.mean()[d]).abs() < 1e-9);
+            assert!((left.var()[d] - whole.var()[d]).abs() < 1e-9);
+        }
+        assert!((whole.mean()[0] - 4.5).abs() < 1e-12);
+        assert!((whole.var()[0] - 8.25).abs() < 1e-12);
+        assert!(left.update(&[1.0]).is_err());
+    }
+
+    #[test]
+    fn test_vector_stats_are_shared_and_freezable() {
+        let envs = vec![CartPole::new(), CartPole::new(), CartPole::new()];
+        let mut venv = NormalizeObservation::new(DummyVectorEnv::new(envs)).with_clip(5.0);
+        venv.reset().unwrap();
+        for _ in 0..4 {
+            let steps = venv.step(&[1, 0, 1]).unwrap();
+            assert!(steps.iter().flat_map(|s| &s.obs).all(|v| v.abs() <= 5.0));
+        }
+        // One set of statistics over every sub-env's observations
+        assert_eq!(venv.obs_rms().count(), 15.0);
+
+        venv.set_frozen(true);
+        let before = venv.obs_rms().clone();
+        venv.step(&[1, 1, 1]).unwrap();
+        assert_eq!(venv.obs_rms(), &before);
+    }
+
+    #[test]
+    fn test_normalize_state_dict_roundtrip() {
+        let mut env = NormalizeReward::new(NormalizeObservation::new(CartPole::new()), 0.99);
+        env.reset().unwrap();
+        for _ in 0..5 {
+            env.step(1).unwrap();
+        }
+        let state = env.state_dict().unwrap();
+
+        let mut restored = NormalizeReward::new(NormalizeObservation::new(CartPole::new()), 0.99);
+        restored.load_state_dict(&state).unwrap();
+        assert_eq!(restored.return_rms(), env.return_rms());
+        assert_eq!(restored.inner().obs_rms(), env.inner().obs_rms());
+        assert_eq!(
+            restored.step(0).unwrap().reward,
+            env.step(0).unwrap().reward
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use crate::error::{HabaError, Result};
+use candle_core::backprop::GradStore;
+use candle_core::{DType, Tensor, Var};
+use candle_nn::ParamsAdamW;
+use std::collections::HashMap;
+use std::path::Path;
+
+struct NamedVar {
+    name: String,
+    var: Var,
+    first_moment: Var,
+    second_moment: Var,
+}
+
+/// AdamW with decoupled weight decay.
+///
+/// Numerically identical to `candle_nn::AdamW`, but its moment estimates are
+/// keyed by variable name so they can be saved and restored for exact resumes.
+pub struct AdamW {
+    vars: Vec<NamedVar>,
+    step_t: usize,
+    params: ParamsAdamW,
+}
+
+impl AdamW {
+    pub fn new(vars: HashMap<String, Var>, params: ParamsAdamW) -> Result<Self> {
+        let mut named: Vec<NamedVar> = Vec::with_capacity(vars.len());
+        for (name, var) in vars {
+            if !var.dtype().is_float() {
+                continue;
+            }
+            let first_moment = Var::zeros(var.shape(), var.dtype(), var.device())?;
+            let second_moment = Var::zeros(var.shape(), var.dtype(), var.device())?;
+            named.push(NamedVar {
+                name,
+                var,
+                first_moment,
+                second_moment,
+            });
+        }
+        // Fixed update order regardless of hash map iteration order
+        named.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Self {
+            vars: named,
+            step_t: 0,
+            params,
+        })
+    }
+
+    pub fn learning_rate(&self) -> f64 {
+        self.params.lr
+    }
+
+    pub fn set_learning_rate(&mut self, lr: f64) {
+        self.params.lr = lr;
+    }
+
+    /// Number of optimizer steps taken, used for bias correction.
+    pub fn step_count(&self) -> usize {
+        self.step_t
+    }
+
+    /// Global L2 norm of the gradients of the optimized variables.
+    pub fn grad_norm(&self, grads: &GradStore) -> Result<f64> {
+        let mut sq_sum = 0.0;
+        for v in &self.vars {
+            if let Some(g) = grads.get(&v.var) {
+                sq_sum += g
+                    .sqr()?
+                    .sum_all()?
+                    .to_dtype(DType::F64)?
+                    .to_scalar::<f64>()?;
+            }
+        }
+        Ok(sq_sum.sqrt())
+    }
+
+    pub fn step(&mut self, grads: &GradStore) -> Result<()> {
+        self.step_t += 1;
+        let lr = self.params.lr;
+        let lr_lambda = lr * self.params.weight_decay;
+        let beta1 = self.params.beta1;
+        let beta2 = self.params.beta2;
+        let scale_m = 1f64 / (1f64 - beta1.powi(self.step_t as i32));
+        let scale_v = 1f64 / (1f64 - beta2.powi(self.step_t as i32));
+        for v in self.vars.iter() {
+            let theta = &v.var;
+            if let Some(g) = grads.get(theta) {
+                let next_m = ((v.first_moment.as_tensor() * beta1)? + (g * (1.0 - beta1))?)?;
+                let next_v =
+                    ((v.second_moment.as_tensor() * beta2)? + (g.sqr()? * (1.0 - beta2))?)?;
+                let m_hat = (&next_m * scale_m)?;
+                let v_hat = (&next_v * scale_v)?;
+                let next_theta = (theta.as_tensor() * (1f64 - lr_lambda))?;
+                let adjusted_grad = (m_hat / (v_hat.sqrt()? + self.params.eps)?)?;
+                let next_theta = (next_theta - (adjusted_grad * lr)?)?;
+                v.first_moment.set(&next_m)?;
+                v.second_moment.set(&next_v)?;
+                theta.set(&next_theta)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write both moment estimates as safetensors (`<name>.m`, `<name>.v`).
+    pub fn save_moments(&self, path: &Path) -> Result<()> {
+        let mut tensors: HashMap<String, Tensor> = HashMap::new();
+        for v in &self.vars {
+            tensors.insert(format!("{}.m", v.name), v.first_moment.as_tensor().clone());
+            tensors.insert(format!("{}.v", v.name), v.second_moment.as_tensor().clone());
+        }
+        candle_core::safetensors::save(&tensors, path)?;
+        Ok(())
+    }
+
+    /// Restore moments written by `save_moments` and the matching step count.
+    pub fn load_moments(&mut self, path: &Path, step_t: usize) -> Result<()> {
+        let Some(first) = self.vars.first() else {
+            self.step_t = step_t;
+            return Ok(());
+        };
+        let tensors = candle_core::safetensors::load(path, first.var.device())?;
+        for v in &self.vars {
+            for (suffix, moment) in [("m", &v.first_moment), ("v", &v.second_moment)] {
+                let key = format!("{}.{}", v.name, suffix);
+                let tensor = tensors.get(&key).ok_or_else(|| {
+                    HabaError::Checkpoint(format!("optimizer state is missing {}", key))
+                })?;
+                moment.set(&tensor.to_dtype(moment.dtype())?)?;
+            }
+        }
+        self.step_t = step_t;
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use crate::env::{EnvCore, EnvResult, Environment, Phase, Step};
+use crate::error::HabaError;
+use crate::spaces::Space;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::f64::consts::PI;
+
+/// Angle from upright (radians) and angular velocity.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PendulumState {
+    pub theta: f64,
+    pub theta_dot: f64,
+}
+
+/// Swing a frictionless pendulum upright and hold it there, as in
+/// Gymnasium's Pendulum-v1. The action is a torque in [-2, 2]; the reward
+/// penalises angle, speed and torque. Episodes never terminate, so wrap it
+/// in a `TimeLimit`. Observations are `[cos θ, sin θ, θ']`.
+pub struct Pendulum {
+    gravity: f64,
+    core: EnvCore<PendulumState>,
+}
+
+impl Pendulum {
+    const MAX_SPEED: f64 = 8.0;
+    const MAX_TORQUE: f64 = 2.0;
+    const DT: f64 = 0.05;
+    const MASS: f64 = 1.0;
+    const LENGTH: f64 = 1.0;
+
+    pub fn new(gravity: f64) -> Self {
+        Self {
+            gravity,
+            core: EnvCore::new(),
+        }
+    }
+
+    pub fn state(&self) -> &PendulumState {
+        &self.core.state
+    }
+
+    fn observation(&self) -> Vec<f64> {
+        let s = &self.core.state;
+        vec![s.theta.cos(), s.theta.sin(), s.theta_dot]
+    }
+}
+
+// Map an angle into [-π, π)
+fn angle_normalize(x: f64) -> f64 {
+    (x + PI).rem_euclid(2.0 * PI) - PI
+}
+
+impl Default for Pendulum {
+    fn default() -> Self {
+        Self::new(10.0)
+    }
+}
+
+impl Environment for Pendulum {
+    type Observation = Vec<f64>;
+    type Action = Vec<f64>;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        self.core.state = PendulumState {
+            theta: self.core.rng.gen_range(-PI..PI),
+            theta_dot: self.core.rng.gen_range(-1.0..1.0),
+        };
+        self.core.phase = Phase::Running;
+        Ok(self.observation())
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        self.core.phase.check_step("Pendulum")?;
+        let &[torque] = action.as_slice() else {
+            return Err(HabaError::Shape(format!(
+                "Pendulum takes 1 action value, got {}",
+                action.len()
+            )));
+        };
+        let u = torque.clamp(-Self::MAX_TORQUE, Self::MAX_TORQUE);
+        let (g, m, l) = (self.gravity, Self::MASS, Self::LENGTH);
+        let PendulumState { theta, theta_dot } = self.core.state;
+
+        let cost = angle_normalize(theta).powi(2) + 0.1 * theta_dot.powi(2) + 0.001 * u.powi(2);
+        let new_theta_dot = (theta_dot
+            + (3.0 * g / (2.0 * l) * theta.sin() + 3.0 / (m * l.powi(2)) * u) * Self::DT)
+            .clamp(-Self::MAX_SPEED, Self::MAX_SPEED);
+        self.core.state = PendulumState {
+            theta: theta + new_theta_dot * Self::DT,
+            theta_dot: new_theta_dot,
+        };
+
+        Ok(Step {
+            obs: self.observation(),
+            reward: -cost,
+            done: false,
+            info: None,
+        })
+    }
+
+    fn observation_space(&self) -> Space {
+        Space::bounded(
+            vec![-1.0, -1.0, -Self::MAX_SPEED],
+            vec![1.0, 1.0, Self::MAX_SPEED],
+        )
+        .expect("3 bounds each")
+    }
+
+    fn action_space(&self) -> Space {
+        Space::uniform(&[1], -Self::MAX_TORQUE, Self::MAX_TORQUE)
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.core.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        self.core.state_dict()
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.core.load_state_dict(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_checker::check_env;
+    use crate::wrappers::TimeLimit;
+
+    #[test]
+    fn test_pendulum_matches_reference() {
+        // Gymnasium's Pendulum-v1 update equations evaluated in float64
+        let expected = [
+            (
+                [2.0165986535059632, 0.33197307011926136],
+                -4.026000000000001,
+            ),
+            ([2.0520322447766723, 0.708671825414186], -4.081690741250505),
+            ([2.12445671820858, 1.448489468638153], -4.261307909216777),
+            ([2.243778900711739, 2.3864436500631725], -4.727128521617134),
+            ([2.392424809927977, 2.972918184324757], -5.604055084771863),
+        ];
+        let mut env = Pendulum::default();
+        env.reset().unwrap();
+        env.core.state = PendulumState {
+            theta: 2.0,
+            theta_dot: -0.5,
+        };
+        for (u, (want, reward)) in [1.0, -3.0, 0.5, 2.0, 0.0].into_iter().zip(&expected) {
+            let step = env.step(vec![u]).unwrap();
+            assert!(!step.done);
+            assert!((step.reward - reward).abs() < 1e-12);
+            let s = env.state();
+            assert!((s.theta - want[0]).abs() < 1e-12);
+            assert!((s.theta_dot - want[1]).abs() < 1e-12);
+        }
+        assert!(env.step(vec![0.0, 0.0]).is_err());
+        assert_eq!(angle_normalize(3.0 * PI), -PI);
+
+        let report = check_env(&mut TimeLimit::new(Pendulum::default(), 200));
+        assert!(report.is_ok(), "{}", report);
+    }
+}
@@ -1,14 +1,150 @@
-use crate::batch::Batch;
+use crate::batch::{Batch, PolicyExtra};
+use crate::checkpoint;
+use crate::error::Result;
+use crate::schedule::Progress;
+use crate::stats::LearnStats;
 use rand::Rng;
+use std::path::Path;
+
+/// Whether a policy is collecting training data or being evaluated.
+///
+/// In `Eval` mode policies act deterministically: no epsilon-greedy exploration,
+/// no dropout and no parameter noise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PolicyMode {
+    #[default]
+    Train,
+    Eval,
+}
+
+/// Recurrent state, one flat row per environment.
+pub type HiddenState = Vec<Vec<f64>>;
+
+/// Everything a policy produces for a batch of observations.
+///
+/// Only `act` is mandatory; the other fields are filled in by policies that
+/// have them (actor-critic methods, recurrent networks, ...).
+#[derive(Debug, Clone)]
+pub struct PolicyOutput<A> {
+    pub act: Vec<A>,
+    pub logits: Option<Vec<Vec<f64>>>,
+    pub log_prob: Option<Vec<f64>>,
+    pub value: Option<Vec<f64>>,
+    pub state: Option<HiddenState>,
+}
+
+impl<A> PolicyOutput<A> {
+    pub fn new(act: Vec<A>) -> Self {
+        Self {
+            act,
+            logits: None,
+            log_prob: None,
+            value: None,
+            state: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.act.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.act.is_empty()
+    }
+
+    /// The policy-side data for environment `i`, as stored in the replay buffer.
+    pub fn extra(&self, i: usize) -> PolicyExtra {
+        PolicyExtra {
+            logits: self.logits.as_ref().map(|l| l[i].clone()),
+            log_prob: self.log_prob.as_ref().map(|l| l[i]),
+            value: self.value.as_ref().map(|v| v[i]),
+            state: None,
+        }
+    }
+}
 
 pub trait Policy {
     type Observation;
     type Action;
 
-    fn forward(&mut self, obs: &[Self::Observation]) -> Vec<Self::Action>;
-    fn learn(&mut self, batch: &Batch<Self::Observation, Self::Action>);
+    // `state` is the hidden state returned by the previous call, if any
+    fn forward(
+        &mut self,
+        obs: &[Self::Observation],
+        state: Option<&HiddenState>,
+    ) -> Result<PolicyOutput<Self::Action>>;
+    fn learn(&mut self, batch: &Batch<Self::Observation, Self::Action>) -> Result<LearnStats>;
+
+    // Called by the trainer so that scheduled hyperparameters can follow training progress
+    fn set_progress(&mut self, _progress: Progress) {}
+
+    // Policies without exploration or stochastic layers can ignore the mode
+    fn set_mode(&mut self, _mode: PolicyMode) {}
+
+    fn mode(&self) -> PolicyMode {
+        PolicyMode::Train
+    }
+
+    fn train(&mut self) {
+        self.set_mode(PolicyMode::Train);
+    }
+
+    fn eval(&mut self) {
+        self.set_mode(PolicyMode::Eval);
+    }
+
+    // Seed the policy's own randomness (exploration, sampling)
+    fn seed(&mut self, _seed: u64) {}
+
+    /// Length of the transition runs `learn` expects, for recurrent policies
+    /// that train on sequences. `None` means independent transitions.
+    fn sequence_len(&self) -> Option<usize> {
+        None
+    }
+
+    /// Whether `learn` needs `Batch::act_next`, as on-policy methods like
+    /// SARSA do. The collector then holds each transition back until the
+    /// action at its next observation has been chosen.
+    fn needs_next_action(&self) -> bool {
+        false
+    }
+
+    /// Whether `learn` must see each transition once, in collection order,
+    /// while it still reflects the current policy. `Collector::train_step`
+    /// then learns on the drained buffer instead of replay samples.
+    fn on_policy(&self) -> bool {
+        self.needs_next_action()
+    }
+
+    /// Reset row `index` of `state` to the initial hidden state, when that
+    /// env's episode ends. Zeros by default.
+    fn reset_state(&self, state: &mut HiddenState, index: usize) {
+        if let Some(row) = state.get_mut(index) {
+            row.iter_mut().for_each(|v| *v = 0.0);
+        }
+    }
+
+    /// Write the weights and hyperparameters needed to run the policy.
+    fn save_checkpoint(&self, _dir: &Path) -> Result<()> {
+        Err(checkpoint::unsupported("this policy"))
+    }
+
+    fn load_checkpoint(&mut self, _dir: &Path) -> Result<()> {
+        Err(checkpoint::unsupported("this policy"))
+    }
+
+    /// Write everything needed to continue training exactly: the checkpoint
+    /// plus optimizer state, RNG state and schedule progress.
+    fn save_training_state(&self, dir: &Path) -> Result<()> {
+        self.save_checkpoint(dir)
+    }
+
+    fn load_training_state(&mut self, dir: &Path) -> Result<()> {
+        self.load_checkpoint(dir)
+    }
 }
 
+#[derive(Default)]
 pub struct RandomPolicy;
 
 impl RandomPolicy {
@@ -19,22 +155,27 @@ impl RandomPolicy {
 
 impl Policy for RandomPolicy {
     type Observation = Vec<f64>;
-    type Action = f64;
+    type Action = usize;
 
-    fn forward(&mut self, obs: &[Self::Observation]) -> Vec<Self::Action> {
+    fn forward(
+        &mut self,
+        obs: &[Self::Observation],
+        _state: Option<&HiddenState>,
+    ) -> Result<PolicyOutput<Self::Action>> {
         let mut rng = rand::thread_rng(); // Renamed from thread_rng
         let mut actions = Vec::with_capacity(obs.len());
         for _ in 0..obs.len() {
             if rng.gen_bool(0.5) {
-                actions.push(1.0);
+                actions.push(1);
             } else {
-                actions.push(0.0);
+                actions.push(0);
             }
         }
-        actions
+        Ok(PolicyOutput::new(actions))
     }
 
-    fn learn(&mut self, _batch: &Batch<Self::Observation, Self::Action>) {
+    fn learn(&mut self, _batch: &Batch<Self::Observation, Self::Action>) -> Result<LearnStats> {
         // Random policy does not learn
+        Ok(LearnStats::new())
     }
 }
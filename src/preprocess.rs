@@ -0,0 +1,250 @@
+use crate::error::{HabaError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// An 8-bit image stored row-major with interleaved channels (HWC).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub height: usize,
+    pub width: usize,
+    pub channels: usize,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// A black image.
+    pub fn new(height: usize, width: usize, channels: usize) -> Self {
+        Self {
+            height,
+            width,
+            channels,
+            data: vec![0; height * width * channels],
+        }
+    }
+
+    pub fn from_raw(height: usize, width: usize, channels: usize, data: Vec<u8>) -> Result<Self> {
+        if data.len() != height * width * channels {
+            return Err(HabaError::Shape(format!(
+                "{}x{}x{} image needs {} bytes, got {}",
+                height,
+                width,
+                channels,
+                height * width * channels,
+                data.len()
+            )));
+        }
+        Ok(Self {
+            height,
+            width,
+            channels,
+            data,
+        })
+    }
+
+    pub fn pixel(&self, y: usize, x: usize) -> &[u8] {
+        let start = (y * self.width + x) * self.channels;
+        &self.data[start..start + self.channels]
+    }
+
+    pub fn set_pixel(&mut self, y: usize, x: usize, value: &[u8]) {
+        let start = (y * self.width + x) * self.channels;
+        self.data[start..start + self.channels].copy_from_slice(value);
+    }
+}
+
+/// Luminance of an RGB image (ITU-R BT.601 weights), ignoring any channels
+/// past the third. Single-channel images are returned unchanged.
+pub fn grayscale(image: &Image) -> Result<Image> {
+    match image.channels {
+        1 => return Ok(image.clone()),
+        0 | 2 => {
+            return Err(HabaError::Config(format!(
+                "grayscale needs 1 or at least 3 channels, got {}",
+                image.channels
+            )));
+        }
+        _ => {}
+    }
+    let data = image
+        .data
+        .chunks_exact(image.channels)
+        .map(|p| (0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64).round() as u8)
+        .collect();
+    Ok(Image {
+        height: image.height,
+        width: image.width,
+        channels: 1,
+        data,
+    })
+}
+
+/// Bilinear resize, sampling at pixel centres.
+pub fn resize(image: &Image, height: usize, width: usize) -> Result<Image> {
+    if height == 0 || width == 0 || image.height == 0 || image.width == 0 {
+        return Err(HabaError::Config(format!(
+            "cannot resize {}x{} to {}x{}: sizes must be non-zero",
+            image.height, image.width, height, width
+        )));
+    }
+    let mut out = Image::new(height, width, image.channels);
+    let scale_y = image.height as f64 / height as f64;
+    let scale_x = image.width as f64 / width as f64;
+    for y in 0..height {
+        let (y0, y1, wy) = sample_pos(y, scale_y, image.height);
+        for x in 0..width {
+            let (x0, x1, wx) = sample_pos(x, scale_x, image.width);
+            for c in 0..image.channels {
+                let at = |yy: usize, xx: usize| image.pixel(yy, xx)[c] as f64;
+                let top = at(y0, x0) * (1.0 - wx) + at(y0, x1) * wx;
+                let bottom = at(y1, x0) * (1.0 - wx) + at(y1, x1) * wx;
+                let value = top * (1.0 - wy) + bottom * wy;
+                out.data[(y * width + x) * image.channels + c] = value.round() as u8;
+            }
+        }
+    }
+    Ok(out)
+}
+
+// Neighbouring source indices and the weight of the second one
+fn sample_pos(i: usize, scale: f64, len: usize) -> (usize, usize, f64) {
+    let pos = ((i as f64 + 0.5) * scale - 0.5).clamp(0.0, (len - 1) as f64);
+    let lo = pos.floor() as usize;
+    (lo, (lo + 1).min(len - 1), pos - lo as f64)
+}
+
+/// Channel-first (CHW) floats in [0, 1].
+pub fn to_chw(image: &Image) -> Vec<f64> {
+    let mut out = Vec::with_capacity(image.data.len());
+    for c in 0..image.channels {
+        out.extend(
+            image
+                .data
+                .iter()
+                .skip(c)
+                .step_by(image.channels)
+                .map(|&v| v as f64 / 255.0),
+        );
+    }
+    out
+}
+
+/// Turns raw frames into network input: optional grayscale, resize, scaling
+/// to [0, 1] and channel-first stacking of the last `n_frames` frames, oldest
+/// first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preprocessor {
+    in_channels: usize,
+    grayscale: bool,
+    height: usize,
+    width: usize,
+    n_frames: usize,
+    frames: VecDeque<Vec<f64>>,
+}
+
+impl Preprocessor {
+    pub fn new(in_channels: usize, height: usize, width: usize, n_frames: usize) -> Self {
+        Self {
+            in_channels,
+            grayscale: false,
+            height,
+            width,
+            n_frames: n_frames.max(1),
+            frames: VecDeque::new(),
+        }
+    }
+
+    pub fn grayscale(mut self, enabled: bool) -> Self {
+        self.grayscale = enabled;
+        self
+    }
+
+    /// (channels, height, width) of the stacked output.
+    pub fn output_shape(&self) -> (usize, usize, usize) {
+        let channels = if self.grayscale { 1 } else { self.in_channels };
+        (channels * self.n_frames, self.height, self.width)
+    }
+
+    /// Start a new episode: the stack is filled with copies of `frame`.
+    pub fn reset(&mut self, frame: &Image) -> Result<Vec<f64>> {
+        let processed = self.process(frame)?;
+        self.frames = std::iter::repeat_n(processed, self.n_frames).collect();
+        Ok(self.stacked())
+    }
+
+    pub fn step(&mut self, frame: &Image) -> Result<Vec<f64>> {
+        if self.frames.is_empty() {
+            return self.reset(frame);
+        }
+        let processed = self.process(frame)?;
+        self.frames.pop_front();
+        self.frames.push_back(processed);
+        Ok(self.stacked())
+    }
+
+    fn process(&self, frame: &Image) -> Result<Vec<f64>> {
+        if frame.channels != self.in_channels {
+            return Err(HabaError::Shape(format!(
+                "expected {} channel frames, got {}",
+                self.in_channels, frame.channels
+            )));
+        }
+        let frame = if self.grayscale {
+            grayscale(frame)?
+        } else {
+            frame.clone()
+        };
+        Ok(to_chw(&resize(&frame, self.height, self.width)?))
+    }
+
+    fn stacked(&self) -> Vec<f64> {
+        self.frames.iter().flatten().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grayscale_and_scaling() {
+        let image = Image::from_raw(1, 2, 3, vec![255, 0, 0, 255, 255, 255]).unwrap();
+        let gray = grayscale(&image).unwrap();
+        assert_eq!(gray.data, vec![76, 255]);
+        let two_channel = Image::new(1, 2, 2);
+        assert!(matches!(grayscale(&two_channel), Err(HabaError::Config(_))));
+
+        let chw = to_chw(&image);
+        assert_eq!(chw, vec![1.0, 1.0, 0.0, 1.0, 0.0, 1.0]);
+        assert!(Image::from_raw(2, 2, 3, vec![0; 5]).is_err());
+    }
+
+    #[test]
+    fn test_resize() {
+        // Halving a 2x2 block image averages each block
+        let image = Image::from_raw(2, 4, 1, vec![0, 100, 200, 200, 0, 100, 200, 200]).unwrap();
+        let small = resize(&image, 1, 2).unwrap();
+        assert_eq!(small.data, vec![50, 200]);
+
+        // Same size is the identity
+        assert_eq!(resize(&image, 2, 4).unwrap(), image);
+
+        assert!(matches!(resize(&image, 0, 4), Err(HabaError::Config(_))));
+        assert!(matches!(
+            resize(&Image::new(0, 4, 1), 2, 2),
+            Err(HabaError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_frame_stack() {
+        let mut pre = Preprocessor::new(3, 1, 1, 3).grayscale(true);
+        assert_eq!(pre.output_shape(), (3, 1, 1));
+
+        let white = Image::from_raw(1, 1, 3, vec![255; 3]).unwrap();
+        let black = Image::new(1, 1, 3);
+        assert_eq!(pre.reset(&black).unwrap(), vec![0.0, 0.0, 0.0]);
+        assert_eq!(pre.step(&white).unwrap(), vec![0.0, 0.0, 1.0]);
+        assert_eq!(pre.step(&black).unwrap(), vec![0.0, 1.0, 0.0]);
+        assert!(pre.step(&Image::new(1, 1, 1)).is_err());
+    }
+}
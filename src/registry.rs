@@ -0,0 +1,362 @@
+use crate::acrobot::Acrobot;
+use crate::cartpole::{CartPole, Integrator, MaskedCartPole, PixelCartPole};
+use crate::env::Environment;
+use crate::error::{HabaError, Result};
+use crate::mountain_car::{MountainCar, MountainCarContinuous};
+use crate::pendulum::Pendulum;
+use crate::toy_text::{CliffWalking, FrozenLake, MAP_4X4, MAP_8X8, Taxi};
+use crate::venv::{DummyVectorEnv, ThreadVectorEnv, VectorEnv};
+use crate::wrappers::TimeLimit;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value, json};
+use std::any::{Any, type_name};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// An environment made by name. `Send`, so it can run in a `ThreadVectorEnv`.
+pub type BoxedEnv<O, A> = Box<dyn Environment<Observation = O, Action = A> + Send>;
+pub type BoxedVectorEnv<O, A> = Box<dyn VectorEnv<Observation = O, Action = A>>;
+
+type Factory<O, A> = Box<dyn Fn(&Value) -> Result<BoxedEnv<O, A>> + Send + Sync>;
+
+/// How `make_vec` runs its sub-envs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VectorMode {
+    /// Sequentially on the calling thread (`DummyVectorEnv`).
+    #[default]
+    Dummy,
+    /// One thread per sub-env (`ThreadVectorEnv`).
+    Parallel,
+}
+
+/// How to build a registered environment.
+#[derive(Clone)]
+pub struct EnvSpec {
+    pub id: String,
+    /// Keyword arguments passed to the constructor. `make` may override
+    /// them, but not add new ones.
+    pub kwargs: Value,
+    /// If set, made environments are wrapped in a `TimeLimit`.
+    pub max_episode_steps: Option<usize>,
+    /// Average return at which the task counts as solved.
+    pub reward_threshold: Option<f64>,
+    // A `Factory<O, A>` for the env's observation and action types
+    factory: Arc<dyn Any + Send + Sync>,
+    types: (&'static str, &'static str),
+}
+
+impl EnvSpec {
+    /// `entry` builds the environment from the merged kwargs.
+    pub fn new<E, F>(id: &str, entry: F) -> Self
+    where
+        E: Environment + Send + 'static,
+        E::Observation: 'static,
+        E::Action: 'static,
+        F: Fn(&Value) -> Result<E> + Send + Sync + 'static,
+    {
+        let factory: Factory<E::Observation, E::Action> =
+            Box::new(move |kwargs| Ok(Box::new(entry(kwargs)?) as BoxedEnv<_, _>));
+        Self {
+            id: id.to_string(),
+            kwargs: Value::Object(Map::new()),
+            max_episode_steps: None,
+            reward_threshold: None,
+            factory: Arc::new(factory),
+            types: (type_name::<E::Observation>(), type_name::<E::Action>()),
+        }
+    }
+
+    pub fn with_kwargs(mut self, kwargs: Value) -> Self {
+        self.kwargs = kwargs;
+        self
+    }
+
+    pub fn with_max_episode_steps(mut self, max_episode_steps: usize) -> Self {
+        self.max_episode_steps = Some(max_episode_steps);
+        self
+    }
+
+    pub fn with_reward_threshold(mut self, reward_threshold: f64) -> Self {
+        self.reward_threshold = Some(reward_threshold);
+        self
+    }
+
+    /// Build the environment with `overrides` applied to the default kwargs.
+    pub fn make<O: 'static, A: 'static>(&self, overrides: Value) -> Result<BoxedEnv<O, A>> {
+        let factory = self
+            .factory
+            .downcast_ref::<Factory<O, A>>()
+            .ok_or_else(|| {
+                HabaError::Config(format!(
+                    "{} has observations {} and actions {}, not {} and {}",
+                    self.id,
+                    self.types.0,
+                    self.types.1,
+                    type_name::<O>(),
+                    type_name::<A>()
+                ))
+            })?;
+        let env = factory(&self.merge_kwargs(overrides)?)?;
+        Ok(match self.max_episode_steps {
+            Some(max_steps) => Box::new(TimeLimit::new(env, max_steps)),
+            None => env,
+        })
+    }
+
+    fn merge_kwargs(&self, overrides: Value) -> Result<Value> {
+        let mut kwargs = self.kwargs.as_object().cloned().unwrap_or_default();
+        let overrides = match overrides {
+            Value::Null => Map::new(),
+            Value::Object(map) => map,
+            other => {
+                return Err(HabaError::Config(format!(
+                    "kwargs for {} must be an object, got {}",
+                    self.id, other
+                )));
+            }
+        };
+        for (key, value) in overrides {
+            if !kwargs.contains_key(&key) {
+                return Err(HabaError::Config(format!(
+                    "{} has no argument {:?}",
+                    self.id, key
+                )));
+            }
+            kwargs.insert(key, value);
+        }
+        Ok(Value::Object(kwargs))
+    }
+}
+
+impl Debug for EnvSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EnvSpec")
+            .field("id", &self.id)
+            .field("kwargs", &self.kwargs)
+            .field("max_episode_steps", &self.max_episode_steps)
+            .field("reward_threshold", &self.reward_threshold)
+            .finish()
+    }
+}
+
+/// Deserialize keyword argument `key`, for use in `EnvSpec` entry points.
+pub fn kwarg<T: DeserializeOwned>(kwargs: &Value, key: &str) -> Result<T> {
+    let value = kwargs
+        .get(key)
+        .ok_or_else(|| HabaError::Config(format!("missing argument {:?}", key)))?;
+    serde_json::from_value(value.clone())
+        .map_err(|e| HabaError::Config(format!("invalid argument {:?}: {}", key, e)))
+}
+
+fn cartpole(kwargs: &Value) -> Result<CartPole> {
+    Ok(CartPole::new().with_integrator(kwarg::<Integrator>(kwargs, "integrator")?))
+}
+
+fn pendulum(kwargs: &Value) -> Result<Pendulum> {
+    Ok(Pendulum::new(kwarg(kwargs, "g")?))
+}
+
+fn frozen_lake(kwargs: &Value) -> Result<FrozenLake> {
+    let map: &[&str] = match kwarg::<String>(kwargs, "map_name")?.as_str() {
+        "4x4" => &MAP_4X4,
+        "8x8" => &MAP_8X8,
+        other => {
+            return Err(HabaError::Config(format!(
+                "unknown FrozenLake map {:?}",
+                other
+            )));
+        }
+    };
+    FrozenLake::from_map(map, kwarg(kwargs, "is_slippery")?)
+}
+
+fn builtins() -> Vec<EnvSpec> {
+    let cartpole_kwargs = json!({"integrator": Integrator::Euler});
+    vec![
+        EnvSpec::new("CartPole-v0", cartpole)
+            .with_kwargs(cartpole_kwargs.clone())
+            .with_max_episode_steps(200)
+            .with_reward_threshold(195.0),
+        EnvSpec::new("CartPole-v1", cartpole)
+            .with_kwargs(cartpole_kwargs)
+            .with_max_episode_steps(500)
+            .with_reward_threshold(475.0),
+        EnvSpec::new("MaskedCartPole-v1", |_| Ok(MaskedCartPole::new()))
+            .with_max_episode_steps(500)
+            .with_reward_threshold(475.0),
+        EnvSpec::new("PixelCartPole-v1", |_| Ok(PixelCartPole::new()))
+            .with_max_episode_steps(500)
+            .with_reward_threshold(475.0),
+        EnvSpec::new("MountainCar-v0", |_| Ok(MountainCar::new()))
+            .with_max_episode_steps(200)
+            .with_reward_threshold(-110.0),
+        EnvSpec::new("MountainCarContinuous-v0", |_| {
+            Ok(MountainCarContinuous::new())
+        })
+        .with_max_episode_steps(999)
+        .with_reward_threshold(90.0),
+        // Same threshold as Gymnasium's Acrobot-v1 registration
+        EnvSpec::new("Acrobot-v1", |_| Ok(Acrobot::new()))
+            .with_max_episode_steps(500)
+            .with_reward_threshold(-100.0),
+        EnvSpec::new("Pendulum-v1", pendulum)
+            .with_kwargs(json!({"g": 10.0}))
+            .with_max_episode_steps(200),
+        EnvSpec::new("FrozenLake-v1", frozen_lake)
+            .with_kwargs(json!({"map_name": "4x4", "is_slippery": true}))
+            .with_max_episode_steps(100)
+            .with_reward_threshold(0.70),
+        EnvSpec::new("FrozenLake8x8-v1", frozen_lake)
+            .with_kwargs(json!({"map_name": "8x8", "is_slippery": true}))
+            .with_max_episode_steps(200)
+            .with_reward_threshold(0.85),
+        EnvSpec::new("CliffWalking-v0", |_| Ok(CliffWalking::new())),
+        EnvSpec::new("Taxi-v3", |_| Ok(Taxi::new()))
+            .with_max_episode_steps(200)
+            .with_reward_threshold(8.0),
+    ]
+}
+
+fn registry() -> &'static RwLock<HashMap<String, EnvSpec>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, EnvSpec>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let specs = builtins().into_iter().map(|s| (s.id.clone(), s));
+        RwLock::new(specs.collect())
+    })
+}
+
+/// Add an environment to the global registry. Ids must be unique.
+pub fn register(spec: EnvSpec) -> Result<()> {
+    let mut registry = registry().write().unwrap();
+    if registry.contains_key(&spec.id) {
+        return Err(HabaError::Config(format!(
+            "{} is already registered",
+            spec.id
+        )));
+    }
+    registry.insert(spec.id.clone(), spec);
+    Ok(())
+}
+
+pub fn spec(id: &str) -> Result<EnvSpec> {
+    registry()
+        .read()
+        .unwrap()
+        .get(id)
+        .cloned()
+        .ok_or_else(|| HabaError::Config(format!("no environment registered as {:?}", id)))
+}
+
+/// Registered ids, sorted.
+pub fn ids() -> Vec<String> {
+    let mut ids: Vec<String> = registry().read().unwrap().keys().cloned().collect();
+    ids.sort();
+    ids
+}
+
+/// Build a registered environment, e.g.
+/// `make::<Vec<f64>, usize>("CartPole-v1", json!({}))`. The observation and
+/// action types must match the environment's.
+pub fn make<O: 'static, A: 'static>(id: &str, kwargs: Value) -> Result<BoxedEnv<O, A>> {
+    spec(id)?.make(kwargs)
+}
+
+/// Build `n` copies of a registered environment with default kwargs.
+pub fn make_vec<O, A>(id: &str, n: usize, mode: VectorMode) -> Result<BoxedVectorEnv<O, A>>
+where
+    O: Clone + Debug + Send + 'static,
+    A: Clone + Debug + Send + 'static,
+{
+    let spec = spec(id)?;
+    let envs = (0..n)
+        .map(|_| spec.make(Value::Null))
+        .collect::<Result<Vec<BoxedEnv<O, A>>>>()?;
+    Ok(match mode {
+        VectorMode::Dummy => Box::new(DummyVectorEnv::new(envs)),
+        VectorMode::Parallel => Box::new(ThreadVectorEnv::new(envs)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockEnv;
+    use crate::spaces::Space;
+    use crate::wrappers::TRUNCATED;
+
+    #[test]
+    fn test_make_applies_time_limit() {
+        let mut env = make::<Vec<f64>, usize>("CartPole-v0", Value::Null).unwrap();
+        let mut obs = env.reset().unwrap();
+        let mut steps = 0;
+        loop {
+            steps += 1;
+            // Push towards where the pole is falling, which balances it
+            let push = if obs[2] + 0.5 * obs[3] > 0.0 { 1 } else { 0 };
+            let step = env.step(push).unwrap();
+            obs = step.obs;
+            if step.done {
+                assert!(step.info.unwrap().contains_key(TRUNCATED));
+                break;
+            }
+        }
+        assert_eq!(steps, 200);
+
+        assert!(make::<Vec<f64>, f64>("CartPole-v1", Value::Null).is_err());
+        assert!(make::<Vec<f64>, usize>("CartPole-v1", json!({"gravity": 1.0})).is_err());
+        assert!(make::<Vec<f64>, usize>("CartPole-v1", json!({"integrator": "rk4"})).is_err());
+        let kwargs = json!({"integrator": "semi_implicit_euler"});
+        assert!(make::<Vec<f64>, usize>("CartPole-v1", kwargs).is_ok());
+        assert!(make::<Vec<f64>, usize>("NoSuchEnv-v0", Value::Null).is_err());
+    }
+
+    #[test]
+    fn test_classic_control_defaults() {
+        assert_eq!(spec("MountainCar-v0").unwrap().max_episode_steps, Some(200));
+        assert_eq!(spec("Acrobot-v1").unwrap().reward_threshold, Some(-100.0));
+        assert!(make::<Vec<f64>, Vec<f64>>("MountainCarContinuous-v0", Value::Null).is_ok());
+
+        // Pendulum never terminates on its own, so the TimeLimit ends it
+        let mut env = make::<Vec<f64>, Vec<f64>>("Pendulum-v1", json!({"g": 9.81})).unwrap();
+        env.reset().unwrap();
+        let steps = (1..).find(|_| env.step(vec![0.0]).unwrap().done);
+        assert_eq!(steps, Some(200));
+    }
+
+    #[test]
+    fn test_toy_text_defaults() {
+        let kwargs = json!({"map_name": "8x8", "is_slippery": false});
+        let env = make::<usize, usize>("FrozenLake-v1", kwargs).unwrap();
+        assert_eq!(env.observation_space(), Space::discrete(64));
+        assert!(make::<usize, usize>("FrozenLake-v1", json!({"map_name": "3x3"})).is_err());
+        assert!(make::<usize, usize>("CliffWalking-v0", Value::Null).is_ok());
+        assert_eq!(spec("Taxi-v3").unwrap().max_episode_steps, Some(200));
+    }
+
+    #[test]
+    fn test_register_with_kwargs() {
+        let spec = EnvSpec::new("Mock-v0", |kwargs| {
+            let max_steps = kwargs["max_steps"].as_u64().unwrap_or(1);
+            Ok(MockEnv::new(max_steps as usize))
+        })
+        .with_kwargs(json!({"max_steps": 3}));
+        register(spec.clone()).unwrap();
+        assert!(register(spec).is_err());
+        assert!(ids().contains(&"Mock-v0".to_string()));
+
+        let mut env = make::<f64, ()>("Mock-v0", json!({"max_steps": 2})).unwrap();
+        env.reset().unwrap();
+        assert!(!env.step(()).unwrap().done);
+        assert!(env.step(()).unwrap().done);
+
+        for mode in [VectorMode::Dummy, VectorMode::Parallel] {
+            let mut venv = make_vec::<f64, ()>("Mock-v0", 2, mode).unwrap();
+            assert_eq!(venv.reset().unwrap(), vec![0.0, 0.0]);
+            let done: Vec<bool> = (0..3)
+                .map(|_| venv.step(&[(), ()]).unwrap()[0].done)
+                .collect();
+            assert_eq!(done, vec![false, false, true]);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// How a hyperparameter evolves over training.
+///
+/// A schedule maps a step count `t` to a value. Which step count is used
+/// (environment steps or gradient steps) is decided by the `Clock` of the
+/// `Scheduled` wrapper that holds it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Schedule {
+    /// Always the same value.
+    Constant(f64),
+    /// Linear interpolation from `start` to `end` over `duration` steps, then held at `end`.
+    Linear {
+        start: f64,
+        end: f64,
+        duration: usize,
+    },
+    /// `end + (start - end) * decay^t`, approaching `end` geometrically.
+    Exponential { start: f64, end: f64, decay: f64 },
+    /// Linear interpolation between `(step, value)` knots, held constant outside them.
+    /// Knots must be sorted by step.
+    Piecewise(Vec<(usize, f64)>),
+    /// Half-cosine annealing from `start` to `end` over `duration` steps, then held at `end`.
+    Cosine {
+        start: f64,
+        end: f64,
+        duration: usize,
+    },
+}
+
+impl Schedule {
+    pub fn value(&self, t: usize) -> f64 {
+        match self {
+            Schedule::Constant(v) => *v,
+            Schedule::Linear {
+                start,
+                end,
+                duration,
+            } => {
+                let frac = fraction(t, *duration);
+                start + frac * (end - start)
+            }
+            Schedule::Exponential { start, end, decay } => {
+                end + (start - end) * decay.powf(t as f64)
+            }
+            Schedule::Piecewise(knots) => {
+                let Some(&(first_t, first_v)) = knots.first() else {
+                    return 0.0;
+                };
+                if t <= first_t {
+                    return first_v;
+                }
+                for pair in knots.windows(2) {
+                    let (t0, v0) = pair[0];
+                    let (t1, v1) = pair[1];
+                    if t < t1 {
+                        let frac = (t - t0) as f64 / (t1 - t0) as f64;
+                        return v0 + frac * (v1 - v0);
+                    }
+                }
+                knots[knots.len() - 1].1
+            }
+            Schedule::Cosine {
+                start,
+                end,
+                duration,
+            } => {
+                let frac = fraction(t, *duration);
+                end + 0.5 * (start - end) * (1.0 + (PI * frac).cos())
+            }
+        }
+    }
+}
+
+impl From<f64> for Schedule {
+    fn from(value: f64) -> Self {
+        Schedule::Constant(value)
+    }
+}
+
+fn fraction(t: usize, duration: usize) -> f64 {
+    if duration == 0 {
+        1.0
+    } else {
+        (t as f64 / duration as f64).min(1.0)
+    }
+}
+
+/// The step counter that drives a schedule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Clock {
+    /// Number of environment transitions collected.
+    EnvStep,
+    /// Number of gradient updates performed.
+    GradStep,
+}
+
+/// Training progress as seen by a policy. Kept up to date by the `Trainer`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Progress {
+    pub env_step: usize,
+    pub grad_step: usize,
+}
+
+impl Progress {
+    pub fn get(&self, clock: Clock) -> usize {
+        match clock {
+            Clock::EnvStep => self.env_step,
+            Clock::GradStep => self.grad_step,
+        }
+    }
+}
+
+/// A schedule bound to the clock that drives it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scheduled {
+    pub schedule: Schedule,
+    pub clock: Clock,
+}
+
+impl Scheduled {
+    pub fn new(schedule: Schedule, clock: Clock) -> Self {
+        Self { schedule, clock }
+    }
+
+    pub fn value(&self, progress: Progress) -> f64 {
+        self.schedule.value(progress.get(self.clock))
+    }
+}
+
+impl From<f64> for Scheduled {
+    fn from(value: f64) -> Self {
+        Self::new(Schedule::Constant(value), Clock::EnvStep)
+    }
+}
+
+impl From<Schedule> for Scheduled {
+    fn from(schedule: Schedule) -> Self {
+        Self::new(schedule, Clock::EnvStep)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_linear_and_cosine_endpoints() {
+        let lin = Schedule::Linear {
+            start: 1.0,
+            end: 0.1,
+            duration: 100,
+        };
+        assert!(close(lin.value(0), 1.0));
+        assert!(close(lin.value(50), 0.55));
+        assert!(close(lin.value(1000), 0.1));
+
+        let cos = Schedule::Cosine {
+            start: 1.0,
+            end: 0.0,
+            duration: 10,
+        };
+        assert!(close(cos.value(0), 1.0));
+        assert!(close(cos.value(5), 0.5));
+        assert!(close(cos.value(10), 0.0));
+    }
+
+    #[test]
+    fn test_exponential_and_piecewise() {
+        let exp = Schedule::Exponential {
+            start: 1.0,
+            end: 0.0,
+            decay: 0.5,
+        };
+        assert!(close(exp.value(2), 0.25));
+
+        let pw = Schedule::Piecewise(vec![(10, 1.0), (20, 0.0), (30, 0.5)]);
+        assert!(close(pw.value(0), 1.0));
+        assert!(close(pw.value(15), 0.5));
+        assert!(close(pw.value(25), 0.25));
+        assert!(close(pw.value(100), 0.5));
+    }
+
+    #[test]
+    fn test_clock_selection() {
+        let s = Scheduled::new(
+            Schedule::Linear {
+                start: 0.0,
+                end: 1.0,
+                duration: 10,
+            },
+            Clock::GradStep,
+        );
+        let p = Progress {
+            env_step: 1000,
+            grad_step: 5,
+        };
+        assert!(close(s.value(p), 0.5));
+    }
+}
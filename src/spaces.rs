@@ -0,0 +1,210 @@
+use crate::error::{HabaError, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// The set of valid observations or actions of an environment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Space {
+    /// The integers `0..n`.
+    Discrete(usize),
+    /// Real arrays of `shape`, bounded elementwise. `low` and `high` are
+    /// flattened row-major. Unbounded dimensions use `f64::MAX`, which
+    /// unlike infinity survives a JSON round trip.
+    Box {
+        low: Vec<f64>,
+        high: Vec<f64>,
+        shape: Vec<usize>,
+    },
+}
+
+impl Space {
+    pub fn discrete(n: usize) -> Self {
+        Space::Discrete(n)
+    }
+
+    /// A one-dimensional box. Errors if the bounds differ in length.
+    pub fn bounded(low: Vec<f64>, high: Vec<f64>) -> Result<Self> {
+        if low.len() != high.len() {
+            return Err(HabaError::Config(format!(
+                "bounds differ in length: {} and {}",
+                low.len(),
+                high.len()
+            )));
+        }
+        Ok(Space::Box {
+            shape: vec![low.len()],
+            low,
+            high,
+        })
+    }
+
+    /// A box of `shape` with the same bounds in every dimension.
+    pub fn uniform(shape: &[usize], low: f64, high: f64) -> Self {
+        let size = shape.iter().product();
+        Space::Box {
+            low: vec![low; size],
+            high: vec![high; size],
+            shape: shape.to_vec(),
+        }
+    }
+
+    /// Shape of an element; empty for discrete spaces.
+    pub fn shape(&self) -> &[usize] {
+        match self {
+            Space::Discrete(_) => &[],
+            Space::Box { shape, .. } => shape,
+        }
+    }
+
+    pub fn contains<T: SpaceElement + ?Sized>(&self, x: &T) -> bool {
+        x.belongs_to(self)
+    }
+
+    /// A random element, or `None` if `T` cannot represent one.
+    pub fn sample<T: SpaceElement, R: Rng + ?Sized>(&self, rng: &mut R) -> Option<T> {
+        T::sample(self, rng)
+    }
+}
+
+// Uniform within finite bounds; unbounded sides get exponential tails, and
+// dimensions unbounded on both sides a standard normal.
+fn sample_interval<R: Rng + ?Sized>(low: f64, high: f64, rng: &mut R) -> f64 {
+    let bounded = |b: f64| b.abs() < f64::MAX / 2.0;
+    let exponential = |rng: &mut R| -(1.0 - rng.r#gen::<f64>()).ln();
+    match (bounded(low), bounded(high)) {
+        (true, true) => rng.gen_range(low..=high),
+        (true, false) => low + exponential(rng),
+        (false, true) => high - exponential(rng),
+        (false, false) => standard_normal(rng),
+    }
+}
+
+// Box-Muller; rand 0.8 has no normal distribution without rand_distr
+pub(crate) fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    let (u1, u2) = (1.0 - rng.r#gen::<f64>(), rng.r#gen::<f64>());
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// Values that can be checked against a `Space`.
+pub trait SpaceElement {
+    fn belongs_to(&self, space: &Space) -> bool;
+
+    /// A random element of `space`, or `None` if this type cannot hold one.
+    fn sample<R: Rng + ?Sized>(_space: &Space, _rng: &mut R) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+}
+
+impl SpaceElement for usize {
+    fn belongs_to(&self, space: &Space) -> bool {
+        matches!(space, Space::Discrete(n) if self < n)
+    }
+
+    fn sample<R: Rng + ?Sized>(space: &Space, rng: &mut R) -> Option<Self> {
+        match space {
+            Space::Discrete(n) if *n > 0 => Some(rng.gen_range(0..*n)),
+            _ => None,
+        }
+    }
+}
+
+/// Either a discrete action encoded as a float, or a scalar box element.
+impl SpaceElement for f64 {
+    fn belongs_to(&self, space: &Space) -> bool {
+        match space {
+            Space::Discrete(n) => self.fract() == 0.0 && *self >= 0.0 && *self < *n as f64,
+            Space::Box { low, high, .. } => low.len() == 1 && (low[0]..=high[0]).contains(self),
+        }
+    }
+
+    fn sample<R: Rng + ?Sized>(space: &Space, rng: &mut R) -> Option<Self> {
+        match space {
+            Space::Discrete(_) => usize::sample(space, rng).map(|a| a as f64),
+            Space::Box { low, high, .. } if low.len() == 1 => {
+                Some(sample_interval(low[0], high[0], rng))
+            }
+            Space::Box { .. } => None,
+        }
+    }
+}
+
+impl SpaceElement for [f64] {
+    fn belongs_to(&self, space: &Space) -> bool {
+        match space {
+            Space::Discrete(_) => false,
+            Space::Box { low, high, .. } => {
+                self.len() == low.len()
+                    && self
+                        .iter()
+                        .zip(low.iter().zip(high))
+                        .all(|(x, (lo, hi))| (lo..=hi).contains(&x))
+            }
+        }
+    }
+}
+
+impl SpaceElement for Vec<f64> {
+    fn belongs_to(&self, space: &Space) -> bool {
+        self.as_slice().belongs_to(space)
+    }
+
+    fn sample<R: Rng + ?Sized>(space: &Space, rng: &mut R) -> Option<Self> {
+        match space {
+            Space::Discrete(_) => None,
+            Space::Box { low, high, .. } => Some(
+                low.iter()
+                    .zip(high)
+                    .map(|(&lo, &hi)| sample_interval(lo, hi, rng))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// The single action of environments that take no decisions.
+impl SpaceElement for () {
+    fn belongs_to(&self, space: &Space) -> bool {
+        *space == Space::Discrete(1)
+    }
+
+    fn sample<R: Rng + ?Sized>(space: &Space, _rng: &mut R) -> Option<Self> {
+        (*space == Space::Discrete(1)).then_some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_contains() {
+        let discrete = Space::discrete(2);
+        assert!(discrete.contains(&1usize));
+        assert!(discrete.contains(&1.0));
+        assert!(!discrete.contains(&2usize));
+        assert!(!discrete.contains(&0.5));
+
+        assert!(Space::bounded(vec![0.0], vec![]).is_err());
+        let boxed = Space::bounded(vec![-1.0, 0.0], vec![1.0, f64::MAX]).unwrap();
+        assert_eq!(boxed.shape(), &[2]);
+        assert!(boxed.contains(&vec![0.5, 1e9]));
+        assert!(!boxed.contains(&vec![1.5, 0.0]));
+        assert!(!boxed.contains(&vec![0.0]));
+
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+        for _ in 0..100 {
+            assert!(boxed.contains(&boxed.sample::<Vec<f64>, _>(&mut rng).unwrap()));
+            assert!(discrete.contains(&discrete.sample::<usize, _>(&mut rng).unwrap()));
+        }
+        assert!(discrete.sample::<Vec<f64>, _>(&mut rng).is_none());
+
+        let image = Space::uniform(&[3, 4, 5], 0.0, 1.0);
+        assert!(image.contains(&vec![0.5; 60]));
+        let json = serde_json::to_value(&boxed).unwrap();
+        assert_eq!(serde_json::from_value::<Space>(json).unwrap(), boxed);
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Named scalars reported by `Policy::learn`, e.g. `loss`, `q_mean`, `lr`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LearnStats {
+    values: BTreeMap<String, f64>,
+}
+
+impl LearnStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, value: f64) {
+        self.values.insert(name.into(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.values.get(name).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), *v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl std::fmt::Display for LearnStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (name, value)) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {:.4}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Averages `LearnStats` over many gradient steps (one epoch, typically).
+/// Each key is averaged over the updates that reported it.
+#[derive(Debug, Clone, Default)]
+pub struct StatsAggregator {
+    sums: BTreeMap<String, (f64, usize)>,
+}
+
+impl StatsAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, stats: &LearnStats) {
+        for (name, value) in stats.iter() {
+            let entry = self.sums.entry(name.to_string()).or_insert((0.0, 0));
+            entry.0 += value;
+            entry.1 += 1;
+        }
+    }
+
+    pub fn mean(&self) -> LearnStats {
+        let mut out = LearnStats::new();
+        for (name, (sum, count)) in &self.sums {
+            out.insert(name.clone(), sum / *count as f64);
+        }
+        out
+    }
+}
+
+/// What a call to `Collector::collect` produced.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CollectStats {
+    pub n_episodes: usize,
+    pub n_steps: usize,
+    // Returns and lengths of the episodes that finished during the call
+    pub returns: Vec<f64>,
+    pub lens: Vec<usize>,
+    // Which sub-env ran each of those episodes
+    pub env_ids: Vec<usize>,
+    pub returns_mean: f64,
+    pub returns_std: f64,
+    pub returns_min: f64,
+    pub returns_max: f64,
+    pub lens_mean: f64,
+    pub duration: Duration,
+    pub steps_per_sec: f64,
+}
+
+impl CollectStats {
+    pub fn new(
+        returns: Vec<f64>,
+        lens: Vec<usize>,
+        env_ids: Vec<usize>,
+        n_steps: usize,
+        duration: Duration,
+    ) -> Self {
+        let (returns_mean, returns_std) = mean_std(&returns);
+        let returns_min = returns.iter().copied().reduce(f64::min).unwrap_or(0.0);
+        let returns_max = returns.iter().copied().reduce(f64::max).unwrap_or(0.0);
+        let lens_f: Vec<f64> = lens.iter().map(|&l| l as f64).collect();
+        let (lens_mean, _) = mean_std(&lens_f);
+        let secs = duration.as_secs_f64();
+        let steps_per_sec = if secs > 0.0 {
+            n_steps as f64 / secs
+        } else {
+            0.0
+        };
+
+        Self {
+            n_episodes: returns.len(),
+            n_steps,
+            returns,
+            lens,
+            env_ids,
+            returns_mean,
+            returns_std,
+            returns_min,
+            returns_max,
+            lens_mean,
+            duration,
+            steps_per_sec,
+        }
+    }
+}
+
+/// Summary of one training epoch, as kept in `Trainer::history`.
+#[derive(Debug, Clone)]
+pub struct EpochStats {
+    pub epoch: usize,
+    pub collect: CollectStats,
+    // Mean of every learn statistic over the epoch's gradient steps
+    pub learn: LearnStats,
+    pub test: Option<CollectStats>,
+}
+
+/// Outcome of a full `OffPolicyTrainer` run.
+#[derive(Debug, Clone)]
+pub struct TrainSummary {
+    /// Epochs completed, fewer than configured if the run stopped early.
+    pub epochs: usize,
+    pub env_steps: usize,
+    pub grad_steps: usize,
+    /// Highest mean test return, or training return without a test env,
+    /// and the epoch it was reached in.
+    pub best_reward: Option<f64>,
+    pub best_epoch: Option<usize>,
+    /// Whether the stop function ended the run.
+    pub stopped: bool,
+    pub duration: Duration,
+    pub history: Vec<EpochStats>,
+}
+
+// Population mean and standard deviation; zeros for an empty slice
+fn mean_std(xs: &[f64]) -> (f64, f64) {
+    if xs.is_empty() {
+        return (0.0, 0.0);
+    }
+    let n = xs.len() as f64;
+    let mean = xs.iter().sum::<f64>() / n;
+    let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+    (mean, var.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mean_std() {
+        assert_eq!(mean_std(&[]), (0.0, 0.0));
+        assert_eq!(mean_std(&[3.0]), (3.0, 0.0));
+        // Population, not sample, standard deviation
+        let (mean, std) = mean_std(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!((mean, std), (5.0, 2.0));
+    }
+
+    #[test]
+    fn test_collect_stats() {
+        let stats = CollectStats::new(
+            vec![1.0, 3.0, 5.0],
+            vec![10, 20, 60],
+            vec![0, 1, 0],
+            90,
+            Duration::from_secs(2),
+        );
+        assert_eq!(stats.n_episodes, 3);
+        assert_eq!(stats.returns_mean, 3.0);
+        assert!((stats.returns_std - (8.0f64 / 3.0).sqrt()).abs() < 1e-12);
+        assert_eq!((stats.returns_min, stats.returns_max), (1.0, 5.0));
+        assert_eq!(stats.lens_mean, 30.0);
+        assert_eq!(stats.steps_per_sec, 45.0);
+
+        // Steps without a finished episode report zeros, not NaN
+        let empty = CollectStats::new(Vec::new(), Vec::new(), Vec::new(), 5, Duration::ZERO);
+        assert_eq!(empty.n_episodes, 0);
+        assert_eq!(empty.n_steps, 5);
+        assert_eq!((empty.returns_mean, empty.returns_std), (0.0, 0.0));
+        assert_eq!((empty.returns_min, empty.returns_max), (0.0, 0.0));
+        assert_eq!(empty.steps_per_sec, 0.0);
+    }
+
+    #[test]
+    fn test_stats_aggregator() {
+        assert!(StatsAggregator::new().mean().is_empty());
+
+        let mut aggregator = StatsAggregator::new();
+        for (loss, lr) in [(1.0, Some(0.1)), (2.0, None), (6.0, Some(0.3))] {
+            let mut stats = LearnStats::new();
+            stats.insert("loss", loss);
+            if let Some(lr) = lr {
+                stats.insert("lr", lr);
+            }
+            aggregator.add(&stats);
+        }
+        let mean = aggregator.mean();
+        assert_eq!(mean.get("loss"), Some(3.0));
+        // Averaged over the updates that reported it
+        assert!((mean.get("lr").unwrap() - 0.2).abs() < 1e-12);
+        assert_eq!(mean.to_string(), "loss: 3.0000, lr: 0.2000");
+    }
+}
@@ -0,0 +1,468 @@
+use crate::batch::Batch;
+use crate::checkpoint;
+use crate::error::{HabaError, Result};
+use crate::policy::{HiddenState, Policy, PolicyMode, PolicyOutput};
+use crate::schedule::{Progress, Scheduled};
+use crate::stats::LearnStats;
+use crate::util::argmax;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const TRAINING_STATE: &str = "training_state.json";
+
+/// The bootstrap target a `TabularPolicy` learns towards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TabularAlgorithm {
+    /// `r + γ max_a Q(s', a)`.
+    QLearning,
+    /// `r + γ Q(s', a')` for the action `a'` actually taken next.
+    Sarsa,
+    /// `r + γ Σ_a π(a|s') Q(s', a)` under the epsilon-greedy policy.
+    ExpectedSarsa,
+    /// Two tables, each evaluating the other's greedy action.
+    DoubleQLearning,
+}
+
+// Written as the checkpoint manifest; the tables are small enough for JSON
+#[derive(Debug, Serialize, Deserialize)]
+struct TabularManifest {
+    algorithm: String,
+    haba_version: String,
+    method: TabularAlgorithm,
+    n_states: usize,
+    n_actions: usize,
+    gamma: f64,
+    epsilon: Scheduled,
+    lr: Scheduled,
+    update_count: usize,
+    q: Vec<f64>,
+    q2: Vec<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TabularTrainingState {
+    progress: Progress,
+    rng: ChaCha8Rng,
+}
+
+/// An epsilon-greedy policy over a Q-table, for `Discrete` observation and
+/// action spaces. `learn` applies one update per transition, in batch order.
+pub struct TabularPolicy {
+    method: TabularAlgorithm,
+    n_states: usize,
+    n_actions: usize,
+    // Row-major n_states x n_actions
+    q: Vec<f64>,
+    // Second table for double Q-learning, empty otherwise
+    q2: Vec<f64>,
+    gamma: f64,
+    epsilon: Scheduled,
+    lr: Scheduled,
+    update_count: usize,
+    progress: Progress,
+    mode: PolicyMode,
+    rng: ChaCha8Rng,
+}
+
+impl TabularPolicy {
+    /// A zero-initialised table with learning rate 0.1.
+    pub fn new(
+        method: TabularAlgorithm,
+        n_states: usize,
+        n_actions: usize,
+        gamma: f64,
+        epsilon: impl Into<Scheduled>,
+    ) -> Self {
+        let size = n_states * n_actions;
+        let q2 = match method {
+            TabularAlgorithm::DoubleQLearning => vec![0.0; size],
+            _ => Vec::new(),
+        };
+        Self {
+            method,
+            n_states,
+            n_actions,
+            q: vec![0.0; size],
+            q2,
+            gamma,
+            epsilon: epsilon.into(),
+            lr: Scheduled::from(0.1),
+            update_count: 0,
+            progress: Progress::default(),
+            mode: PolicyMode::Train,
+            rng: ChaCha8Rng::from_entropy(),
+        }
+    }
+
+    /// Replace the learning rate (constant 0.1 by default) with a schedule.
+    pub fn with_lr(mut self, lr: impl Into<Scheduled>) -> Self {
+        self.lr = lr.into();
+        self
+    }
+
+    /// Start every entry at `value`; optimistic values drive exploration.
+    pub fn with_initial_value(mut self, value: f64) -> Self {
+        self.q.fill(value);
+        self.q2.fill(value);
+        self
+    }
+
+    pub fn method(&self) -> TabularAlgorithm {
+        self.method
+    }
+
+    /// Exploration rate at the current training progress. Always 0 in eval mode.
+    pub fn epsilon(&self) -> f64 {
+        match self.mode {
+            PolicyMode::Train => self.epsilon.value(self.progress),
+            PolicyMode::Eval => 0.0,
+        }
+    }
+
+    /// Learning rate at the current training progress.
+    pub fn learning_rate(&self) -> f64 {
+        self.lr.value(self.progress)
+    }
+
+    /// Number of `learn` calls so far.
+    pub fn update_count(&self) -> usize {
+        self.update_count
+    }
+
+    /// Action values of `state`; for double Q-learning, the mean of both tables.
+    pub fn q_values(&self, state: usize) -> Vec<f64> {
+        let row = state * self.n_actions..(state + 1) * self.n_actions;
+        match self.method {
+            TabularAlgorithm::DoubleQLearning => self.q[row.clone()]
+                .iter()
+                .zip(&self.q2[row])
+                .map(|(a, b)| (a + b) / 2.0)
+                .collect(),
+            _ => self.q[row].to_vec(),
+        }
+    }
+
+    /// The whole table as one row per state, comparable to `dp::Solution::q_values`.
+    pub fn q_table(&self) -> Vec<Vec<f64>> {
+        (0..self.n_states).map(|s| self.q_values(s)).collect()
+    }
+
+    /// The greedy action in every state.
+    pub fn greedy_policy(&self) -> Vec<usize> {
+        (0..self.n_states)
+            .map(|s| argmax(self.q_values(s)))
+            .collect()
+    }
+
+    fn check_state(&self, state: usize) -> Result<()> {
+        if state >= self.n_states {
+            return Err(HabaError::Shape(format!(
+                "state {} out of range for {} states",
+                state, self.n_states
+            )));
+        }
+        Ok(())
+    }
+
+    // Expected value of `row` under the epsilon-greedy policy
+    fn expected(&self, row: &[f64]) -> f64 {
+        let epsilon = self.epsilon().clamp(0.0, 1.0);
+        let uniform = row.iter().sum::<f64>() / row.len() as f64;
+        (1.0 - epsilon) * row[argmax(row)] + epsilon * uniform
+    }
+}
+
+impl Policy for TabularPolicy {
+    type Observation = usize;
+    type Action = usize;
+
+    fn forward(
+        &mut self,
+        obs: &[Self::Observation],
+        _state: Option<&HiddenState>,
+    ) -> Result<PolicyOutput<Self::Action>> {
+        let epsilon = self.epsilon().clamp(0.0, 1.0);
+        let mut actions = Vec::with_capacity(obs.len());
+        for &state in obs {
+            self.check_state(state)?;
+            // Eval mode must not consume randomness, as in DQN
+            if epsilon > 0.0 && self.rng.gen_bool(epsilon) {
+                actions.push(self.rng.gen_range(0..self.n_actions));
+            } else {
+                actions.push(argmax(self.q_values(state)));
+            }
+        }
+        Ok(PolicyOutput::new(actions))
+    }
+
+    fn learn(&mut self, batch: &Batch<Self::Observation, Self::Action>) -> Result<LearnStats> {
+        let act_next = match (self.method, &batch.act_next) {
+            (TabularAlgorithm::Sarsa, None) => {
+                return Err(HabaError::Config(
+                    "SARSA needs the next action of every transition".to_string(),
+                ));
+            }
+            (_, act_next) => act_next.as_deref(),
+        };
+        let lr = self.learning_rate();
+        let n = self.n_actions;
+        let mut td_sum = 0.0;
+        let mut q_sum = 0.0;
+        for i in 0..batch.len() {
+            let (s, a, s_next) = (batch.obs[i], batch.act[i], batch.obs_next[i]);
+            self.check_state(s)?;
+            self.check_state(s_next)?;
+            if a >= n {
+                return Err(HabaError::Shape(format!(
+                    "action {} out of range for {} actions",
+                    a, n
+                )));
+            }
+
+            // Double Q-learning updates one of its tables at random
+            let update_second =
+                self.method == TabularAlgorithm::DoubleQLearning && self.rng.gen_bool(0.5);
+            let (table, other) = if update_second {
+                (&self.q2, &self.q)
+            } else {
+                (&self.q, &self.q2)
+            };
+            let next = &table[s_next * n..(s_next + 1) * n];
+            let bootstrap = match self.method {
+                TabularAlgorithm::QLearning => next[argmax(next)],
+                TabularAlgorithm::Sarsa => next[act_next.map_or(0, |acts| acts[i])],
+                TabularAlgorithm::ExpectedSarsa => self.expected(next),
+                TabularAlgorithm::DoubleQLearning => other[s_next * n + argmax(next)],
+            };
+            let future = if batch.done[i] { 0.0 } else { bootstrap };
+            let target = batch.rew[i] + self.gamma * future;
+
+            let table = if update_second {
+                &mut self.q2
+            } else {
+                &mut self.q
+            };
+            let td_error = target - table[s * n + a];
+            table[s * n + a] += lr * td_error;
+            td_sum += td_error.abs();
+            q_sum += table[s * n + a];
+        }
+        self.update_count += 1;
+
+        let len = batch.len().max(1) as f64;
+        let mut stats = LearnStats::new();
+        stats.insert("td_error", td_sum / len);
+        stats.insert("q_mean", q_sum / len);
+        stats.insert("lr", lr);
+        Ok(stats)
+    }
+
+    fn needs_next_action(&self) -> bool {
+        self.method == TabularAlgorithm::Sarsa
+    }
+
+    fn on_policy(&self) -> bool {
+        matches!(
+            self.method,
+            TabularAlgorithm::Sarsa | TabularAlgorithm::ExpectedSarsa
+        )
+    }
+
+    /// Writes the tables and hyperparameters as `manifest.json`.
+    fn save_checkpoint(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)?;
+        let manifest = TabularManifest {
+            algorithm: "tabular".to_string(),
+            haba_version: env!("CARGO_PKG_VERSION").to_string(),
+            method: self.method,
+            n_states: self.n_states,
+            n_actions: self.n_actions,
+            gamma: self.gamma,
+            epsilon: self.epsilon.clone(),
+            lr: self.lr.clone(),
+            update_count: self.update_count,
+            q: self.q.clone(),
+            q2: self.q2.clone(),
+        };
+        checkpoint::write_json(&dir.join(checkpoint::MANIFEST_FILE), &manifest)
+    }
+
+    fn load_checkpoint(&mut self, dir: &Path) -> Result<()> {
+        let manifest: TabularManifest =
+            checkpoint::read_json(&dir.join(checkpoint::MANIFEST_FILE))?;
+        if manifest.algorithm != "tabular" || manifest.method != self.method {
+            return Err(HabaError::Checkpoint(format!(
+                "expected a tabular {:?} checkpoint, found {} {:?}",
+                self.method, manifest.algorithm, manifest.method
+            )));
+        }
+        if (manifest.n_states, manifest.n_actions) != (self.n_states, self.n_actions) {
+            return Err(HabaError::Checkpoint(format!(
+                "table shape mismatch: checkpoint has {}x{}, policy has {}x{}",
+                manifest.n_states, manifest.n_actions, self.n_states, self.n_actions
+            )));
+        }
+        self.q = manifest.q;
+        self.q2 = manifest.q2;
+        self.gamma = manifest.gamma;
+        self.epsilon = manifest.epsilon;
+        self.lr = manifest.lr;
+        self.update_count = manifest.update_count;
+        Ok(())
+    }
+
+    /// Adds the RNG and schedule progress to the checkpoint.
+    fn save_training_state(&self, dir: &Path) -> Result<()> {
+        self.save_checkpoint(dir)?;
+        let state = TabularTrainingState {
+            progress: self.progress,
+            rng: self.rng.clone(),
+        };
+        checkpoint::write_json(&dir.join(TRAINING_STATE), &state)
+    }
+
+    fn load_training_state(&mut self, dir: &Path) -> Result<()> {
+        self.load_checkpoint(dir)?;
+        let state: TabularTrainingState = checkpoint::read_json(&dir.join(TRAINING_STATE))?;
+        self.rng = state.rng;
+        self.progress = state.progress;
+        Ok(())
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    fn set_progress(&mut self, progress: Progress) {
+        self.progress = progress;
+    }
+
+    fn set_mode(&mut self, mode: PolicyMode) {
+        self.mode = mode;
+    }
+
+    fn mode(&self) -> PolicyMode {
+        self.mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::ReplayBuffer;
+    use crate::collector::Collector;
+    use crate::dp::{DpConfig, value_iteration};
+    use crate::toy_text::GridWorld;
+    use crate::trainer::Trainer;
+    use crate::venv::DummyVectorEnv;
+
+    fn one_step(act_next: Option<usize>) -> Batch<usize, usize> {
+        let mut batch = Batch::new(vec![0], vec![1], vec![1.0], vec![false], vec![1]);
+        batch.act_next = act_next.map(|a| vec![a]);
+        batch
+    }
+
+    #[test]
+    fn test_targets() {
+        // Q(1) = [2, 0]; each method bootstraps a different value from it
+        let cases = [
+            (TabularAlgorithm::QLearning, 2.0),
+            (TabularAlgorithm::Sarsa, 0.0),
+            (TabularAlgorithm::ExpectedSarsa, 0.75 * 2.0),
+        ];
+        for (method, bootstrap) in cases {
+            let mut policy = TabularPolicy::new(method, 2, 2, 0.5, 0.5).with_lr(0.5);
+            policy.q[2] = 2.0;
+            let stats = policy.learn(&one_step(Some(1))).unwrap();
+            let expected = 0.5 * (1.0 + 0.5 * bootstrap);
+            assert!(
+                (policy.q_values(0)[1] - expected).abs() < 1e-12,
+                "{:?}",
+                method
+            );
+            assert_eq!(stats.get("lr"), Some(0.5));
+        }
+
+        let mut sarsa = TabularPolicy::new(TabularAlgorithm::Sarsa, 2, 2, 0.5, 0.5);
+        assert!(sarsa.needs_next_action());
+        assert!(sarsa.learn(&one_step(None)).is_err());
+
+        // Each double Q update moves exactly one table
+        let mut double = TabularPolicy::new(TabularAlgorithm::DoubleQLearning, 2, 2, 0.5, 0.5);
+        double.seed(0);
+        double.learn(&one_step(None)).unwrap();
+        assert_eq!(double.q[1] + double.q2[1], 0.1);
+        assert_eq!(double.q_values(0)[1], 0.05);
+    }
+
+    #[test]
+    fn test_learns_optimal_q_values() {
+        let map = ["S..", "#.G"];
+        let solution = value_iteration(
+            GridWorld::from_map(&map).unwrap().model(),
+            &DpConfig {
+                gamma: 0.9,
+                ..DpConfig::default()
+            },
+        )
+        .unwrap();
+
+        for method in [
+            TabularAlgorithm::QLearning,
+            TabularAlgorithm::DoubleQLearning,
+        ] {
+            // Uniformly random behaviour visits every state-action pair
+            let mut policy = TabularPolicy::new(method, 6, 4, 0.9, 1.0).with_lr(0.5);
+            policy.seed(1);
+            let venv = DummyVectorEnv::new(vec![GridWorld::from_map(&map).unwrap()]);
+            let collector = Collector::new(venv, policy, Some(ReplayBuffer::new(1000))).unwrap();
+            let mut trainer = Trainer::new(collector, 20, 100, 32);
+            trainer.seed(1).unwrap();
+            trainer.train().unwrap();
+
+            let learned = trainer.collector().policy().q_table();
+            // The wall cell is never visited
+            for state in [0, 1, 2, 4, 5] {
+                for (q, q_star) in learned[state].iter().zip(&solution.q_values[state]) {
+                    assert!((q - q_star).abs() < 1e-3, "{:?}: {:?}", method, learned);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sarsa_trains_on_policy() {
+        // Steps cost 1, so untried actions look best and get explored
+        let env = || {
+            GridWorld::from_map(&["S..G"])
+                .unwrap()
+                .with_rewards(-1.0, 0.0, 0.0)
+        };
+        let venv = DummyVectorEnv::new(vec![env(), env()]);
+        let policy = TabularPolicy::new(TabularAlgorithm::Sarsa, 4, 4, 0.9, 0.1).with_lr(0.5);
+        let collector = Collector::new(venv, policy, Some(ReplayBuffer::new(100))).unwrap();
+        let mut trainer = Trainer::new(collector, 10, 40, 16);
+        trainer.seed(2).unwrap();
+        trainer.train().unwrap();
+        // One update per epoch on the freshly collected data, never a replay sample
+        assert_eq!(trainer.collector().policy().update_count(), 10);
+        // Heading right is greedy everywhere on the way to the goal
+        assert_eq!(
+            &trainer.collector().policy().greedy_policy()[..3],
+            &[2, 2, 2]
+        );
+
+        let dir = std::env::temp_dir().join(format!("haba_tabular_{}", std::process::id()));
+        let policy = trainer.collector().policy();
+        policy.save_checkpoint(&dir).unwrap();
+        let mut restored = TabularPolicy::new(TabularAlgorithm::Sarsa, 4, 4, 0.5, 0.0);
+        restored.load_checkpoint(&dir).unwrap();
+        assert_eq!(restored.q_table(), policy.q_table());
+        let mut wrong = TabularPolicy::new(TabularAlgorithm::QLearning, 4, 4, 0.5, 0.0);
+        assert!(wrong.load_checkpoint(&dir).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,767 @@
+use crate::checkpoint;
+use crate::dp::{Transition, TransitionModel};
+use crate::env::{EnvResult, Environment, Info, Phase, Step};
+use crate::error::HabaError;
+use crate::spaces::Space;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde_json::Value;
+
+// Episode state shared by every tabular env: sample from the model
+struct Tabular {
+    model: TransitionModel,
+    state: usize,
+    rng: ChaCha8Rng,
+    phase: Phase,
+}
+
+impl Tabular {
+    fn new(model: TransitionModel) -> Self {
+        Self {
+            model,
+            state: 0,
+            rng: ChaCha8Rng::from_entropy(),
+            phase: Phase::NeedsReset,
+        }
+    }
+
+    // Index drawn from a categorical distribution by inverse CDF
+    fn sample(&mut self, probs: impl Iterator<Item = f64>) -> usize {
+        let u: f64 = self.rng.r#gen();
+        let mut cumulative = 0.0;
+        let mut last = 0;
+        for (i, p) in probs.enumerate() {
+            cumulative += p;
+            if p > 0.0 {
+                last = i;
+                if u < cumulative {
+                    return i;
+                }
+            }
+        }
+        last
+    }
+
+    fn reset(&mut self) -> usize {
+        let initial = self.model.initial().to_vec();
+        self.state = self.sample(initial.into_iter());
+        self.phase = Phase::Running;
+        self.state
+    }
+
+    fn step(&mut self, env: &str, action: usize) -> EnvResult<Step<usize>> {
+        self.phase.check_step(env)?;
+        if action >= self.model.n_actions() {
+            return Err(HabaError::Env(format!("invalid {} action {}", env, action)));
+        }
+        let outcomes = self.model.transitions(self.state, action).to_vec();
+        let t = outcomes[self.sample(outcomes.iter().map(|t| t.prob))];
+        self.state = t.next_state;
+        if t.done {
+            self.phase = Phase::Terminated;
+        }
+        Ok(Step {
+            obs: t.next_state,
+            reward: t.reward,
+            done: t.done,
+            info: Some(Info::from([("prob".to_string(), t.prob.into())])),
+        })
+    }
+}
+
+// Environment impl and model accessors for a struct with a `tabular` field
+macro_rules! impl_tabular_env {
+    ($env:ty, $name:literal) => {
+        impl $env {
+            /// The full transition model P(s'|s,a).
+            pub fn model(&self) -> &TransitionModel {
+                &self.tabular.model
+            }
+
+            /// The current state index.
+            pub fn state(&self) -> usize {
+                self.tabular.state
+            }
+        }
+
+        impl Environment for $env {
+            type Observation = usize;
+            type Action = usize;
+
+            fn reset(&mut self) -> EnvResult<Self::Observation> {
+                Ok(self.tabular.reset())
+            }
+
+            fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+                self.tabular.step($name, action)
+            }
+
+            fn observation_space(&self) -> Space {
+                Space::discrete(self.tabular.model.n_states())
+            }
+
+            fn action_space(&self) -> Space {
+                Space::discrete(self.tabular.model.n_actions())
+            }
+
+            fn seed(&mut self, seed: u64) {
+                self.tabular.rng = ChaCha8Rng::seed_from_u64(seed);
+            }
+
+            fn state_dict(&self) -> EnvResult<Value> {
+                Ok(serde_json::json!({
+                    "state": self.tabular.state,
+                    "phase": self.tabular.phase,
+                    "rng": self.tabular.rng,
+                }))
+            }
+
+            fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+                let index: usize = checkpoint::field(state, "state")?;
+                if index >= self.tabular.model.n_states() {
+                    return Err(HabaError::Checkpoint(format!(
+                        "{} has no state {}",
+                        $name, index
+                    )));
+                }
+                self.tabular.state = index;
+                self.tabular.phase = checkpoint::field(state, "phase")?;
+                self.tabular.rng = checkpoint::field(state, "rng")?;
+                Ok(())
+            }
+        }
+    };
+}
+
+// A rectangular character map, indexed row-major
+#[derive(Debug, Clone)]
+struct Grid {
+    cells: Vec<Vec<u8>>,
+}
+
+impl Grid {
+    fn parse(map: &[&str], allowed: &str) -> EnvResult<Self> {
+        let cells: Vec<Vec<u8>> = map.iter().map(|row| row.as_bytes().to_vec()).collect();
+        let ncol = cells.first().map_or(0, |row| row.len());
+        if ncol == 0 || cells.iter().any(|row| row.len() != ncol) {
+            return Err(HabaError::Config(
+                "map must be a non-empty rectangle".to_string(),
+            ));
+        }
+        if let Some(&c) = cells
+            .iter()
+            .flatten()
+            .find(|c| !allowed.contains(**c as char))
+        {
+            return Err(HabaError::Config(format!(
+                "unexpected {:?} in map; expected one of {:?}",
+                c as char, allowed
+            )));
+        }
+        if !cells.iter().flatten().any(|&c| c == b'S') {
+            return Err(HabaError::Config("map has no start cell 'S'".to_string()));
+        }
+        Ok(Self { cells })
+    }
+
+    fn nrow(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn ncol(&self) -> usize {
+        self.cells[0].len()
+    }
+
+    fn at(&self, state: usize) -> u8 {
+        self.cells[state / self.ncol()][state % self.ncol()]
+    }
+
+    // Uniform over the start cells
+    fn initial(&self) -> Vec<f64> {
+        let starts = self.cells.iter().flatten().filter(|&&c| c == b'S').count();
+        let p = 1.0 / starts as f64;
+        self.cells
+            .iter()
+            .flatten()
+            .map(|&c| if c == b'S' { p } else { 0.0 })
+            .collect()
+    }
+
+    // Move one cell left (0), down (1), right (2) or up (3), stopping at
+    // the edges and at walls
+    fn moved(&self, state: usize, direction: usize) -> usize {
+        let (row, col) = (state / self.ncol(), state % self.ncol());
+        let (row, col) = match direction {
+            0 => (row, col.saturating_sub(1)),
+            1 => ((row + 1).min(self.nrow() - 1), col),
+            2 => (row, (col + 1).min(self.ncol() - 1)),
+            _ => (row.saturating_sub(1), col),
+        };
+        let next = row * self.ncol() + col;
+        if self.at(next) == b'#' { state } else { next }
+    }
+
+    // The map with the agent's cell replaced by `marker`
+    fn render(&self, state: usize, marker: char) -> String {
+        let mut lines = Vec::new();
+        for (row, cells) in self.cells.iter().enumerate() {
+            let line: String = cells
+                .iter()
+                .enumerate()
+                .map(|(col, &c)| {
+                    if row * self.ncol() + col == state {
+                        marker
+                    } else {
+                        c as char
+                    }
+                })
+                .collect();
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+}
+
+// Rewards for entering a cell of a grid map
+#[derive(Debug, Clone, Copy)]
+struct GridRewards {
+    step: f64,
+    goal: f64,
+    hole: f64,
+}
+
+// Four-directional moves where the agent goes left of, towards and right
+// of the intended direction with probabilities `probs`. 'G' and 'H' cells
+// end the episode.
+fn grid_model(grid: &Grid, probs: [f64; 3], rewards: GridRewards) -> TransitionModel {
+    let n_states = grid.nrow() * grid.ncol();
+    let mut model = TransitionModel::new(n_states, 4, grid.initial())
+        .expect("the grid has one initial probability per cell");
+    for state in 0..n_states {
+        for action in 0..4 {
+            let outcomes = if matches!(grid.at(state), b'G' | b'H') {
+                vec![Transition {
+                    prob: 1.0,
+                    next_state: state,
+                    reward: 0.0,
+                    done: true,
+                }]
+            } else {
+                [(action + 3) % 4, action, (action + 1) % 4]
+                    .into_iter()
+                    .zip(probs)
+                    .filter(|&(_, prob)| prob > 0.0)
+                    .map(|(direction, prob)| {
+                        let next_state = grid.moved(state, direction);
+                        let (reward, done) = match grid.at(next_state) {
+                            b'G' => (rewards.goal, true),
+                            b'H' => (rewards.hole, true),
+                            _ => (rewards.step, false),
+                        };
+                        Transition {
+                            prob,
+                            next_state,
+                            reward,
+                            done,
+                        }
+                    })
+                    .collect()
+            };
+            model.set(state, action, outcomes);
+        }
+    }
+    model
+}
+
+/// Gymnasium's default 4x4 FrozenLake map.
+pub const MAP_4X4: [&str; 4] = ["SFFF", "FHFH", "FFFH", "HFFG"];
+
+/// Gymnasium's default 8x8 FrozenLake map.
+pub const MAP_8X8: [&str; 8] = [
+    "SFFFFFFF", "FFFFFFFF", "FFFHFFFF", "FFFFFHFF", "FFFHFFFF", "FHHFFFHF", "FHFFHFHF", "FFFHFFFG",
+];
+
+/// Cross a frozen lake from S to G without falling into a hole H, as in
+/// Gymnasium's FrozenLake-v1. Actions are left (0), down (1), right (2) and
+/// up (3); on slippery ice the agent moves in the intended or either
+/// perpendicular direction with probability 1/3 each. Reaching G pays 1.
+pub struct FrozenLake {
+    grid: Grid,
+    tabular: Tabular,
+}
+
+impl FrozenLake {
+    /// The 4x4 lake.
+    pub fn new(is_slippery: bool) -> Self {
+        Self::from_map(&MAP_4X4, is_slippery).expect("the 4x4 map is valid")
+    }
+
+    /// A lake from rows of 'S' (start), 'F' (frozen), 'H' (hole) and 'G' (goal).
+    pub fn from_map(map: &[&str], is_slippery: bool) -> EnvResult<Self> {
+        let grid = Grid::parse(map, "SFHG")?;
+        let probs = if is_slippery {
+            [1.0 / 3.0; 3]
+        } else {
+            [0.0, 1.0, 0.0]
+        };
+        let rewards = GridRewards {
+            step: 0.0,
+            goal: 1.0,
+            hole: 0.0,
+        };
+        let model = grid_model(&grid, probs, rewards);
+        Ok(Self {
+            grid,
+            tabular: Tabular::new(model),
+        })
+    }
+
+    /// The map with the agent shown as '@'.
+    pub fn render_text(&self) -> String {
+        self.grid.render(self.tabular.state, '@')
+    }
+}
+
+impl_tabular_env!(FrozenLake, "FrozenLake");
+
+/// Walk along the edge of a 4x12 cliff from the bottom-left corner to the
+/// bottom-right, as in Gymnasium's CliffWalking-v0. Actions are up (0),
+/// right (1), down (2) and left (3). Every step costs -1; stepping off the
+/// cliff costs -100 and returns the agent to the start.
+pub struct CliffWalking {
+    tabular: Tabular,
+}
+
+impl CliffWalking {
+    const NROW: usize = 4;
+    const NCOL: usize = 12;
+    const START: usize = 36;
+    const GOAL: usize = 47;
+
+    pub fn new() -> Self {
+        let n_states = Self::NROW * Self::NCOL;
+        let mut initial = vec![0.0; n_states];
+        initial[Self::START] = 1.0;
+        let mut model =
+            TransitionModel::new(n_states, 4, initial).expect("one probability per cell");
+        for state in 0..n_states {
+            let (row, col) = (state / Self::NCOL, state % Self::NCOL);
+            for action in 0..4 {
+                let (row, col) = match action {
+                    0 => (row.saturating_sub(1), col),
+                    1 => (row, (col + 1).min(Self::NCOL - 1)),
+                    2 => ((row + 1).min(Self::NROW - 1), col),
+                    _ => (row, col.saturating_sub(1)),
+                };
+                let next_state = row * Self::NCOL + col;
+                let transition = if Self::is_cliff(next_state) {
+                    Transition {
+                        prob: 1.0,
+                        next_state: Self::START,
+                        reward: -100.0,
+                        done: false,
+                    }
+                } else {
+                    Transition {
+                        prob: 1.0,
+                        next_state,
+                        reward: -1.0,
+                        done: next_state == Self::GOAL,
+                    }
+                };
+                model.set(state, action, vec![transition]);
+            }
+        }
+        Self {
+            tabular: Tabular::new(model),
+        }
+    }
+
+    fn is_cliff(state: usize) -> bool {
+        state > Self::START && state < Self::GOAL
+    }
+
+    /// The grid with the agent as 'x', the cliff as 'C' and the goal as 'T'.
+    pub fn render_text(&self) -> String {
+        (0..Self::NROW)
+            .map(|row| {
+                (0..Self::NCOL)
+                    .map(|col| match row * Self::NCOL + col {
+                        s if s == self.tabular.state => 'x',
+                        s if s == Self::GOAL => 'T',
+                        s if Self::is_cliff(s) => 'C',
+                        _ => 'o',
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl Default for CliffWalking {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl_tabular_env!(CliffWalking, "CliffWalking");
+
+const TAXI_MAP: [&str; 7] = [
+    "+---------+",
+    "|R: | : :G|",
+    "| : | : : |",
+    "| : : : : |",
+    "| | : | : |",
+    "|Y| : |B: |",
+    "+---------+",
+];
+
+/// Pick up a passenger at one of four stands and drop them off at another,
+/// as in Gymnasium's Taxi-v3. Actions are south (0), north (1), east (2),
+/// west (3), pickup (4) and dropoff (5). Every step costs -1, an illegal
+/// pickup or dropoff -10, and delivering the passenger pays 20.
+pub struct Taxi {
+    tabular: Tabular,
+}
+
+impl Taxi {
+    /// Stand (row, column) of R, G, Y and B.
+    pub const LOCATIONS: [(usize, usize); 4] = [(0, 0), (0, 4), (4, 0), (4, 3)];
+    /// Passenger index meaning "in the taxi".
+    pub const IN_TAXI: usize = 4;
+
+    pub fn new() -> Self {
+        let map: Vec<&[u8]> = TAXI_MAP.iter().map(|row| row.as_bytes()).collect();
+        // The passenger waits at a stand other than the destination
+        let initial = (0..500)
+            .map(|state| {
+                let (_, _, passenger, destination) = Self::decode(state);
+                if passenger < 4 && passenger != destination {
+                    1.0 / 300.0
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let mut model = TransitionModel::new(500, 6, initial).expect("one probability per state");
+        for row in 0..5 {
+            for col in 0..5 {
+                for passenger in 0..5 {
+                    for destination in 0..4 {
+                        let state = Self::encode(row, col, passenger, destination);
+                        for action in 0..6 {
+                            let (mut row, mut col, mut passenger) = (row, col, passenger);
+                            let (mut reward, mut done) = (-1.0, false);
+                            let taxi = (row, col);
+                            match action {
+                                0 => row = (row + 1).min(4),
+                                1 => row = row.saturating_sub(1),
+                                2 if map[1 + row][2 * col + 2] == b':' => col = (col + 1).min(4),
+                                3 if map[1 + row][2 * col] == b':' => col = col.saturating_sub(1),
+                                4 => {
+                                    if passenger < 4 && taxi == Self::LOCATIONS[passenger] {
+                                        passenger = Self::IN_TAXI;
+                                    } else {
+                                        reward = -10.0;
+                                    }
+                                }
+                                5 => {
+                                    let stand = Self::LOCATIONS.iter().position(|&l| l == taxi);
+                                    if taxi == Self::LOCATIONS[destination]
+                                        && passenger == Self::IN_TAXI
+                                    {
+                                        passenger = destination;
+                                        (reward, done) = (20.0, true);
+                                    } else if let Some(stand) = stand
+                                        && passenger == Self::IN_TAXI
+                                    {
+                                        passenger = stand;
+                                    } else {
+                                        reward = -10.0;
+                                    }
+                                }
+                                _ => {}
+                            }
+                            let transition = Transition {
+                                prob: 1.0,
+                                next_state: Self::encode(row, col, passenger, destination),
+                                reward,
+                                done,
+                            };
+                            model.set(state, action, vec![transition]);
+                        }
+                    }
+                }
+            }
+        }
+        Self {
+            tabular: Tabular::new(model),
+        }
+    }
+
+    /// State index of taxi position, passenger (0-3 at a stand, 4 in the
+    /// taxi) and destination stand.
+    pub fn encode(row: usize, col: usize, passenger: usize, destination: usize) -> usize {
+        ((row * 5 + col) * 5 + passenger) * 4 + destination
+    }
+
+    /// Inverse of `encode`: (row, col, passenger, destination).
+    pub fn decode(state: usize) -> (usize, usize, usize, usize) {
+        let (destination, state) = (state % 4, state / 4);
+        let (passenger, state) = (state % 5, state / 5);
+        (state / 5, state % 5, passenger, destination)
+    }
+
+    /// The map with the taxi as 'T' (empty) or 'P' (carrying the
+    /// passenger), then the passenger and destination stands.
+    pub fn render_text(&self) -> String {
+        let (row, col, passenger, destination) = Self::decode(self.tabular.state);
+        let mut lines: Vec<Vec<u8>> = TAXI_MAP.iter().map(|r| r.as_bytes().to_vec()).collect();
+        lines[1 + row][2 * col + 1] = if passenger == Self::IN_TAXI {
+            b'P'
+        } else {
+            b'T'
+        };
+        let stand = |i: usize| ["R", "G", "Y", "B"][i];
+        let passenger = if passenger == Self::IN_TAXI {
+            "taxi"
+        } else {
+            stand(passenger)
+        };
+        let mut text: Vec<String> = lines
+            .into_iter()
+            .map(|l| String::from_utf8(l).unwrap())
+            .collect();
+        text.push(format!(
+            "passenger: {}, destination: {}",
+            passenger,
+            stand(destination)
+        ));
+        text.join("\n")
+    }
+}
+
+impl Default for Taxi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl_tabular_env!(Taxi, "Taxi");
+
+/// A grid world built from an ASCII map of '.' (floor), '#' (wall), 'S'
+/// (start), 'G' (goal) and 'H' (hole, or any other bad terminal cell).
+/// Actions are left (0), down (1), right (2) and up (3); walls and edges
+/// block movement. By default moves are deterministic, reaching G pays 1,
+/// falling into H costs 1, and other steps are free.
+pub struct GridWorld {
+    grid: Grid,
+    probs: [f64; 3],
+    rewards: GridRewards,
+    tabular: Tabular,
+}
+
+impl GridWorld {
+    pub fn from_map(map: &[&str]) -> EnvResult<Self> {
+        let grid = Grid::parse(map, ".#SGH")?;
+        let probs = [0.0, 1.0, 0.0];
+        let rewards = GridRewards {
+            step: 0.0,
+            goal: 1.0,
+            hole: -1.0,
+        };
+        let model = grid_model(&grid, probs, rewards);
+        Ok(Self {
+            grid,
+            probs,
+            rewards,
+            tabular: Tabular::new(model),
+        })
+    }
+
+    /// Slip to either side of the intended direction with total
+    /// probability `slip`, half each way.
+    pub fn with_slip(mut self, slip: f64) -> EnvResult<Self> {
+        if !(0.0..=1.0).contains(&slip) {
+            return Err(HabaError::Config(format!(
+                "slip probability {} is not in [0, 1]",
+                slip
+            )));
+        }
+        self.probs = [slip / 2.0, 1.0 - slip, slip / 2.0];
+        Ok(self.rebuilt())
+    }
+
+    /// Rewards for a plain step, entering G and entering H.
+    pub fn with_rewards(mut self, step: f64, goal: f64, hole: f64) -> Self {
+        self.rewards = GridRewards { step, goal, hole };
+        self.rebuilt()
+    }
+
+    fn rebuilt(mut self) -> Self {
+        self.tabular.model = grid_model(&self.grid, self.probs, self.rewards);
+        self
+    }
+
+    /// The map with the agent shown as '@'.
+    pub fn render_text(&self) -> String {
+        self.grid.render(self.tabular.state, '@')
+    }
+}
+
+impl_tabular_env!(GridWorld, "GridWorld");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dp::{DpConfig, policy_iteration, value_iteration};
+    use crate::env_checker::check_env;
+
+    #[test]
+    fn test_frozen_lake_model_matches_gymnasium() {
+        let env = FrozenLake::new(true);
+        let model = env.model();
+        model.validate().unwrap();
+        assert_eq!(model.initial()[0], 1.0);
+        // Slipping right from state 14 reaches the goal a third of the time,
+        // with outcomes in Gymnasium's order: down, right, up
+        let outcomes = model.transitions(14, 2);
+        let next: Vec<usize> = outcomes.iter().map(|t| t.next_state).collect();
+        assert_eq!(next, vec![14, 15, 10]);
+        assert!(outcomes.iter().all(|t| t.prob == 1.0 / 3.0));
+        assert_eq!((outcomes[1].reward, outcomes[1].done), (1.0, true));
+        // Holes absorb
+        assert_eq!(model.transitions(5, 0)[0].next_state, 5);
+        assert!(model.transitions(5, 0)[0].done);
+
+        let model = FrozenLake::new(false);
+        assert_eq!(model.model().transitions(0, 1).len(), 1);
+        assert!(FrozenLake::from_map(&["SFX"], true).is_err());
+        assert!(FrozenLake::from_map(&["FF", "F"], true).is_err());
+        assert!(FrozenLake::from_map(&["FG"], true).is_err());
+    }
+
+    #[test]
+    fn test_frozen_lake_optimal_values() {
+        let env = FrozenLake::new(true);
+        let config = DpConfig::default();
+        let vi = value_iteration(env.model(), &config).unwrap();
+        let pi = policy_iteration(env.model(), &config).unwrap();
+        // Known optimum for the slippery 4x4 lake with gamma 0.99
+        assert!((vi.values[0] - 0.5420).abs() < 1e-3, "{}", vi.values[0]);
+        for (a, b) in vi.values.iter().zip(&pi.values) {
+            assert!((a - b).abs() < 1e-6);
+        }
+        assert_eq!(vi.policy, pi.policy);
+
+        // Without slipping, the shortest path is 6 steps
+        let env = FrozenLake::new(false);
+        let vi = value_iteration(env.model(), &config).unwrap();
+        assert!((vi.values[0] - 0.99f64.powi(5)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cliff_walking_optimal_path() {
+        let mut env = CliffWalking::new();
+        env.reset().unwrap();
+        let step = env.step(1).unwrap();
+        assert_eq!((step.obs, step.reward, step.done), (36, -100.0, false));
+
+        let config = DpConfig {
+            gamma: 1.0,
+            ..DpConfig::default()
+        };
+        let solution = value_iteration(env.model(), &config).unwrap();
+        // Up, eleven steps right along the edge, down
+        assert_eq!(solution.values[36], -13.0);
+        let (mut obs, mut total) = (env.reset().unwrap(), 0.0);
+        loop {
+            let step = env.step(solution.policy[obs]).unwrap();
+            (obs, total) = (step.obs, total + step.reward);
+            if step.done {
+                break;
+            }
+        }
+        assert_eq!(total, -13.0);
+        assert_eq!(env.render_text().lines().nth(3), Some("oCCCCCCCCCCx"));
+    }
+
+    #[test]
+    fn test_taxi_model_matches_gymnasium() {
+        for state in [0, 123, 499] {
+            let (row, col, passenger, destination) = Taxi::decode(state);
+            assert_eq!(Taxi::encode(row, col, passenger, destination), state);
+        }
+        let env = Taxi::new();
+        let model = env.model();
+        model.validate().unwrap();
+        assert_eq!(model.initial().iter().filter(|&&p| p > 0.0).count(), 300);
+
+        let next = |state, action| model.transitions(state, action)[0];
+        // A wall blocks going east from (0, 1)
+        let state = Taxi::encode(0, 1, 0, 1);
+        assert_eq!(next(state, 2).next_state, state);
+        assert_eq!(next(state, 3).next_state, Taxi::encode(0, 0, 0, 1));
+        // Pickup at R, then deliver at G
+        let state = Taxi::encode(0, 0, 0, 1);
+        assert_eq!(next(state, 4).next_state, Taxi::encode(0, 0, 4, 1));
+        let t = next(Taxi::encode(0, 4, 4, 1), 5);
+        assert_eq!(
+            (t.next_state, t.reward, t.done),
+            (Taxi::encode(0, 4, 1, 1), 20.0, true)
+        );
+        // Dropping off at the wrong stand leaves the passenger there
+        let t = next(Taxi::encode(4, 0, 4, 1), 5);
+        assert_eq!((t.next_state, t.reward), (Taxi::encode(4, 0, 2, 1), -1.0));
+        assert_eq!(next(Taxi::encode(2, 2, 4, 1), 5).reward, -10.0);
+
+        let solution = value_iteration(model, &DpConfig::default()).unwrap();
+        let mut env = Taxi::new();
+        env.seed(3);
+        let mut obs = env.reset().unwrap();
+        for _ in 0..20 {
+            let step = env.step(solution.policy[obs]).unwrap();
+            obs = step.obs;
+            if step.done {
+                assert_eq!(step.reward, 20.0);
+                assert_eq!(env.render_text().lines().count(), 8);
+                return;
+            }
+        }
+        panic!("the optimal policy should deliver within 20 steps");
+    }
+
+    #[test]
+    fn test_grid_world_walls_and_slip() {
+        let map = ["S.#G", "..#.", "H..."];
+        let env = GridWorld::from_map(&map).unwrap();
+        // Walls block, so the goal is seven steps away round the bottom
+        assert_eq!(env.model().transitions(1, 2)[0].next_state, 1);
+        let solution = value_iteration(env.model(), &DpConfig::default()).unwrap();
+        assert!((solution.values[0] - 0.99f64.powi(6)).abs() < 1e-9);
+
+        let env = GridWorld::from_map(&map)
+            .unwrap()
+            .with_slip(0.2)
+            .unwrap()
+            .with_rewards(-0.04, 1.0, -1.0);
+        env.model().validate().unwrap();
+        let outcomes = env.model().transitions(4, 1);
+        assert_eq!(outcomes.len(), 3);
+        assert_eq!(outcomes[0].reward, -0.04);
+        assert!(GridWorld::from_map(&map).unwrap().with_slip(1.5).is_err());
+    }
+
+    #[test]
+    fn test_toy_text_envs_pass_checker() {
+        for report in [
+            check_env(&mut FrozenLake::new(true)),
+            check_env(&mut CliffWalking::new()),
+            check_env(&mut Taxi::new()),
+            check_env(&mut GridWorld::from_map(&["S.", ".G"]).unwrap()),
+        ] {
+            assert!(report.is_ok(), "{}", report);
+        }
+    }
+}
@@ -1,23 +1,55 @@
-use crate::collector::Collector;
-use crate::env::Environment;
+use crate::checkpoint;
+use crate::collector::{CollectSpec, Collector};
+use crate::error::{HabaError, Result};
 use crate::policy::Policy;
+use crate::schedule::Progress;
+use crate::stats::{CollectStats, EpochStats, StatsAggregator, TrainSummary};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 use crate::venv::VectorEnv;
 
+const TRAINER_STATE: &str = "trainer.json";
+// Test envs are seeded apart from the training ones, so they don't replay
+// the same episodes
+const TEST_SEED_OFFSET: u64 = 1 << 32;
+
+#[derive(Serialize, Deserialize)]
+struct TrainerState {
+    epoch: usize,
+    progress: Progress,
+    // So a resumed run evaluates on the same episodes
+    #[serde(default)]
+    test_env: Option<serde_json::Value>,
+}
+
 pub struct Trainer<V: VectorEnv, P: Policy> {
     collector: Collector<V, P>,
     max_epochs: usize,
     step_per_epoch: usize,
     batch_size: usize,
+    progress: Progress,
+    test_env: Option<V>,
+    episode_per_test: usize,
+    test_interval: usize,
+    // Last completed epoch; non-zero after a resume
+    epoch: usize,
+    history: Vec<EpochStats>,
+    checkpoint_dir: Option<PathBuf>,
+    checkpoint_interval: usize,
+    keep_checkpoints: usize,
 }
 
 impl<V, P> Trainer<V, P>
 where
     V: VectorEnv,
     P: Policy<Observation = V::Observation, Action = V::Action>,
-    V::Observation: Clone + Debug,
-    V::Action: Clone + Debug,
+    V::Observation: Clone + Debug + Serialize + DeserializeOwned,
+    V::Action: Clone + Debug + Serialize + DeserializeOwned,
 {
     pub fn new(
         collector: Collector<V, P>,
@@ -30,38 +62,424 @@ where
             max_epochs,
             step_per_epoch,
             batch_size,
+            progress: Progress::default(),
+            test_env: None,
+            episode_per_test: 0,
+            test_interval: 1,
+            epoch: 0,
+            history: Vec::new(),
+            checkpoint_dir: None,
+            checkpoint_interval: 1,
+            keep_checkpoints: 1,
+        }
+    }
+
+    /// Evaluate the greedy policy on `test_env` for `episode_per_test` episodes
+    /// every `test_interval` epochs.
+    pub fn with_test_env(
+        mut self,
+        test_env: V,
+        episode_per_test: usize,
+        test_interval: usize,
+    ) -> Self {
+        self.test_env = Some(test_env);
+        self.episode_per_test = episode_per_test;
+        self.test_interval = test_interval.max(1);
+        self
+    }
+
+    /// Checkpoint the full training state into `dir` every `interval` epochs,
+    /// keeping only the newest `keep` checkpoints.
+    pub fn with_checkpointing(
+        mut self,
+        dir: impl Into<PathBuf>,
+        interval: usize,
+        keep: usize,
+    ) -> Self {
+        self.checkpoint_dir = Some(dir.into());
+        self.checkpoint_interval = interval.max(1);
+        self.keep_checkpoints = keep.max(1);
+        self
+    }
+
+    /// Seed every source of randomness owned by the training loop,
+    /// including the training and test envs.
+    pub fn seed(&mut self, seed: u64) -> Result<()> {
+        if let Some(test_env) = self.test_env.as_mut() {
+            test_env.seed(seed.wrapping_add(TEST_SEED_OFFSET));
+        }
+        self.collector.seed(seed)
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.progress
+    }
+
+    /// Last completed epoch.
+    pub fn epoch(&self) -> usize {
+        self.epoch
+    }
+
+    /// Per-epoch statistics of the epochs run by this trainer.
+    pub fn history(&self) -> &[EpochStats] {
+        &self.history
+    }
+
+    pub fn collector(&self) -> &Collector<V, P> {
+        &self.collector
+    }
+
+    /// Write a checkpoint of everything needed to resume: policy networks,
+    /// optimizer moments, RNG states, replay buffer, training and test env
+    /// state, schedule progress and epoch counter.
+    pub fn save_checkpoint(&self, dir: &Path) -> Result<()> {
+        self.collector.save_state(dir)?;
+        let state = TrainerState {
+            epoch: self.epoch,
+            progress: self.progress,
+            test_env: self
+                .test_env
+                .as_ref()
+                .map(|env| env.state_dict())
+                .transpose()?,
+        };
+        checkpoint::write_json(&dir.join(TRAINER_STATE), &state)
+    }
+
+    /// Continue a run from `dir`, which is either a single checkpoint or a
+    /// checkpoint root written by `with_checkpointing` (the newest one is used).
+    pub fn resume(&mut self, dir: &Path) -> Result<()> {
+        let ckpt = if dir.join(TRAINER_STATE).exists() {
+            dir.to_path_buf()
+        } else {
+            checkpoint::list_epochs(dir)?
+                .pop()
+                .map(|(_, path)| path)
+                .ok_or_else(|| {
+                    HabaError::Checkpoint(format!("no checkpoint found in {}", dir.display()))
+                })?
+        };
+
+        let state: TrainerState = checkpoint::read_json(&ckpt.join(TRAINER_STATE))?;
+        self.collector.load_state(&ckpt)?;
+        if let (Some(test_env), Some(test_state)) = (self.test_env.as_mut(), &state.test_env) {
+            test_env.load_state_dict(test_state)?;
+        }
+        self.epoch = state.epoch;
+        self.progress = state.progress;
+        self.sync_progress();
+        Ok(())
+    }
+
+    // Written to a temporary directory first so a crash never leaves a half-written latest checkpoint
+    fn checkpoint_epoch(&self) -> Result<()> {
+        let Some(root) = &self.checkpoint_dir else {
+            return Ok(());
+        };
+        let final_dir = checkpoint::epoch_dir(root, self.epoch);
+        let tmp_dir = final_dir.with_extension("tmp");
+        if tmp_dir.exists() {
+            fs::remove_dir_all(&tmp_dir)?;
+        }
+        self.save_checkpoint(&tmp_dir)?;
+        if final_dir.exists() {
+            fs::remove_dir_all(&final_dir)?;
         }
+        fs::rename(&tmp_dir, &final_dir)?;
+        checkpoint::prune(root, self.keep_checkpoints)
     }
 
-    pub fn train(&mut self) -> Result<(), String> {
-        println!("Collecting initial data...");
-        self.collector.collect(10);
+    // Push the current step counters to the policy so its schedules advance
+    fn sync_progress(&mut self) {
+        self.progress.env_step = self.collector.env_steps();
+        self.collector.policy_mut().set_progress(self.progress);
+    }
+
+    // Greedy evaluation on the separate test env, reported apart from training returns
+    fn test(&mut self, epoch: usize) -> Result<Option<CollectStats>> {
+        let Some(test_env) = self.test_env.as_mut() else {
+            return Ok(None);
+        };
+        let stats = self.collector.evaluate(test_env, self.episode_per_test)?;
+        if stats.n_episodes == 0 {
+            return Ok(None);
+        }
+        println!(
+            "Epoch {}: Test Reward: {:.2} ± {:.2} ({} episodes)",
+            epoch, stats.returns_mean, stats.returns_std, stats.n_episodes
+        );
+        Ok(Some(stats))
+    }
+
+    pub fn train(&mut self) -> Result<()> {
+        if self.epoch == 0 {
+            println!("Collecting initial data...");
+            self.collector.collect(CollectSpec::Steps(10))?;
+            self.sync_progress();
+        }
 
         println!("Starting Training...");
-        for epoch in 1..=self.max_epochs {
-            let mut total_reward = 0.0;
+        for epoch in self.epoch + 1..=self.max_epochs {
             // Collect experience
-            let episodes = self.collector.collect(self.step_per_epoch);
-            for r in &episodes {
-                total_reward += r;
-            }
-            let num_episodes = episodes.len();
-            let avg_reward = if num_episodes > 0 {
-                total_reward / num_episodes as f64
-            } else {
-                0.0
-            };
+            let collect_stats = self
+                .collector
+                .collect(CollectSpec::Steps(self.step_per_epoch))?;
+            self.sync_progress();
 
             // Train
+            let mut learn_stats = StatsAggregator::new();
             for _ in 0..self.step_per_epoch {
-                self.collector.train_step(self.batch_size);
+                if let Some(stats) = self.collector.train_step(self.batch_size)? {
+                    learn_stats.add(&stats);
+                    self.progress.grad_step += 1;
+                    self.sync_progress();
+                }
             }
+            let learn_stats = learn_stats.mean();
 
             println!(
-                "Epoch {}: Avg Reward: {:.2} ({} episodes)",
-                epoch, avg_reward, num_episodes
+                "Epoch {}: Avg Reward: {:.2} ({} episodes, {:.0} steps/s) | {}",
+                epoch,
+                collect_stats.returns_mean,
+                collect_stats.n_episodes,
+                collect_stats.steps_per_sec,
+                learn_stats
             );
+
+            let test_stats = if epoch % self.test_interval == 0 {
+                self.test(epoch)?
+            } else {
+                None
+            };
+
+            self.epoch = epoch;
+            self.history.push(EpochStats {
+                epoch,
+                collect: collect_stats,
+                learn: learn_stats,
+                test: test_stats,
+            });
+
+            if epoch % self.checkpoint_interval == 0 {
+                self.checkpoint_epoch()?;
+            }
         }
         Ok(())
     }
 }
+
+/// Settings of `OffPolicyTrainer`.
+#[derive(Debug, Clone)]
+pub struct OffPolicyConfig {
+    pub max_epochs: usize,
+    /// Env steps collected per epoch.
+    pub step_per_epoch: usize,
+    /// Env steps collected between rounds of updates.
+    pub step_per_collect: usize,
+    /// Gradient steps per collected env step. Fractions are carried over,
+    /// so 0.25 runs one update every 4 steps.
+    pub update_per_step: f64,
+    pub batch_size: usize,
+    /// Env steps collected with the exploring policy before any update.
+    pub start_timesteps: usize,
+    /// Test episodes after each epoch, if a test env is set.
+    pub episode_per_test: usize,
+}
+
+impl Default for OffPolicyConfig {
+    fn default() -> Self {
+        Self {
+            max_epochs: 10,
+            step_per_epoch: 10_000,
+            step_per_collect: 10,
+            update_per_step: 1.0,
+            batch_size: 64,
+            start_timesteps: 0,
+            episode_per_test: 10,
+        }
+    }
+}
+
+/// Training loop for off-policy algorithms such as DQN: alternate short
+/// collections with a number of updates proportional to the data
+/// collected, test after every epoch, and stop early once `stop_fn`
+/// accepts the epoch's mean reward.
+pub struct OffPolicyTrainer<V: VectorEnv, P: Policy> {
+    collector: Collector<V, P>,
+    config: OffPolicyConfig,
+    test_env: Option<V>,
+    stop_fn: Option<Box<dyn FnMut(f64) -> bool>>,
+    progress: Progress,
+    // Fractional updates owed to past collections
+    update_debt: f64,
+}
+
+impl<V, P> OffPolicyTrainer<V, P>
+where
+    V: VectorEnv,
+    P: Policy<Observation = V::Observation, Action = V::Action>,
+    V::Observation: Clone + Debug,
+    V::Action: Clone + Debug,
+{
+    pub fn new(collector: Collector<V, P>, config: OffPolicyConfig) -> Result<Self> {
+        if config.step_per_collect == 0 || config.batch_size == 0 {
+            return Err(HabaError::Config(
+                "step_per_collect and batch_size must be positive".to_string(),
+            ));
+        }
+        if config.update_per_step < 0.0 || !config.update_per_step.is_finite() {
+            return Err(HabaError::Config(format!(
+                "invalid update_per_step {}",
+                config.update_per_step
+            )));
+        }
+        Ok(Self {
+            collector,
+            config,
+            test_env: None,
+            stop_fn: None,
+            progress: Progress::default(),
+            update_debt: 0.0,
+        })
+    }
+
+    /// Evaluate the greedy policy on `test_env` after every epoch. The stop
+    /// function then sees the mean test return instead of the training one.
+    pub fn with_test_env(mut self, test_env: V) -> Self {
+        self.test_env = Some(test_env);
+        self
+    }
+
+    /// Stop after the first epoch whose mean reward `stop_fn` accepts.
+    pub fn with_stop_fn(mut self, stop_fn: impl FnMut(f64) -> bool + 'static) -> Self {
+        self.stop_fn = Some(Box::new(stop_fn));
+        self
+    }
+
+    /// Stop once the mean reward reaches `threshold`, e.g. an `EnvSpec`'s
+    /// `reward_threshold`.
+    pub fn with_reward_threshold(self, threshold: f64) -> Self {
+        self.with_stop_fn(move |reward| reward >= threshold)
+    }
+
+    /// Seed every source of randomness owned by the training loop,
+    /// including the training and test envs.
+    pub fn seed(&mut self, seed: u64) -> Result<()> {
+        if let Some(test_env) = self.test_env.as_mut() {
+            test_env.seed(seed.wrapping_add(TEST_SEED_OFFSET));
+        }
+        self.collector.seed(seed)
+    }
+
+    pub fn collector(&self) -> &Collector<V, P> {
+        &self.collector
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.progress
+    }
+
+    fn sync_progress(&mut self) {
+        self.progress.env_step = self.collector.env_steps();
+        self.collector.policy_mut().set_progress(self.progress);
+    }
+
+    // Collect one round and run the updates it pays for
+    fn collect_and_update(&mut self, learn_stats: &mut StatsAggregator) -> Result<CollectStats> {
+        let stats = self
+            .collector
+            .collect(CollectSpec::Steps(self.config.step_per_collect))?;
+        self.sync_progress();
+        self.update_debt += stats.n_steps as f64 * self.config.update_per_step;
+        while self.update_debt >= 1.0 {
+            self.update_debt -= 1.0;
+            if let Some(stats) = self.collector.train_step(self.config.batch_size)? {
+                learn_stats.add(&stats);
+                self.progress.grad_step += 1;
+                self.sync_progress();
+            }
+        }
+        Ok(stats)
+    }
+
+    pub fn train(&mut self) -> Result<TrainSummary> {
+        let start = Instant::now();
+        let warmup = self
+            .config
+            .start_timesteps
+            .saturating_sub(self.collector.env_steps());
+        if warmup > 0 {
+            self.collector.collect(CollectSpec::Steps(warmup))?;
+            self.sync_progress();
+        }
+
+        let mut history = Vec::new();
+        let mut best: Option<(f64, usize)> = None;
+        let mut stopped = false;
+        for epoch in 1..=self.config.max_epochs {
+            let epoch_start = Instant::now();
+            let first_step = self.collector.env_steps();
+            let (mut returns, mut lens, mut env_ids) = (Vec::new(), Vec::new(), Vec::new());
+            let mut learn_stats = StatsAggregator::new();
+            while self.collector.env_steps() - first_step < self.config.step_per_epoch {
+                let stats = self.collect_and_update(&mut learn_stats)?;
+                returns.extend(stats.returns);
+                lens.extend(stats.lens);
+                env_ids.extend(stats.env_ids);
+            }
+            let n_steps = self.collector.env_steps() - first_step;
+            let collect = CollectStats::new(returns, lens, env_ids, n_steps, epoch_start.elapsed());
+            let learn = learn_stats.mean();
+            println!(
+                "Epoch {}: Avg Reward: {:.2} ({} episodes, {:.0} steps/s) | {}",
+                epoch, collect.returns_mean, collect.n_episodes, collect.steps_per_sec, learn
+            );
+
+            let test = match self.test_env.as_mut() {
+                Some(env) => Some(self.collector.evaluate(env, self.config.episode_per_test)?),
+                None => None,
+            };
+            // Judge the epoch by its test episodes if there are any
+            let reward = match &test {
+                Some(test) if test.n_episodes > 0 => Some(test.returns_mean),
+                _ if collect.n_episodes > 0 => Some(collect.returns_mean),
+                _ => None,
+            };
+            if let Some(test) = test.as_ref().filter(|t| t.n_episodes > 0) {
+                println!(
+                    "Epoch {}: Test Reward: {:.2} ± {:.2} ({} episodes)",
+                    epoch, test.returns_mean, test.returns_std, test.n_episodes
+                );
+            }
+            history.push(EpochStats {
+                epoch,
+                collect,
+                learn,
+                test,
+            });
+
+            if let Some(reward) = reward {
+                if best.is_none_or(|(best, _)| reward > best) {
+                    best = Some((reward, epoch));
+                }
+                if let Some(stop_fn) = self.stop_fn.as_mut()
+                    && stop_fn(reward)
+                {
+                    stopped = true;
+                    break;
+                }
+            }
+        }
+
+        Ok(TrainSummary {
+            epochs: history.len(),
+            env_steps: self.collector.env_steps(),
+            grad_steps: self.progress.grad_step,
+            best_reward: best.map(|(reward, _)| reward),
+            best_epoch: best.map(|(_, epoch)| epoch),
+            stopped,
+            duration: start.elapsed(),
+            history,
+        })
+    }
+}
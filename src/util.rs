@@ -0,0 +1,18 @@
+use std::borrow::Borrow;
+
+/// Index of the first largest value, or 0 if there are none. Taking the
+/// first makes ties resolve like numpy's argmax.
+pub(crate) fn argmax<I>(values: I) -> usize
+where
+    I: IntoIterator,
+    I::Item: Borrow<f64>,
+{
+    let mut best = (0, f64::NEG_INFINITY);
+    for (i, v) in values.into_iter().enumerate() {
+        let v = *v.borrow();
+        if v > best.1 {
+            best = (i, v);
+        }
+    }
+    best.0
+}
@@ -1,17 +1,72 @@
-use crate::env::{EnvResult, Environment, Step};
-use std::error::Error;
+use crate::checkpoint;
+use crate::env::{Environment, Step};
+use crate::error::{HabaError, Result};
+use serde_json::Value;
 use std::fmt::Debug;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
 
 pub trait VectorEnv {
     type Observation: Clone + Debug;
     type Action: Clone + Debug;
 
-    fn step(
-        &mut self,
-        actions: &[Self::Action],
-    ) -> Result<Vec<Step<Self::Observation>>, Box<dyn Error>>;
-    fn reset(&mut self) -> Result<Vec<Self::Observation>, Box<dyn Error>>;
+    fn step(&mut self, actions: &[Self::Action]) -> Result<Vec<Step<Self::Observation>>>;
+    fn reset(&mut self) -> Result<Vec<Self::Observation>>;
     fn len(&self) -> usize;
+
+    /// Seed every sub-env: sub-env `i` gets `seed + i`.
+    fn seed(&mut self, seed: u64);
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn state_dict(&self) -> Result<Value> {
+        Err(checkpoint::unsupported("this vector env"))
+    }
+
+    fn load_state_dict(&mut self, _state: &Value) -> Result<()> {
+        Err(checkpoint::unsupported("this vector env"))
+    }
+}
+
+impl<V: VectorEnv + ?Sized> VectorEnv for Box<V> {
+    type Observation = V::Observation;
+    type Action = V::Action;
+
+    fn step(&mut self, actions: &[Self::Action]) -> Result<Vec<Step<Self::Observation>>> {
+        (**self).step(actions)
+    }
+
+    fn reset(&mut self) -> Result<Vec<Self::Observation>> {
+        (**self).reset()
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        (**self).seed(seed)
+    }
+
+    fn state_dict(&self) -> Result<Value> {
+        (**self).state_dict()
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> Result<()> {
+        (**self).load_state_dict(state)
+    }
+}
+
+// Step one sub-env. When the episode ends the env is reset and `obs` is the
+// first observation of the next episode; the final observation is dropped.
+fn step_autoreset<E: Environment>(env: &mut E, action: E::Action) -> Result<Step<E::Observation>> {
+    let mut step = env.step(action)?;
+    if step.done {
+        step.obs = env.reset()?;
+    }
+    Ok(step)
 }
 
 pub struct DummyVectorEnv<E: Environment> {
@@ -22,6 +77,10 @@ impl<E: Environment> DummyVectorEnv<E> {
     pub fn new(envs: Vec<E>) -> Self {
         Self { envs }
     }
+
+    pub fn envs(&self) -> &[E] {
+        &self.envs
+    }
 }
 
 impl<E: Environment> VectorEnv for DummyVectorEnv<E>
@@ -32,44 +91,23 @@ where
     type Observation = E::Observation;
     type Action = E::Action;
 
-    fn step(
-        &mut self,
-        actions: &[Self::Action],
-    ) -> Result<Vec<Step<Self::Observation>>, Box<dyn Error>> {
+    fn step(&mut self, actions: &[Self::Action]) -> Result<Vec<Step<Self::Observation>>> {
         if actions.len() != self.envs.len() {
-            return Err("Action count must match env count".into());
-        }
-
-        let mut next_steps = Vec::with_capacity(self.envs.len());
-
-        for (i, env) in self.envs.iter_mut().enumerate() {
-            let action = actions[i].clone();
-            let mut step = env.step(action)?;
-
-            if step.done {
-                // Auto-reset
-                let obs = env.reset()?;
-                // We typically want to return the 'last' observation in info or somewhere,
-                // but for now, let's just update the next_obs to be the reset one
-                // so the agent can continue acting, but mark it as done.
-                // Tianshou actually returns the 'next_obs' as the reset observation if done is true,
-                // and puts the terminal observation in 'info'.
-                // Our Step struct has `obs` (next_obs).
-                // Let's replace `step.obs` with the reset observation for continuity,
-                // but keep `step.done = true`.
-                // The true terminal observation is lost if we overwrite it,
-                // but `step.obs` WAS the terminal observation before this override.
-                // Ideally we check `info`.
-                // For simplicity: simple auto-reset logic.
-                step.obs = obs;
-            }
-            next_steps.push(step);
+            return Err(HabaError::Shape(format!(
+                "got {} actions for {} envs",
+                actions.len(),
+                self.envs.len()
+            )));
         }
 
-        Ok(next_steps)
+        self.envs
+            .iter_mut()
+            .zip(actions)
+            .map(|(env, action)| step_autoreset(env, action.clone()))
+            .collect()
     }
 
-    fn reset(&mut self) -> Result<Vec<Self::Observation>, Box<dyn Error>> {
+    fn reset(&mut self) -> Result<Vec<Self::Observation>> {
         let mut obs_vec = Vec::with_capacity(self.envs.len());
         for env in self.envs.iter_mut() {
             obs_vec.push(env.reset()?);
@@ -80,4 +118,257 @@ where
     fn len(&self) -> usize {
         self.envs.len()
     }
+
+    fn seed(&mut self, seed: u64) {
+        for (i, env) in self.envs.iter_mut().enumerate() {
+            env.seed(seed.wrapping_add(i as u64));
+        }
+    }
+
+    fn state_dict(&self) -> Result<Value> {
+        let states = self
+            .envs
+            .iter()
+            .map(|env| env.state_dict())
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Value::Array(states))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> Result<()> {
+        let states = match state.as_array() {
+            Some(states) if states.len() == self.envs.len() => states,
+            _ => {
+                return Err(HabaError::Checkpoint(format!(
+                    "expected state for {} envs",
+                    self.envs.len()
+                )));
+            }
+        };
+        for (env, s) in self.envs.iter_mut().zip(states) {
+            env.load_state_dict(s)?;
+        }
+        Ok(())
+    }
+}
+
+enum Command<A> {
+    Step(A),
+    Reset,
+    Seed(u64),
+    StateDict,
+    LoadStateDict(Value),
+}
+
+enum Reply<O> {
+    Step(Result<Step<O>>),
+    Reset(Result<O>),
+    Seed,
+    StateDict(Result<Value>),
+    LoadStateDict(Result<()>),
+}
+
+struct Worker<O, A> {
+    commands: Sender<Command<A>>,
+    replies: Receiver<Reply<O>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl<O, A> Worker<O, A> {
+    fn send(&self, command: Command<A>) -> Result<()> {
+        self.commands.send(command).map_err(|_| Self::exited())
+    }
+
+    fn recv(&self) -> Result<Reply<O>> {
+        self.replies.recv().map_err(|_| Self::exited())
+    }
+
+    fn exited() -> HabaError {
+        HabaError::Env("vector env worker thread exited".to_string())
+    }
+}
+
+/// Runs each sub-env on its own thread, so that slow environments step in
+/// parallel. Sub-envs auto-reset like in `DummyVectorEnv`.
+pub struct ThreadVectorEnv<O, A> {
+    workers: Vec<Worker<O, A>>,
+}
+
+impl<O: Send + 'static, A: Send + 'static> ThreadVectorEnv<O, A> {
+    pub fn new<E>(envs: Vec<E>) -> Self
+    where
+        E: Environment<Observation = O, Action = A> + Send + 'static,
+    {
+        let workers = envs
+            .into_iter()
+            .map(|mut env| {
+                let (commands, command_rx) = mpsc::channel();
+                let (reply_tx, replies) = mpsc::channel();
+                let handle = thread::spawn(move || {
+                    // Ends once the vector env drops its sender
+                    for command in command_rx {
+                        let reply = match command {
+                            Command::Step(action) => Reply::Step(step_autoreset(&mut env, action)),
+                            Command::Reset => Reply::Reset(env.reset()),
+                            Command::Seed(seed) => {
+                                env.seed(seed);
+                                Reply::Seed
+                            }
+                            Command::StateDict => Reply::StateDict(env.state_dict()),
+                            Command::LoadStateDict(state) => {
+                                Reply::LoadStateDict(env.load_state_dict(&state))
+                            }
+                        };
+                        if reply_tx.send(reply).is_err() {
+                            break;
+                        }
+                    }
+                });
+                Worker {
+                    commands,
+                    replies,
+                    handle: Some(handle),
+                }
+            })
+            .collect();
+        Self { workers }
+    }
+
+    // Send one command to every worker, then collect the replies in order
+    fn broadcast<T>(
+        &self,
+        mut commands: impl FnMut(usize) -> Command<A>,
+        mut unpack: impl FnMut(Reply<O>) -> Option<Result<T>>,
+    ) -> Result<Vec<T>> {
+        for (i, worker) in self.workers.iter().enumerate() {
+            worker.send(commands(i))?;
+        }
+        // Drain every reply before failing, so the channels stay in sync
+        let replies: Vec<Result<Reply<O>>> = self.workers.iter().map(Worker::recv).collect();
+        replies
+            .into_iter()
+            .map(|reply| unpack(reply?).unwrap_or_else(|| Err(Worker::<O, A>::exited())))
+            .collect()
+    }
+}
+
+impl<O, A> VectorEnv for ThreadVectorEnv<O, A>
+where
+    O: Clone + Debug + Send + 'static,
+    A: Clone + Debug + Send + 'static,
+{
+    type Observation = O;
+    type Action = A;
+
+    fn step(&mut self, actions: &[Self::Action]) -> Result<Vec<Step<Self::Observation>>> {
+        if actions.len() != self.workers.len() {
+            return Err(HabaError::Shape(format!(
+                "got {} actions for {} envs",
+                actions.len(),
+                self.workers.len()
+            )));
+        }
+        self.broadcast(
+            |i| Command::Step(actions[i].clone()),
+            |reply| match reply {
+                Reply::Step(step) => Some(step),
+                _ => None,
+            },
+        )
+    }
+
+    fn reset(&mut self) -> Result<Vec<Self::Observation>> {
+        self.broadcast(
+            |_| Command::Reset,
+            |reply| match reply {
+                Reply::Reset(obs) => Some(obs),
+                _ => None,
+            },
+        )
+    }
+
+    fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        // Only fails if a worker died, which the next step or reset reports
+        let _ = self.broadcast(
+            |i| Command::Seed(seed.wrapping_add(i as u64)),
+            |reply| match reply {
+                Reply::Seed => Some(Ok(())),
+                _ => None,
+            },
+        );
+    }
+
+    fn state_dict(&self) -> Result<Value> {
+        let states = self.broadcast(
+            |_| Command::StateDict,
+            |reply| match reply {
+                Reply::StateDict(state) => Some(state),
+                _ => None,
+            },
+        )?;
+        Ok(Value::Array(states))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> Result<()> {
+        let states = match state.as_array() {
+            Some(states) if states.len() == self.workers.len() => states,
+            _ => {
+                return Err(HabaError::Checkpoint(format!(
+                    "expected state for {} envs",
+                    self.workers.len()
+                )));
+            }
+        };
+        self.broadcast(
+            |i| Command::LoadStateDict(states[i].clone()),
+            |reply| match reply {
+                Reply::LoadStateDict(result) => Some(result),
+                _ => None,
+            },
+        )?;
+        Ok(())
+    }
+}
+
+impl<O, A> Drop for ThreadVectorEnv<O, A> {
+    fn drop(&mut self) {
+        for mut worker in self.workers.drain(..) {
+            drop(worker.commands);
+            if let Some(handle) = worker.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockEnv;
+
+    #[test]
+    fn test_thread_vector_env_matches_dummy() {
+        let make = || vec![MockEnv::new(2), MockEnv::new(3), MockEnv::new(5)];
+        let mut dummy = DummyVectorEnv::new(make());
+        let mut threaded = ThreadVectorEnv::new(make());
+        assert_eq!(threaded.reset().unwrap(), dummy.reset().unwrap());
+        for _ in 0..7 {
+            let expected = dummy.step(&[(), (), ()]).unwrap();
+            let steps = threaded.step(&[(), (), ()]).unwrap();
+            let obs: Vec<f64> = steps.iter().map(|s| s.obs).collect();
+            let done: Vec<bool> = steps.iter().map(|s| s.done).collect();
+            assert_eq!(obs, expected.iter().map(|s| s.obs).collect::<Vec<_>>());
+            assert_eq!(done, expected.iter().map(|s| s.done).collect::<Vec<_>>());
+        }
+        assert!(threaded.step(&[()]).is_err());
+
+        let state = threaded.state_dict().unwrap();
+        assert_eq!(state, dummy.state_dict().unwrap());
+        let mut restored = ThreadVectorEnv::new(make());
+        restored.load_state_dict(&state).unwrap();
+        assert_eq!(restored.state_dict().unwrap(), state);
+    }
 }
@@ -0,0 +1,1100 @@
+use crate::checkpoint;
+use crate::env::{EnvResult, Environment, InfoValue, Parameterized, Step};
+use crate::error::HabaError;
+use crate::spaces::{Space, standard_normal};
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::{HashMap, VecDeque};
+use std::marker::PhantomData;
+
+/// `info` key set by `TimeLimit` when it ends an episode the environment
+/// itself would have continued. It is informational only: vector envs reset
+/// on every `done`, and the collector and replay buffer treat it as terminal.
+pub const TRUNCATED: &str = "TimeLimit.truncated";
+/// `info` keys set by `RecordEpisodeStatistics` at the end of an episode.
+pub const EPISODE_RETURN: &str = "episode.return";
+pub const EPISODE_LENGTH: &str = "episode.length";
+/// Prefix of the `info` keys in which `DomainRandomization` reports the
+/// current value of each randomized parameter, e.g. `param.gravity`.
+pub const PARAM_PREFIX: &str = "param.";
+
+/// An environment that modifies another. Wrappers are themselves
+/// environments, so they compose by nesting.
+pub trait Wrapper {
+    type Inner;
+
+    fn inner(&self) -> &Self::Inner;
+    fn inner_mut(&mut self) -> &mut Self::Inner;
+}
+
+macro_rules! impl_wrapper {
+    ($name:ident<E $(, $param:ident)*>) => {
+        impl<E $(, $param)*> Wrapper for $name<E $(, $param)*> {
+            type Inner = E;
+
+            fn inner(&self) -> &E {
+                &self.env
+            }
+
+            fn inner_mut(&mut self) -> &mut E {
+                &mut self.env
+            }
+        }
+    };
+}
+
+fn set_info<O>(step: &mut Step<O>, key: &str, value: impl Into<InfoValue>) {
+    step.info
+        .get_or_insert_with(HashMap::new)
+        .insert(key.to_string(), value.into());
+}
+
+// Seed a wrapper's own RNG and the env it wraps. The env gets a seed drawn
+// from the RNG rather than `seed` itself, so the two don't share a stream.
+fn seed_with_env<E: Environment>(rng: &mut ChaCha8Rng, env: &mut E, seed: u64) {
+    *rng = ChaCha8Rng::seed_from_u64(seed);
+    env.seed(rng.next_u64());
+}
+
+/// Ends episodes after `max_steps` steps, marking them `TRUNCATED`.
+pub struct TimeLimit<E> {
+    env: E,
+    max_steps: usize,
+    elapsed: usize,
+}
+
+impl<E> TimeLimit<E> {
+    pub fn new(env: E, max_steps: usize) -> Self {
+        Self {
+            env,
+            max_steps,
+            elapsed: 0,
+        }
+    }
+}
+
+impl_wrapper!(TimeLimit<E>);
+
+impl<E: Environment> Environment for TimeLimit<E> {
+    type Observation = E::Observation;
+    type Action = E::Action;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        self.elapsed = 0;
+        self.env.reset()
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        let mut step = self.env.step(action)?;
+        self.elapsed += 1;
+        if self.elapsed >= self.max_steps && !step.done {
+            step.done = true;
+            set_info(&mut step, TRUNCATED, true);
+        }
+        Ok(step)
+    }
+
+    fn observation_space(&self) -> Space {
+        self.env.observation_space()
+    }
+
+    fn action_space(&self) -> Space {
+        self.env.action_space()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        Ok(json!({"env": self.env.state_dict()?, "elapsed": self.elapsed}))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.elapsed = checkpoint::field(state, "elapsed")?;
+        self.env
+            .load_state_dict(&checkpoint::field::<Value>(state, "env")?)
+    }
+}
+
+/// Repeats each action `repeat` times (frame skipping), summing rewards.
+/// Stops early when the episode ends; `info` is that of the last step.
+pub struct ActionRepeat<E> {
+    env: E,
+    repeat: usize,
+}
+
+impl<E> ActionRepeat<E> {
+    pub fn new(env: E, repeat: usize) -> Self {
+        Self {
+            env,
+            repeat: repeat.max(1),
+        }
+    }
+}
+
+impl_wrapper!(ActionRepeat<E>);
+
+impl<E: Environment> Environment for ActionRepeat<E>
+where
+    E::Action: Clone,
+{
+    type Observation = E::Observation;
+    type Action = E::Action;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        self.env.reset()
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        let mut step = self.env.step(action.clone())?;
+        let mut reward = step.reward;
+        for _ in 1..self.repeat {
+            if step.done {
+                break;
+            }
+            step = self.env.step(action.clone())?;
+            reward += step.reward;
+        }
+        step.reward = reward;
+        Ok(step)
+    }
+
+    fn observation_space(&self) -> Space {
+        self.env.observation_space()
+    }
+
+    fn action_space(&self) -> Space {
+        self.env.action_space()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        self.env.state_dict()
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.env.load_state_dict(state)
+    }
+}
+
+/// Concatenates the last `n_frames` observations, oldest first. For
+/// multi-dimensional observations frames are stacked along the first axis,
+/// so `(c, h, w)` becomes `(n_frames * c, h, w)`.
+pub struct FrameStack<E> {
+    env: E,
+    n_frames: usize,
+    frames: VecDeque<Vec<f64>>,
+}
+
+impl<E> FrameStack<E> {
+    pub fn new(env: E, n_frames: usize) -> Self {
+        Self {
+            env,
+            n_frames: n_frames.max(1),
+            frames: VecDeque::new(),
+        }
+    }
+
+    fn stacked(&self) -> Vec<f64> {
+        self.frames.iter().flatten().copied().collect()
+    }
+}
+
+impl_wrapper!(FrameStack<E>);
+
+impl<E: Environment<Observation = Vec<f64>>> Environment for FrameStack<E> {
+    type Observation = Vec<f64>;
+    type Action = E::Action;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        let obs = self.env.reset()?;
+        self.frames = std::iter::repeat_n(obs, self.n_frames).collect();
+        Ok(self.stacked())
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        if self.frames.is_empty() {
+            return Err(HabaError::Env(
+                "FrameStack stepped before reset".to_string(),
+            ));
+        }
+        let mut step = self.env.step(action)?;
+        self.frames.pop_front();
+        self.frames.push_back(std::mem::take(&mut step.obs));
+        step.obs = self.stacked();
+        Ok(step)
+    }
+
+    fn observation_space(&self) -> Space {
+        match self.env.observation_space() {
+            Space::Box { low, high, shape } => {
+                let mut shape = shape;
+                match shape.first_mut() {
+                    Some(first) => *first *= self.n_frames,
+                    None => shape.push(self.n_frames),
+                }
+                Space::Box {
+                    low: low.repeat(self.n_frames),
+                    high: high.repeat(self.n_frames),
+                    shape,
+                }
+            }
+            space => space,
+        }
+    }
+
+    fn action_space(&self) -> Space {
+        self.env.action_space()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        Ok(json!({"env": self.env.state_dict()?, "frames": self.frames}))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.frames = checkpoint::field(state, "frames")?;
+        self.env
+            .load_state_dict(&checkpoint::field::<Value>(state, "env")?)
+    }
+}
+
+/// Clips continuous actions into the bounds of the wrapped action space,
+/// which then accepts any real action.
+pub struct ClipAction<E> {
+    env: E,
+}
+
+impl<E> ClipAction<E> {
+    pub fn new(env: E) -> Self {
+        Self { env }
+    }
+}
+
+impl_wrapper!(ClipAction<E>);
+
+impl<E: Environment<Action = Vec<f64>>> Environment for ClipAction<E> {
+    type Observation = E::Observation;
+    type Action = Vec<f64>;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        self.env.reset()
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        let action = match self.env.action_space() {
+            Space::Box { low, high, .. } => {
+                check_action_len("ClipAction", &action, low.len())?;
+                action
+                    .iter()
+                    .zip(low.iter().zip(&high))
+                    .map(|(a, (lo, hi))| a.clamp(*lo, *hi))
+                    .collect()
+            }
+            Space::Discrete(_) => action,
+        };
+        self.env.step(action)
+    }
+
+    fn observation_space(&self) -> Space {
+        self.env.observation_space()
+    }
+
+    fn action_space(&self) -> Space {
+        Space::uniform(self.env.action_space().shape(), f64::MIN, f64::MAX)
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        self.env.state_dict()
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.env.load_state_dict(state)
+    }
+}
+
+// Errors unless a continuous action has one entry per dimension
+fn check_action_len(wrapper: &str, action: &[f64], expected: usize) -> EnvResult<()> {
+    if action.len() != expected {
+        return Err(HabaError::Env(format!(
+            "{} expected an action of length {}, got {}",
+            wrapper,
+            expected,
+            action.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Maps actions from `[low, high]` linearly onto the bounds of the wrapped
+/// continuous action space, e.g. so a tanh policy can drive any env.
+pub struct RescaleAction<E> {
+    env: E,
+    low: f64,
+    high: f64,
+    // Bounds of the wrapped action space
+    env_low: Vec<f64>,
+    env_high: Vec<f64>,
+}
+
+impl<E: Environment> RescaleAction<E> {
+    pub fn new(env: E, low: f64, high: f64) -> EnvResult<Self> {
+        match env.action_space() {
+            Space::Box {
+                low: env_low,
+                high: env_high,
+                ..
+            } if low < high => Ok(Self {
+                env,
+                low,
+                high,
+                env_low,
+                env_high,
+            }),
+            space => Err(HabaError::Config(format!(
+                "cannot rescale {:?} to [{}, {}]",
+                space, low, high
+            ))),
+        }
+    }
+}
+
+impl_wrapper!(RescaleAction<E>);
+
+impl<E: Environment<Action = Vec<f64>>> Environment for RescaleAction<E> {
+    type Observation = E::Observation;
+    type Action = Vec<f64>;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        self.env.reset()
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        check_action_len("RescaleAction", &action, self.env_low.len())?;
+        let action = action
+            .iter()
+            .zip(self.env_low.iter().zip(&self.env_high))
+            .map(|(a, (lo, hi))| lo + (a - self.low) / (self.high - self.low) * (hi - lo))
+            .collect();
+        self.env.step(action)
+    }
+
+    fn observation_space(&self) -> Space {
+        self.env.observation_space()
+    }
+
+    fn action_space(&self) -> Space {
+        Space::uniform(self.env.action_space().shape(), self.low, self.high)
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        self.env.state_dict()
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.env.load_state_dict(state)
+    }
+}
+
+/// Applies `f` to every observation. The observation space is assumed
+/// unchanged unless set with `with_space`.
+pub struct TransformObservation<E, F, O> {
+    env: E,
+    f: F,
+    space: Option<Space>,
+    _obs: PhantomData<fn() -> O>,
+}
+
+impl<E, F, O> TransformObservation<E, F, O> {
+    pub fn new(env: E, f: F) -> Self {
+        Self {
+            env,
+            f,
+            space: None,
+            _obs: PhantomData,
+        }
+    }
+
+    pub fn with_space(mut self, space: Space) -> Self {
+        self.space = Some(space);
+        self
+    }
+}
+
+impl_wrapper!(TransformObservation<E, F, O>);
+
+impl<E, F, O> Environment for TransformObservation<E, F, O>
+where
+    E: Environment,
+    F: FnMut(E::Observation) -> O,
+{
+    type Observation = O;
+    type Action = E::Action;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        let obs = self.env.reset()?;
+        Ok((self.f)(obs))
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        let step = self.env.step(action)?;
+        Ok(Step {
+            obs: (self.f)(step.obs),
+            done: step.done,
+            reward: step.reward,
+            info: step.info,
+        })
+    }
+
+    fn observation_space(&self) -> Space {
+        self.space
+            .clone()
+            .unwrap_or_else(|| self.env.observation_space())
+    }
+
+    fn action_space(&self) -> Space {
+        self.env.action_space()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        self.env.state_dict()
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.env.load_state_dict(state)
+    }
+}
+
+/// Applies `f` to every reward.
+pub struct TransformReward<E, F> {
+    env: E,
+    f: F,
+}
+
+impl<E, F> TransformReward<E, F> {
+    pub fn new(env: E, f: F) -> Self {
+        Self { env, f }
+    }
+}
+
+impl_wrapper!(TransformReward<E, F>);
+
+impl<E: Environment, F: FnMut(f64) -> f64> Environment for TransformReward<E, F> {
+    type Observation = E::Observation;
+    type Action = E::Action;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        self.env.reset()
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        let mut step = self.env.step(action)?;
+        step.reward = (self.f)(step.reward);
+        Ok(step)
+    }
+
+    fn observation_space(&self) -> Space {
+        self.env.observation_space()
+    }
+
+    fn action_space(&self) -> Space {
+        self.env.action_space()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        self.env.state_dict()
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.env.load_state_dict(state)
+    }
+}
+
+/// With probability `prob`, ignores the agent and repeats the previous
+/// action instead (Machado et al., 2018), making dynamics stochastic.
+pub struct StickyActions<E: Environment> {
+    env: E,
+    prob: f64,
+    last: Option<E::Action>,
+    rng: ChaCha8Rng,
+}
+
+impl<E: Environment> StickyActions<E> {
+    /// Errors unless `prob` is in [0, 1].
+    pub fn new(env: E, prob: f64) -> EnvResult<Self> {
+        if !(0.0..=1.0).contains(&prob) {
+            return Err(HabaError::Config(format!(
+                "invalid sticky action probability {}",
+                prob
+            )));
+        }
+        Ok(Self {
+            env,
+            prob,
+            last: None,
+            rng: ChaCha8Rng::from_entropy(),
+        })
+    }
+}
+
+impl<E: Environment> Wrapper for StickyActions<E> {
+    type Inner = E;
+
+    fn inner(&self) -> &E {
+        &self.env
+    }
+
+    fn inner_mut(&mut self) -> &mut E {
+        &mut self.env
+    }
+}
+
+impl<E: Environment> Environment for StickyActions<E>
+where
+    E::Action: Clone + Serialize + DeserializeOwned,
+{
+    type Observation = E::Observation;
+    type Action = E::Action;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        self.last = None;
+        self.env.reset()
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        let action = match self.last.take() {
+            Some(last) if self.rng.gen_bool(self.prob) => last,
+            _ => action,
+        };
+        self.last = Some(action.clone());
+        self.env.step(action)
+    }
+
+    fn observation_space(&self) -> Space {
+        self.env.observation_space()
+    }
+
+    fn action_space(&self) -> Space {
+        self.env.action_space()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        seed_with_env(&mut self.rng, &mut self.env, seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        Ok(json!({"env": self.env.state_dict()?, "last": self.last, "rng": self.rng}))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.last = checkpoint::field(state, "last")?;
+        self.rng = checkpoint::field(state, "rng")?;
+        self.env
+            .load_state_dict(&checkpoint::field::<Value>(state, "env")?)
+    }
+}
+
+/// Distribution a parameter is drawn from by `DomainRandomization`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamDistribution {
+    Uniform {
+        low: f64,
+        high: f64,
+    },
+    /// Uniform in log space; both bounds must be positive.
+    LogUniform {
+        low: f64,
+        high: f64,
+    },
+    Normal {
+        mean: f64,
+        std: f64,
+    },
+    /// The parameter's nominal value times a factor uniform in [low, high].
+    Scale {
+        low: f64,
+        high: f64,
+    },
+}
+
+impl ParamDistribution {
+    fn validate(&self) -> EnvResult<()> {
+        let valid = match *self {
+            Self::Uniform { low, high } | Self::Scale { low, high } => low <= high,
+            Self::LogUniform { low, high } => 0.0 < low && low <= high,
+            Self::Normal { std, .. } => std >= 0.0,
+        };
+        if !valid {
+            return Err(HabaError::Config(format!(
+                "invalid parameter distribution {:?}",
+                self
+            )));
+        }
+        Ok(())
+    }
+
+    fn sample(&self, nominal: f64, rng: &mut ChaCha8Rng) -> f64 {
+        let uniform =
+            |rng: &mut ChaCha8Rng, low: f64, high: f64| low + (high - low) * rng.r#gen::<f64>();
+        match *self {
+            Self::Uniform { low, high } => uniform(rng, low, high),
+            Self::LogUniform { low, high } => uniform(rng, low.ln(), high.ln()).exp(),
+            Self::Normal { mean, std } => mean + std * standard_normal(rng),
+            Self::Scale { low, high } => nominal * uniform(rng, low, high),
+        }
+    }
+}
+
+/// Resamples the wrapped environment's parameters on every reset, for
+/// training controllers that are robust to modelling error. The current
+/// values are reported in every step's `info` under `PARAM_PREFIX`. A draw
+/// the environment rejects, e.g. a negative mass, is redrawn up to
+/// `MAX_REDRAWS` times before the parameter falls back to its nominal value.
+pub struct DomainRandomization<E> {
+    env: E,
+    // (name, distribution, nominal value)
+    distributions: Vec<(String, ParamDistribution, f64)>,
+    rng: ChaCha8Rng,
+}
+
+impl<E: Parameterized> DomainRandomization<E> {
+    pub const MAX_REDRAWS: usize = 100;
+
+    pub fn new(env: E) -> Self {
+        Self {
+            env,
+            distributions: Vec::new(),
+            rng: ChaCha8Rng::from_entropy(),
+        }
+    }
+
+    /// Draw `name` from `distribution` on each reset. The value it has now
+    /// is its nominal value.
+    pub fn randomize(mut self, name: &str, distribution: ParamDistribution) -> EnvResult<Self> {
+        distribution.validate()?;
+        let nominal = self
+            .env
+            .param(name)
+            .ok_or_else(|| HabaError::Config(format!("environment has no parameter {}", name)))?;
+        self.distributions.retain(|(n, _, _)| n != name);
+        self.distributions
+            .push((name.to_string(), distribution, nominal));
+        Ok(self)
+    }
+
+    /// Current value of every randomized parameter.
+    pub fn params(&self) -> Vec<(&str, f64)> {
+        self.distributions
+            .iter()
+            .filter_map(|(name, _, _)| Some((name.as_str(), self.env.param(name)?)))
+            .collect()
+    }
+}
+
+impl_wrapper!(DomainRandomization<E>);
+
+impl<E: Environment + Parameterized> Environment for DomainRandomization<E> {
+    type Observation = E::Observation;
+    type Action = E::Action;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        for (name, distribution, nominal) in &self.distributions {
+            let accepted = (0..Self::MAX_REDRAWS).any(|_| {
+                let value = distribution.sample(*nominal, &mut self.rng);
+                self.env.set_param(name, value).is_ok()
+            });
+            if !accepted {
+                self.env.set_param(name, *nominal)?;
+            }
+        }
+        self.env.reset()
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        let mut step = self.env.step(action)?;
+        for (name, value) in self.params() {
+            set_info(&mut step, &format!("{}{}", PARAM_PREFIX, name), value);
+        }
+        Ok(step)
+    }
+
+    fn observation_space(&self) -> Space {
+        self.env.observation_space()
+    }
+
+    fn action_space(&self) -> Space {
+        self.env.action_space()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        seed_with_env(&mut self.rng, &mut self.env, seed);
+    }
+
+    // The drawn parameters are part of the wrapped env's state
+    fn state_dict(&self) -> EnvResult<Value> {
+        Ok(json!({"env": self.env.state_dict()?, "rng": self.rng}))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.rng = checkpoint::field(state, "rng")?;
+        self.env
+            .load_state_dict(&checkpoint::field::<Value>(state, "env")?)
+    }
+}
+
+/// Tracks the return and length of each episode. They are reported in the
+/// final step's `info` and kept for the last `queue_size` episodes.
+pub struct RecordEpisodeStatistics<E> {
+    env: E,
+    episode_return: f64,
+    episode_length: usize,
+    return_queue: VecDeque<f64>,
+    length_queue: VecDeque<usize>,
+    queue_size: usize,
+}
+
+impl<E> RecordEpisodeStatistics<E> {
+    pub fn new(env: E) -> Self {
+        Self {
+            env,
+            episode_return: 0.0,
+            episode_length: 0,
+            return_queue: VecDeque::new(),
+            length_queue: VecDeque::new(),
+            queue_size: 100,
+        }
+    }
+
+    pub fn with_queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size;
+        self
+    }
+
+    /// Returns of the most recent episodes, oldest first.
+    pub fn return_queue(&self) -> &VecDeque<f64> {
+        &self.return_queue
+    }
+
+    pub fn length_queue(&self) -> &VecDeque<usize> {
+        &self.length_queue
+    }
+}
+
+impl_wrapper!(RecordEpisodeStatistics<E>);
+
+impl<E: Environment> Environment for RecordEpisodeStatistics<E> {
+    type Observation = E::Observation;
+    type Action = E::Action;
+
+    fn reset(&mut self) -> EnvResult<Self::Observation> {
+        self.episode_return = 0.0;
+        self.episode_length = 0;
+        self.env.reset()
+    }
+
+    fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+        let mut step = self.env.step(action)?;
+        self.episode_return += step.reward;
+        self.episode_length += 1;
+        if step.done {
+            set_info(&mut step, EPISODE_RETURN, self.episode_return);
+            set_info(&mut step, EPISODE_LENGTH, self.episode_length);
+            self.return_queue.push_back(self.episode_return);
+            self.length_queue.push_back(self.episode_length);
+            if self.return_queue.len() > self.queue_size {
+                self.return_queue.pop_front();
+                self.length_queue.pop_front();
+            }
+            self.episode_return = 0.0;
+            self.episode_length = 0;
+        }
+        Ok(step)
+    }
+
+    fn observation_space(&self) -> Space {
+        self.env.observation_space()
+    }
+
+    fn action_space(&self) -> Space {
+        self.env.action_space()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    fn state_dict(&self) -> EnvResult<Value> {
+        Ok(json!({
+            "env": self.env.state_dict()?,
+            "episode_return": self.episode_return,
+            "episode_length": self.episode_length,
+            "return_queue": self.return_queue,
+            "length_queue": self.length_queue,
+        }))
+    }
+
+    fn load_state_dict(&mut self, state: &Value) -> EnvResult<()> {
+        self.episode_return = checkpoint::field(state, "episode_return")?;
+        self.episode_length = checkpoint::field(state, "episode_length")?;
+        self.return_queue = checkpoint::field(state, "return_queue")?;
+        self.length_queue = checkpoint::field(state, "length_queue")?;
+        self.env
+            .load_state_dict(&checkpoint::field::<Value>(state, "env")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartpole::CartPole;
+    use crate::mock::MockEnv;
+
+    // Observes the last action it was given; actions are bounded to [-2, 2]
+    struct Echo;
+
+    impl Environment for Echo {
+        type Observation = Vec<f64>;
+        type Action = Vec<f64>;
+
+        fn reset(&mut self) -> EnvResult<Self::Observation> {
+            Ok(vec![0.0, 0.0])
+        }
+
+        fn step(&mut self, action: Self::Action) -> EnvResult<Step<Self::Observation>> {
+            Ok(Step {
+                obs: action,
+                done: false,
+                reward: 0.0,
+                info: None,
+            })
+        }
+
+        fn observation_space(&self) -> Space {
+            Space::uniform(&[2], -2.0, 2.0)
+        }
+
+        fn action_space(&self) -> Space {
+            Space::uniform(&[2], -2.0, 2.0)
+        }
+    }
+
+    #[test]
+    fn test_time_limit_truncates() {
+        let mut env = TimeLimit::new(CartPole::new(), 3);
+        env.reset().unwrap();
+        // Alternating pushes keep the pole up, so only the limit ends the episode
+        for t in 1..=3 {
+            let step = env.step(t % 2).unwrap();
+            assert_eq!(step.done, t == 3);
+            let truncated = step.info.as_ref().and_then(|info| info.get(TRUNCATED));
+            assert_eq!(truncated.is_some(), t == 3);
+        }
+
+        let state = env.state_dict().unwrap();
+        let mut restored = TimeLimit::new(CartPole::new(), 3);
+        restored.load_state_dict(&state).unwrap();
+        assert_eq!(restored.elapsed, 3);
+        assert_eq!(restored.inner().state().x, env.inner().state().x);
+    }
+
+    #[test]
+    fn test_spaces_compose() {
+        let mut env = FrameStack::new(TimeLimit::new(CartPole::new(), 10), 3);
+        let space = env.observation_space();
+        assert_eq!(space.shape(), &[12]);
+        assert!(space.contains(&env.reset().unwrap()));
+        assert!(space.contains(&env.step(1).unwrap().obs));
+        assert_eq!(env.action_space(), Space::discrete(2));
+
+        let env = RescaleAction::new(ClipAction::new(Echo), -1.0, 1.0).unwrap();
+        assert_eq!(env.action_space(), Space::uniform(&[2], -1.0, 1.0));
+        // ClipAction accepts anything, so RescaleAction maps onto the full real line
+        assert!(env.inner().action_space().contains(&vec![5.0, -5.0]));
+        assert!(RescaleAction::new(Echo, -1.0, 1.0).is_ok());
+        assert!(RescaleAction::new(CartPole::new(), -1.0, 1.0).is_err());
+
+        let mut env = ClipAction::new(RescaleAction::new(Echo, -1.0, 1.0).unwrap());
+        assert_eq!(env.step(vec![0.5, -3.0]).unwrap().obs, vec![1.0, -2.0]);
+        // Actions of the wrong length are rejected instead of truncated
+        assert!(env.step(vec![0.5]).is_err());
+        assert!(env.inner_mut().step(vec![0.5, 0.5, 0.5]).is_err());
+        assert!(RescaleAction::new(Echo, f64::NAN, 1.0).is_err());
+
+        let mut env = TransformObservation::new(Echo, |obs: Vec<f64>| obs[0])
+            .with_space(Space::uniform(&[], -2.0, 2.0));
+        assert_eq!(env.step(vec![1.5, 0.0]).unwrap().obs, 1.5);
+        assert!(env.observation_space().contains(&1.5));
+    }
+
+    #[test]
+    fn test_repeat_and_episode_statistics() {
+        let env = TransformReward::new(ActionRepeat::new(MockEnv::new(10), 3), |r| r * 2.0);
+        let mut env = RecordEpisodeStatistics::new(env);
+        env.reset().unwrap();
+        let rewards: Vec<f64> = (0..4).map(|_| env.step(()).unwrap().reward).collect();
+        // The last repeat is cut short by the end of the episode
+        assert_eq!(rewards, vec![6.0, 6.0, 6.0, 2.0]);
+        assert_eq!(env.return_queue(), &[20.0]);
+        assert_eq!(env.length_queue(), &[4]);
+    }
+
+    #[test]
+    fn test_sticky_actions() {
+        let mut env = StickyActions::new(Echo, 1.0).unwrap();
+        env.seed(0);
+        env.reset().unwrap();
+        assert_eq!(env.step(vec![1.0, 1.0]).unwrap().obs, vec![1.0, 1.0]);
+        assert_eq!(env.step(vec![0.0, 0.0]).unwrap().obs, vec![1.0, 1.0]);
+        env.reset().unwrap();
+        assert_eq!(env.step(vec![0.0, 0.0]).unwrap().obs, vec![0.0, 0.0]);
+
+        assert!(StickyActions::new(Echo, 1.5).is_err());
+        assert!(StickyActions::new(Echo, f64::NAN).is_err());
+        let mut env = StickyActions::new(Echo, 0.0).unwrap();
+        env.reset().unwrap();
+        env.step(vec![1.0, 1.0]).unwrap();
+        assert_eq!(env.step(vec![0.0, 0.0]).unwrap().obs, vec![0.0, 0.0]);
+
+        // The wrapped env is not seeded with the wrapper's own seed
+        let mut sticky = StickyActions::new(CartPole::new(), 0.5).unwrap();
+        let mut plain = CartPole::new();
+        sticky.seed(3);
+        plain.seed(3);
+        assert_ne!(sticky.reset().unwrap(), plain.reset().unwrap());
+    }
+
+    #[test]
+    fn test_domain_randomization_seeds_env_independently() {
+        let gravity = ParamDistribution::Uniform {
+            low: 5.0,
+            high: 15.0,
+        };
+        let (draws, xs): (Vec<f64>, Vec<f64>) = (0..200)
+            .map(|seed| {
+                let mut env = DomainRandomization::new(CartPole::new())
+                    .randomize("gravity", gravity)
+                    .unwrap();
+                env.seed(seed);
+                let x = env.reset().unwrap()[0];
+                (env.inner().params().gravity, x)
+            })
+            .unzip();
+        let mean = |v: &[f64]| v.iter().sum::<f64>() / v.len() as f64;
+        let (mg, mx) = (mean(&draws), mean(&xs));
+        let cov: f64 = draws
+            .iter()
+            .zip(&xs)
+            .map(|(g, x)| (g - mg) * (x - mx))
+            .sum();
+        let var = |v: &[f64], m: f64| v.iter().map(|a| (a - m).powi(2)).sum::<f64>();
+        let corr = cov / (var(&draws, mg) * var(&xs, mx)).sqrt();
+        assert!(corr.abs() < 0.3, "correlation {}", corr);
+    }
+
+    #[test]
+    fn test_domain_randomization() {
+        let gravity = ParamDistribution::Uniform {
+            low: 5.0,
+            high: 15.0,
+        };
+        let masspole = ParamDistribution::Scale {
+            low: 0.5,
+            high: 2.0,
+        };
+        let make = || {
+            DomainRandomization::new(CartPole::new())
+                .randomize("gravity", gravity)
+                .unwrap()
+                .randomize("masspole", masspole)
+                .unwrap()
+        };
+        let mut env = make();
+        env.seed(3);
+        let mut seen = Vec::new();
+        for _ in 0..20 {
+            env.reset().unwrap();
+            let params = *env.inner().params();
+            assert!((5.0..=15.0).contains(&params.gravity));
+            assert!((0.05..=0.2).contains(&params.masspole));
+            assert_eq!(params.length, 0.5);
+            seen.push(params.gravity);
+
+            let info = env.step(0).unwrap().info.unwrap();
+            assert_eq!(info["param.gravity"].as_f64(), Some(params.gravity));
+            assert_eq!(info["param.masspole"].as_f64(), Some(params.masspole));
+            assert!(!info.contains_key("param.length"));
+        }
+        assert!(seen.windows(2).all(|w| w[0] != w[1]));
+
+        // Seeding replays the draws, and checkpoints keep them
+        let mut replay = make();
+        replay.seed(3);
+        replay.reset().unwrap();
+        assert_eq!(replay.inner().params().gravity, seen[0]);
+        let mut restored = make();
+        restored
+            .load_state_dict(&replay.state_dict().unwrap())
+            .unwrap();
+        assert_eq!(restored.params(), replay.params());
+
+        // Rejected draws are redrawn, or fall back to the nominal value
+        let mut env = DomainRandomization::new(CartPole::new())
+            .randomize(
+                "masspole",
+                ParamDistribution::Normal {
+                    mean: 0.1,
+                    std: 1.0,
+                },
+            )
+            .unwrap()
+            .randomize(
+                "length",
+                ParamDistribution::Normal {
+                    mean: -1.0,
+                    std: 0.0,
+                },
+            )
+            .unwrap();
+        for _ in 0..20 {
+            env.reset().unwrap();
+            assert!(env.inner().params().masspole > 0.0);
+            assert_eq!(env.inner().params().length, 0.5);
+        }
+
+        let env = DomainRandomization::new(CartPole::new());
+        assert!(env.randomize("mass", gravity).is_err());
+        let env = DomainRandomization::new(CartPole::new());
+        let bad = ParamDistribution::LogUniform {
+            low: 0.0,
+            high: 1.0,
+        };
+        assert!(env.randomize("tau", bad).is_err());
+    }
+}
@@ -2,14 +2,17 @@ use Haba::buffer::ReplayBuffer;
 use Haba::cartpole::CartPole;
 use Haba::collector::Collector;
 use Haba::dqn::DQNPolicy;
-use Haba::trainer::Trainer;
+use Haba::tabular::{TabularAlgorithm, TabularPolicy};
+use Haba::toy_text::{FrozenLake, GridWorld};
+use Haba::trainer::{OffPolicyConfig, OffPolicyTrainer, Trainer};
 use Haba::venv::DummyVectorEnv;
+use Haba::wrappers::TimeLimit;
 
 #[test]
 fn test_integration_cartpole_dqn() {
     // 1. Env
     // Use fewer steps for fast testing
-    let env = CartPole::new(500);
+    let env = TimeLimit::new(CartPole::new(), 500);
     let venv = DummyVectorEnv::new(vec![env]);
 
     // 2. Policy
@@ -20,11 +23,15 @@ fn test_integration_cartpole_dqn() {
     let buffer = ReplayBuffer::new(1000);
 
     // 4. Collector
-    let collector = Collector::new(venv, policy, Some(buffer));
+    let collector = Collector::new(venv, policy, Some(buffer)).expect("Failed to reset env");
 
     // 5. Trainer
     // 2 epochs, 2 episodes per epoch, batch size 16
-    let mut trainer = Trainer::new(collector, 2, 2, 16);
+    let mut trainer = Trainer::new(collector, 2, 2, 16).with_test_env(
+        DummyVectorEnv::new(vec![TimeLimit::new(CartPole::new(), 50)]),
+        1,
+        1,
+    );
 
     // 6. Train
     let result = trainer.train();
@@ -32,3 +39,138 @@ fn test_integration_cartpole_dqn() {
     // 7. Verify
     assert!(result.is_ok(), "Training failed: {:?}", result.err());
 }
+
+fn make_trainer(max_epochs: usize) -> Trainer<DummyVectorEnv<TimeLimit<CartPole>>, DQNPolicy> {
+    let venv = DummyVectorEnv::new(vec![
+        TimeLimit::new(CartPole::new(), 50),
+        TimeLimit::new(CartPole::new(), 50),
+    ]);
+    let policy = DQNPolicy::new(4, 16, 2, 0.99, 0.3).expect("Failed to create DQN Policy");
+    let collector =
+        Collector::new(venv, policy, Some(ReplayBuffer::new(500))).expect("Failed to reset env");
+    Trainer::new(collector, max_epochs, 20, 8).with_test_env(
+        DummyVectorEnv::new(vec![TimeLimit::new(CartPole::new(), 50)]),
+        3,
+        1,
+    )
+}
+
+#[test]
+fn test_resume_matches_uninterrupted_run() {
+    let dir = std::env::temp_dir().join(format!("haba_resume_{}", std::process::id()));
+
+    // Uninterrupted run that checkpoints every epoch and keeps the last 3
+    let mut full = make_trainer(4).with_checkpointing(&dir, 1, 3);
+    full.seed(7).unwrap();
+    full.train().expect("Training failed");
+
+    let kept: Vec<usize> = Haba::checkpoint::list_epochs(&dir)
+        .unwrap()
+        .into_iter()
+        .map(|(epoch, _)| epoch)
+        .collect();
+    assert_eq!(kept, vec![2, 3, 4]);
+
+    // A fresh process picks the run up after epoch 2 with different initial weights
+    let mut resumed = make_trainer(4);
+    resumed
+        .resume(&Haba::checkpoint::epoch_dir(&dir, 2))
+        .expect("Resume failed");
+    assert_eq!(resumed.epoch(), 2);
+    resumed.train().expect("Training failed");
+
+    let expected = &full.history()[2..];
+    let actual = resumed.history();
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected) {
+        assert_eq!(a.epoch, e.epoch);
+        assert_eq!(a.collect.returns, e.collect.returns);
+        assert_eq!(a.learn, e.learn);
+        // The test env continues from its checkpointed RNG, so it plays the
+        // same episodes
+        let test_lens = |stats: &Haba::stats::EpochStats| stats.test.clone().unwrap().lens;
+        assert_eq!(test_lens(a), test_lens(e));
+    }
+    assert_eq!(resumed.progress(), full.progress());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_seeded_runs_are_identical() {
+    // Slippery moves make the envs' own randomness matter
+    let run = || {
+        let lake = || TimeLimit::new(FrozenLake::new(true), 100);
+        let policy = TabularPolicy::new(TabularAlgorithm::QLearning, 16, 4, 0.95, 0.3);
+        let collector = Collector::new(
+            DummyVectorEnv::new(vec![lake(), lake()]),
+            policy,
+            Some(ReplayBuffer::new(1000)),
+        )
+        .expect("Failed to reset env");
+        let mut trainer = Trainer::new(collector, 3, 200, 16).with_test_env(
+            DummyVectorEnv::new(vec![lake()]),
+            5,
+            1,
+        );
+        trainer.seed(11).unwrap();
+        trainer.train().expect("Training failed");
+        trainer
+    };
+
+    let (first, second) = (run(), run());
+    for (a, b) in first.history().iter().zip(second.history()) {
+        assert_eq!(a.collect.returns, b.collect.returns);
+        assert_eq!(a.collect.lens, b.collect.lens);
+        assert_eq!(a.learn, b.learn);
+        assert_eq!(
+            a.test.as_ref().map(|t| &t.lens),
+            b.test.as_ref().map(|t| &t.lens)
+        );
+    }
+    assert_eq!(
+        first.collector().policy().q_table(),
+        second.collector().policy().q_table()
+    );
+}
+
+#[test]
+fn test_off_policy_trainer_ratio_and_stop() {
+    let grid = || TimeLimit::new(GridWorld::from_map(&["S..", "..G"]).unwrap(), 30);
+    let policy = TabularPolicy::new(TabularAlgorithm::QLearning, 6, 4, 0.9, 0.3).with_lr(0.5);
+    let collector = Collector::new(
+        DummyVectorEnv::new(vec![grid()]),
+        policy,
+        Some(ReplayBuffer::new(1000)),
+    )
+    .expect("Failed to reset env");
+    let config = OffPolicyConfig {
+        max_epochs: 50,
+        step_per_epoch: 40,
+        step_per_collect: 4,
+        update_per_step: 0.25,
+        batch_size: 8,
+        start_timesteps: 20,
+        episode_per_test: 2,
+    };
+    let mut trainer = OffPolicyTrainer::new(collector, config)
+        .unwrap()
+        .with_test_env(DummyVectorEnv::new(vec![grid()]))
+        .with_reward_threshold(1.0);
+    trainer.seed(3).unwrap();
+    let summary = trainer.train().expect("Training failed");
+
+    // Stops as soon as the greedy policy reaches the goal
+    assert!(summary.stopped);
+    assert!(summary.epochs < 50);
+    assert_eq!(summary.best_reward, Some(1.0));
+    assert_eq!(summary.best_epoch, Some(summary.epochs));
+    assert_eq!(summary.history.len(), summary.epochs);
+    // One update per 4 steps after the warmup
+    assert_eq!(summary.env_steps, 20 + 40 * summary.epochs);
+    assert_eq!(summary.grad_steps, 10 * summary.epochs);
+    assert_eq!(
+        trainer.collector().policy().update_count(),
+        summary.grad_steps
+    );
+}